@@ -0,0 +1,59 @@
+// Run show and recover of the hammer2 binary against a mini image, so that
+// directory parsers of the binary crate (show_blockref_data() and
+// dump_dir_data()), which this crate can't link, see corrupted media.
+// The binary is specified by HAMMER2_FUZZ_BIN, and its panic (exit code
+// 101) is reported as a crash, e.g.
+//
+//     $ cargo build --release
+//     $ export HAMMER2_FUZZ_BIN=$PWD/target/release/hammer2
+//     $ cd fuzz && cargo fuzz run directive corpus/image
+//
+// Coverage of the binary isn't visible to libFuzzer, so seed the corpus
+// the same way as the image target.
+#![no_main]
+
+const VOLUME_SIZE: u64 = libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE;
+const PANIC_EXIT_CODE: i32 = 101;
+
+fn run(bin: &str, args: &[&str]) {
+    let out = std::process::Command::new(bin)
+        .args(args)
+        .env("RUST_LOG", "off")
+        .output()
+        .unwrap();
+    assert_ne!(
+        out.status.code(),
+        Some(PANIC_EXIT_CODE),
+        "{bin} {args:?} panicked\n{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    let bin = std::env::var("HAMMER2_FUZZ_BIN").expect("HAMMER2_FUZZ_BIN not set");
+    let Ok(len) = u64::try_from(data.len()) else {
+        return;
+    };
+    if len > VOLUME_SIZE {
+        return;
+    }
+    let d = std::env::temp_dir().join(format!(
+        "hammer2-utils-fuzz-directive-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&d);
+    std::fs::create_dir_all(&d).unwrap();
+    let f = d.join("image");
+    let dest = d.join("dest");
+    let (Some(path), Some(dest)) = (f.to_str(), dest.to_str()) else {
+        return;
+    };
+    let fp = std::fs::File::create(&f).unwrap();
+    std::os::unix::fs::FileExt::write_all_at(&fp, data, 0).unwrap();
+    fp.set_len(VOLUME_SIZE).unwrap();
+    drop(fp);
+
+    run(&bin, &["show", path]);
+    run(&bin, &["recover", path, "/", dest]);
+    let _ = std::fs::remove_dir_all(&d);
+});
@@ -0,0 +1,100 @@
+// Feed a mini image to libhammer2 and walk its topology through on-media
+// parsers.  Input is written to head of a sparse volume, so that a corpus
+// can be seeded with head of a freshly formatted image, e.g.
+//
+//     $ truncate -s 1G /tmp/x && newfs_hammer2 --nodiscard /tmp/x
+//     $ mkdir -p corpus/image && head -c 8M /tmp/x > corpus/image/seed
+#![no_main]
+
+const VOLUME_SIZE: u64 = libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE;
+const MAX_DEPTH: usize = 32;
+
+fn scan(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    visited: &mut std::collections::HashSet<u64>,
+    depth: usize,
+) {
+    if depth >= MAX_DEPTH || !visited.insert(bref.data_off) {
+        return;
+    }
+    let Ok(media) = fso.read_media(bref) else {
+        return;
+    };
+    let _ = libhammer2::ondisk::verify_media(bref, &media);
+    let _ = hammer2_utils::media::get_key_end(bref);
+    let _ = hammer2_utils::media::format_media(0, bref, &media);
+    match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+            if let Some(ipdata) = hammer2_utils::media::media_as_inode_data(&media) {
+                let _ = ipdata.get_filename_string();
+            }
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+            let _ = hammer2_utils::media::get_dirent_name(bref, &media);
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE => {
+            let _ = hammer2_utils::media::get_freemap_rotation(bref);
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF => {
+            let _ = hammer2_utils::media::get_freemap_rotation(bref);
+            let _ = hammer2_utils::media::media_as_bmap_data(&media);
+        }
+        _ => (),
+    }
+    if media.is_empty() {
+        return;
+    }
+    for bref in &libhammer2::ondisk::media_as_blockref_safe(bref, &media) {
+        if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
+            scan(fso, bref, visited, depth + 1);
+        }
+    }
+}
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    let Ok(len) = u64::try_from(data.len()) else {
+        return;
+    };
+    if len > VOLUME_SIZE {
+        return;
+    }
+    let f = std::env::temp_dir().join(format!("hammer2-utils-fuzz-{}", std::process::id()));
+    let Some(path) = f.to_str() else {
+        return;
+    };
+    let fp = std::fs::File::create(&f).unwrap();
+    std::os::unix::fs::FileExt::write_all_at(&fp, data, 0).unwrap();
+    fp.set_len(VOLUME_SIZE).unwrap();
+    drop(fp);
+
+    let Ok(mut fso) = libhammer2::ondisk::init(path, true) else {
+        return;
+    };
+    let Ok(bests) = fso.get_best_volume_data() else {
+        return;
+    };
+    let offset = libhammer2::volume::get_volume_data_offset(
+        bests[usize::from(libhammer2::fs::HAMMER2_ROOT_VOLUME)].0,
+    );
+    let Some(vol) = fso.get_root_volume_mut() else {
+        return;
+    };
+    let Ok(buf) = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset) else {
+        return;
+    };
+    let Some(voldata) = hammer2_utils::media::media_as_volume_data(&buf) else {
+        return;
+    };
+    let mut visited = std::collections::HashSet::new();
+    for bref in voldata
+        .sroot_blockset
+        .as_blockref()
+        .iter()
+        .chain(voldata.freemap_blockset.as_blockref())
+    {
+        if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
+            scan(&mut fso, bref, &mut visited, 0);
+        }
+    }
+});
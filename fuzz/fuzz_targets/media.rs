@@ -0,0 +1,36 @@
+// Feed a blockref followed by its media block to on-media parsers.
+#![no_main]
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    let size = std::mem::size_of::<libhammer2::fs::Hammer2Blockref>();
+    if data.len() < size {
+        return;
+    }
+    let bref = *libfs::cast::align_head_to::<libhammer2::fs::Hammer2Blockref>(data);
+    let media = &data[size..];
+
+    let _ = hammer2_utils::media::get_key_end(&bref);
+    let _ = hammer2_utils::media::format_media(0, &bref, media);
+    match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+            if let Some(ipdata) = hammer2_utils::media::media_as_inode_data(media) {
+                let _ = ipdata.get_filename_string();
+            }
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+            let _ = hammer2_utils::media::get_dirent_name(&bref, media);
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE => {
+            let _ = hammer2_utils::media::get_freemap_rotation(&bref);
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF => {
+            let _ = hammer2_utils::media::get_freemap_rotation(&bref);
+            let _ = hammer2_utils::media::media_as_bmap_data(media);
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME | libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP => {
+            let _ = hammer2_utils::media::media_as_volume_data(media);
+        }
+        _ => (),
+    }
+    let _ = libhammer2::ondisk::media_as_blockref_safe(&bref, media);
+});
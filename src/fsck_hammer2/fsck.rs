@@ -371,12 +371,17 @@ fn print_blockref_entry(
             );
             if opt.verbose {
                 match fso.read_media(&m.bref) {
-                    Ok(v) => {
-                        for s in &format_media(2, &m.bref, &v)? {
-                            eprint!("{s}");
+                    Ok(v) => match hammer2_utils::media::format_media(2, &m.bref, &v) {
+                        Ok(v) => {
+                            for s in &v {
+                                eprint!("{s}");
+                            }
+                            std::io::stderr().flush()?;
                         }
-                        std::io::stderr().flush()?;
-                    }
+                        Err(e) => {
+                            hammer2_utils::tab::error!(2, "Failed to format media: {e}");
+                        }
+                    },
                     Err(e) => {
                         hammer2_utils::tab::error!(2, "Failed to read media: {e}");
                     }
@@ -420,7 +425,7 @@ fn print_blockref_stats(
         ),
         _ => panic!("{}", bstats.typ),
     };
-    let buf = if let Some(v) = buf.get(..get_chars_per_line()) {
+    let buf = if let Some(v) = buf.get(..hammer2_utils::util::get_chars_per_line()) {
         v.to_string()
     } else {
         buf
@@ -434,18 +439,6 @@ fn print_blockref_stats(
     Ok(())
 }
 
-fn get_chars_per_line() -> usize {
-    if let Some((terminal_size::Width(w), terminal_size::Height(_))) =
-        terminal_size::terminal_size()
-    {
-        w.into()
-    } else if let Ok(v) = std::env::var("COLUMNS") {
-        v.parse().unwrap_or(80)
-    } else {
-        80 // last resort
-    }
-}
-
 fn verify_volume_header(voldata: &libhammer2::fs::Hammer2VolumeData) -> nix::Result<()> {
     if voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_HBO
         && voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_ABO
@@ -586,7 +579,12 @@ fn verify_blockref(
         dstats.total_bytes += bytes;
     }
     if !opt.count_empty && bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
-        assert_eq!(bytes, 0);
+        if bytes != 0 {
+            let msg = "Bad I/O bytes";
+            add_blockref_entry_from_str(&mut bstats.root, bref, msg);
+            print_blockref_debug(bref, msg, opt);
+            failed = true;
+        }
         bstats.total_bytes -= bytes;
         dstats.total_bytes -= bytes;
     }
@@ -668,7 +666,8 @@ fn scan_pfs_blockref(
     let mut v = vec![];
     let bscan = match bref.typ {
         libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
-            let ipdata = libhammer2::ondisk::media_as_inode_data(&media);
+            let ipdata = hammer2_utils::media::media_as_inode_data(&media)
+                .ok_or(nix::errno::Errno::EINVAL)?;
             if ipdata.meta.is_sup_root() {
                 ipdata
                     .u_as::<libhammer2::fs::Hammer2Blockset>()
@@ -678,12 +677,19 @@ fn scan_pfs_blockref(
                 v.push(BlockrefMessage::new_from(bref, ipdata));
                 vec![]
             } else {
-                panic!("{}", ipdata.meta.inum); // should only see SUPROOT or PFS
+                // should only see SUPROOT or PFS
+                log::error!(
+                    "{:016x}: unexpected inode {}",
+                    bref.data_off,
+                    ipdata.meta.inum
+                );
+                return Err(Box::new(nix::errno::Errno::EINVAL));
             }
         }
         libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => libhammer2::fs::media_as(&media),
         libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME => {
-            libhammer2::ondisk::media_as_volume_data(&media)
+            hammer2_utils::media::media_as_volume_data(&media)
+                .ok_or(nix::errno::Errno::EINVAL)?
                 .sroot_blockset
                 .as_blockref()
                 .to_vec()
@@ -697,260 +703,6 @@ fn scan_pfs_blockref(
     Ok(v)
 }
 
-#[allow(clippy::too_many_lines)]
-fn format_media(
-    tab: usize,
-    bref: &libhammer2::fs::Hammer2Blockref,
-    media: &[u8],
-) -> hammer2_utils::Result<Vec<String>> {
-    let mut v = vec![];
-    match bref.typ {
-        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
-            let ipdata = libhammer2::ondisk::media_as_inode_data(media);
-            let meta = &ipdata.meta;
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "filename \"{}\"\n",
-                ipdata.get_filename_string()?
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "version {}\n",
-                meta.version
-            ));
-            if meta.is_root() {
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "pfs_subtype {} ({})\n",
-                    meta.pfs_subtype,
-                    libhammer2::subs::get_pfs_subtype_string(meta.pfs_subtype)
-                ));
-            }
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "uflags {:#010x}\n",
-                meta.uflags
-            ));
-            if meta.rmajor != 0 || meta.rminor != 0 {
-                v.push(hammer2_utils::tab::format!(tab, "rmajor {}\n", meta.rmajor));
-                v.push(hammer2_utils::tab::format!(tab, "rminor {}\n", meta.rminor));
-            }
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "ctime {}\n",
-                libhammer2::subs::get_local_time_string(meta.ctime)
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "mtime {}\n",
-                libhammer2::subs::get_local_time_string(meta.mtime)
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "atime {}\n",
-                libhammer2::subs::get_local_time_string(meta.atime)
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "btime {}\n",
-                libhammer2::subs::get_local_time_string(meta.btime)
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "uid {}\n",
-                libhammer2::subs::get_uuid_string_from_bytes(&meta.uid)
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "gid {}\n",
-                libhammer2::subs::get_uuid_string_from_bytes(&meta.gid)
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "type {}\n",
-                libhammer2::subs::get_inode_type_string(meta.typ)
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "op_flags {:#04x}\n",
-                meta.op_flags
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "cap_flags {:#06x}\n",
-                meta.cap_flags
-            ));
-            v.push(hammer2_utils::tab::format!(tab, "mode {:<7o}\n", meta.mode));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "inum {:#018x}\n",
-                meta.inum
-            ));
-            v.push(hammer2_utils::tab::format!(tab, "size {} ", meta.size));
-            if meta.has_direct_data() && meta.size <= libhammer2::fs::HAMMER2_EMBEDDED_BYTES {
-                v.push("(embedded data)\n".to_string());
-            } else {
-                v.push("\n".to_string());
-            }
-            v.push(hammer2_utils::tab::format!(tab, "nlinks {}\n", meta.nlinks));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "iparent {:#018x}\n",
-                meta.iparent
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "name_key {:#018x}\n",
-                meta.name_key
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "name_len {}\n",
-                meta.name_len
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "ncopies {}\n",
-                meta.ncopies
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "comp_algo {}\n",
-                libhammer2::subs::get_comp_mode_string(meta.comp_algo)
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "check_algo {}\n",
-                libhammer2::subs::get_check_mode_string(meta.check_algo)
-            ));
-            if meta.is_root() {
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "pfs_nmasters {}\n",
-                    meta.pfs_nmasters
-                ));
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "pfs_type {} ({})\n",
-                    meta.pfs_type,
-                    libhammer2::subs::get_pfs_type_string(meta.pfs_type)
-                ));
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "pfs_inum {:#018x}\n",
-                    meta.pfs_inum
-                ));
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "pfs_clid {}\n",
-                    libhammer2::subs::get_uuid_string_from_bytes(&meta.pfs_clid)
-                ));
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "pfs_fsid {}\n",
-                    libhammer2::subs::get_uuid_string_from_bytes(&meta.pfs_fsid)
-                ));
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "pfs_lsnap_tid {:#018x}\n",
-                    meta.pfs_lsnap_tid
-                ));
-            }
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "data_quota {}\n",
-                meta.data_quota
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "data_count {}\n",
-                bref.embed_as::<libhammer2::fs::Hammer2BlockrefEmbedStats>()
-                    .data_count
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "inode_quota {}\n",
-                meta.inode_quota
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "inode_count {}\n",
-                bref.embed_as::<libhammer2::fs::Hammer2BlockrefEmbedStats>()
-                    .inode_count
-            ));
-        }
-        libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT
-        | libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE => {
-            for (i, bref) in libhammer2::fs::media_as::<libhammer2::fs::Hammer2Blockref>(media)
-                .iter()
-                .enumerate()
-            {
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "{i:<3} {:016x} {:<12} {:016x}/{:<2}\n",
-                    bref.data_off,
-                    libhammer2::subs::get_blockref_type_string(bref.typ),
-                    bref.key,
-                    bref.keybits
-                ));
-            }
-        }
-        libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
-            let dirent = bref.embed_as::<libhammer2::fs::Hammer2DirentHead>();
-            let namelen = usize::from(dirent.namlen);
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "filename \"{}\"\n",
-                if namelen <= bref.check.len() {
-                    std::str::from_utf8(&bref.check[..namelen])
-                } else {
-                    std::str::from_utf8(&media[..namelen])
-                }?
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "inum {:#018x}\n",
-                dirent.inum
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "namelen {}\n",
-                dirent.namlen
-            ));
-            v.push(hammer2_utils::tab::format!(
-                tab,
-                "type {}\n",
-                libhammer2::subs::get_inode_type_string(dirent.typ)
-            ));
-        }
-        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF => {
-            let bmdata = libhammer2::fs::media_as::<libhammer2::fs::Hammer2BmapData>(media);
-            for i in 0..libhammer2::fs::HAMMER2_FREEMAP_COUNT {
-                let bmdata = &bmdata[i];
-                let data_off =
-                    bref.key + u64::try_from(i)? * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
-                v.push(hammer2_utils::tab::format!(
-                    tab,
-                    "{data_off:016x} {i:04}.{:04x} (avail={:07}) \
-                    {:016x} {:016x} {:016x} {:016x} {:016x} {:016x} {:016x} {:016x}\n",
-                    bmdata.class,
-                    bmdata.avail,
-                    bmdata.bitmapq[0],
-                    bmdata.bitmapq[1],
-                    bmdata.bitmapq[2],
-                    bmdata.bitmapq[3],
-                    bmdata.bitmapq[4],
-                    bmdata.bitmapq[5],
-                    bmdata.bitmapq[6],
-                    bmdata.bitmapq[7]
-                ));
-            }
-        }
-        _ => (),
-    }
-    Ok(v)
-}
-
 pub(crate) fn fsck(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
     let mut fso = libhammer2::ondisk::init(devpath, true)?;
     let best = fso.get_best_volume_data()?[libhammer2::fs::HAMMER2_ROOT_VOLUME as usize];
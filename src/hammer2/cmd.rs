@@ -1,14 +1,20 @@
 pub(crate) mod bulkfree;
 pub(crate) mod cidprune;
 pub(crate) mod cleanup;
+pub(crate) mod compstat;
+pub(crate) mod corrupt;
 pub(crate) mod destroy;
 pub(crate) mod destroy_inum;
+pub(crate) mod df;
 pub(crate) mod dhash;
+pub(crate) mod dumpblock;
 pub(crate) mod dumpchain;
 pub(crate) mod emergency_mode;
 pub(crate) mod freemap;
 pub(crate) mod growfs;
 pub(crate) mod hash;
+pub(crate) mod history;
+pub(crate) mod image;
 pub(crate) mod pfs_create;
 pub(crate) mod pfs_delete;
 pub(crate) mod pfs_id;
@@ -21,6 +27,7 @@ pub(crate) mod show;
 pub(crate) mod snapshot;
 pub(crate) mod stat;
 pub(crate) mod volhdr;
+pub(crate) mod volume_add;
 pub(crate) mod volume_list;
 pub(crate) mod volume_list2;
 
@@ -36,3 +43,55 @@ pub(crate) fn get_ioctl_handle(sel_path: &str) -> hammer2_utils::Result<std::fs:
     }
     Ok(fp)
 }
+
+// Split <devpath>[@<label>[:<path>]] into devpath and PFS label and path.
+// Devpath may contain ':' to separate volumes, but not '@'.
+pub(crate) fn parse_devpath(s: &str) -> (&str, Option<(&str, &str)>) {
+    match s.split_once('@') {
+        Some((devpath, v)) => (devpath, Some(v.split_once(':').unwrap_or((v, "")))),
+        None => (s, None),
+    }
+}
+
+// Offsets and inode numbers are decimal, or hexadecimal with 0x prefix.
+pub(crate) fn parse_u64(s: &str) -> hammer2_utils::Result<u64> {
+    Ok(if let Some(v) = s.strip_prefix("0x") {
+        u64::from_str_radix(v, 16)?
+    } else {
+        s.parse()?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse_devpath() {
+        assert_eq!(super::parse_devpath("/dev/da0"), ("/dev/da0", None));
+        assert_eq!(
+            super::parse_devpath("/dev/da0:/dev/da1"),
+            ("/dev/da0:/dev/da1", None)
+        );
+        assert_eq!(
+            super::parse_devpath("/dev/da0@ROOT"),
+            ("/dev/da0", Some(("ROOT", "")))
+        );
+        assert_eq!(
+            super::parse_devpath("/dev/da0@ROOT:/a/b"),
+            ("/dev/da0", Some(("ROOT", "/a/b")))
+        );
+        assert_eq!(
+            super::parse_devpath("/dev/da0:/dev/da1@ROOT:/a"),
+            ("/dev/da0:/dev/da1", Some(("ROOT", "/a")))
+        );
+    }
+
+    #[test]
+    fn test_parse_u64() {
+        assert_eq!(super::parse_u64("1").unwrap(), 1);
+        assert_eq!(super::parse_u64("10").unwrap(), 10);
+        assert_eq!(super::parse_u64("0x10").unwrap(), 0x10);
+        assert!(super::parse_u64("").is_err());
+        assert!(super::parse_u64("1f").is_err());
+        assert!(super::parse_u64("-1").is_err());
+    }
+}
@@ -0,0 +1,300 @@
+// Offline analysis of compression effectiveness of DATA blockrefs, per
+// directory subtree and per file extension.  Logical bytes are key range
+// of blockrefs, and physical bytes are allocated size on media.  Data
+// embedded in inodes counts as uncompressed with its file size.
+
+#[derive(Clone, Copy, Debug, Default)]
+struct CompStat {
+    logical: u64,
+    physical: u64,
+    none: u64,
+    autozero: u64,
+    lz4: u64,
+    zlib: u64,
+    lz4_logical: u64,
+    lz4_physical: u64,
+}
+
+impl CompStat {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn add_blockref(&mut self, bref: &libhammer2::fs::Hammer2Blockref) {
+        let logical = 1 << bref.keybits.min(63);
+        let radix = crate::walk::get_radix(bref);
+        let physical = if radix == 0 { 0 } else { 1 << radix };
+        self.logical += logical;
+        self.physical += physical;
+        match libhammer2::fs::dec_comp(bref.methods) {
+            libhammer2::fs::HAMMER2_COMP_NONE => self.none += 1,
+            libhammer2::fs::HAMMER2_COMP_AUTOZERO => self.autozero += 1,
+            libhammer2::fs::HAMMER2_COMP_LZ4 => {
+                self.lz4 += 1;
+                self.lz4_logical += logical;
+                self.lz4_physical += physical;
+            }
+            libhammer2::fs::HAMMER2_COMP_ZLIB => self.zlib += 1,
+            _ => (),
+        }
+    }
+
+    fn add_direct_data(&mut self, size: u64) {
+        self.logical += size;
+        self.physical += size;
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.logical += other.logical;
+        self.physical += other.physical;
+        self.none += other.none;
+        self.autozero += other.autozero;
+        self.lz4 += other.lz4;
+        self.zlib += other.zlib;
+        self.lz4_logical += other.lz4_logical;
+        self.lz4_physical += other.lz4_physical;
+    }
+
+    fn get_blocks(&self) -> u64 {
+        self.none + self.autozero + self.lz4 + self.zlib
+    }
+}
+
+// Return percentage of a to b.
+fn get_percent(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 * 100.0 / b as f64
+    }
+}
+
+// Suggest a compression algorithm for a subtree, given compression
+// algorithm of the directory.  Blocks which didn't compress are stored
+// uncompressed, thus mostly uncompressed blocks under a compressing
+// directory indicate incompressible data.  AUTOZERO only detects zero
+// filled blocks and isn't a compressing algorithm here.
+fn get_suggestion(stat: &CompStat, comp_algo: u8) -> Option<String> {
+    let blocks = stat.get_blocks();
+    if blocks == 0 {
+        return None;
+    }
+    let none = get_percent(stat.none, blocks);
+    let compressing = matches!(
+        libhammer2::fs::dec_comp(comp_algo),
+        libhammer2::fs::HAMMER2_COMP_LZ4 | libhammer2::fs::HAMMER2_COMP_ZLIB
+    );
+    if compressing && none >= 75.0 {
+        return Some(format!(
+            "{none:.0}% of blocks stored uncompressed, consider \"setcomp none\""
+        ));
+    }
+    let ratio = get_percent(stat.lz4_physical, stat.lz4_logical);
+    if stat.lz4 * 2 >= blocks && ratio <= 50.0 {
+        return Some(format!(
+            "lz4 blocks compressed to {ratio:.0}%, consider \"setcomp zlib\""
+        ));
+    }
+    None
+}
+
+// Get extension of a filename, or an empty string if none.
+fn get_extension(name: &[u8]) -> String {
+    match name.iter().rposition(|x| *x == b'.') {
+        Some(i) if i > 0 && i < name.len() - 1 => {
+            String::from_utf8_lossy(&name[i..]).to_lowercase()
+        }
+        _ => String::new(),
+    }
+}
+
+fn scan_file(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    file: &crate::walk::Found,
+) -> hammer2_utils::Result<CompStat> {
+    let mut stat = CompStat::new();
+    let media = fso.read_media(&file.bref)?;
+    if let Some(ipdata) = hammer2_utils::media::media_as_inode_data(&media) {
+        if ipdata.meta.has_direct_data() {
+            stat.add_direct_data(ipdata.meta.size);
+            return Ok(stat);
+        }
+    }
+    let mut parents = file.parents.clone();
+    crate::walk::walk(
+        fso,
+        &file.bref,
+        &mut parents,
+        &mut std::collections::HashSet::new(),
+        &mut |_, bref, _, _| {
+            match bref.typ {
+                libhammer2::fs::HAMMER2_BREF_TYPE_DATA => stat.add_blockref(bref),
+                libhammer2::fs::HAMMER2_BREF_TYPE_INODE
+                | libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => (),
+                _ => return Ok(crate::walk::Walk::Skip),
+            }
+            Ok(crate::walk::Walk::Continue)
+        },
+    )?;
+    Ok(stat)
+}
+
+#[derive(Debug, Default)]
+struct Context {
+    dirs: Vec<(String, CompStat, u8)>, // path, stat, comp_algo
+    exts: std::collections::BTreeMap<String, CompStat>,
+    visited: std::collections::HashSet<u64>,
+}
+
+fn scan_dir(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    pfs: &crate::walk::Found,
+    dir: &crate::walk::Found,
+    path: &str,
+    ctx: &mut Context,
+) -> hammer2_utils::Result<CompStat> {
+    let media = fso.read_media(&dir.bref)?;
+    let comp_algo = hammer2_utils::media::media_as_inode_data(&media)
+        .map_or(libhammer2::fs::HAMMER2_COMP_NONE, |x| x.meta.comp_algo);
+    let index = ctx.dirs.len();
+    ctx.dirs
+        .push((path.to_string(), CompStat::new(), comp_algo));
+
+    let mut total = CompStat::new();
+    for (name, inum, typ) in crate::walk::get_dirents(fso, dir)? {
+        if !ctx.visited.insert(inum) {
+            continue; // hardlink
+        }
+        let Some(found) = crate::walk::lookup_inum(fso, pfs, inum)? else {
+            log::warn!("{path}: no inode {inum:#x}");
+            continue;
+        };
+        match typ {
+            libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY => {
+                let path = format!(
+                    "{}/{}",
+                    path.trim_end_matches('/'),
+                    String::from_utf8_lossy(&name)
+                );
+                total.add(&scan_dir(fso, pfs, &found, &path, ctx)?);
+            }
+            libhammer2::fs::HAMMER2_OBJTYPE_REGFILE => {
+                let stat = scan_file(fso, &found)?;
+                total.add(&stat);
+                ctx.exts
+                    .entry(get_extension(&name))
+                    .or_insert_with(CompStat::new)
+                    .add(&stat);
+            }
+            _ => (),
+        }
+    }
+    ctx.dirs[index].1 = total;
+    Ok(total)
+}
+
+fn print_stat(name: &str, stat: &CompStat, w: usize) {
+    println!(
+        "{name:<w$} {:>9} {:>9} {:>5.1}% {:>8} {:>8} {:>8} {:>8}",
+        libhammer2::subs::get_size_string(stat.logical),
+        libhammer2::subs::get_size_string(stat.physical),
+        get_percent(stat.physical, stat.logical),
+        stat.none,
+        stat.lz4,
+        stat.zlib,
+        stat.autozero
+    );
+}
+
+fn print_header(name: &str, w: usize) {
+    println!("{name:<w$}   logical  physical  ratio     none      lz4     zlib autozero");
+}
+
+pub(crate) fn run(devpath: &str, path: Option<&str>) -> hammer2_utils::Result<()> {
+    let (devpath, target) = super::parse_devpath(devpath);
+    let Some((label, s)) = target else {
+        log::error!("Requires <devpath>@<label>");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    };
+    let path = path.unwrap_or(s);
+    let path = if path.is_empty() { "/" } else { path };
+
+    let mut fso = libhammer2::ondisk::init(devpath, true)?;
+    let pfs = crate::walk::get_pfs_root(&mut fso, label)?;
+    let Some(dir) = crate::walk::lookup_path(&mut fso, &pfs, path)? else {
+        log::error!("No such path {path}");
+        return Err(Box::new(nix::errno::Errno::ENOENT));
+    };
+    let mut ctx = Context::default();
+    scan_dir(&mut fso, &pfs, &dir, path, &mut ctx)?;
+    ctx.dirs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let w = ctx
+        .dirs
+        .iter()
+        .map(|x| x.0.len())
+        .max()
+        .unwrap_or(0)
+        .max(16);
+    print_header("PATH", w);
+    for (path, stat, _) in &ctx.dirs {
+        print_stat(path, stat, w);
+    }
+    println!();
+    print_header("EXTENSION", w);
+    for (ext, stat) in &ctx.exts {
+        print_stat(if ext.is_empty() { "-" } else { ext }, stat, w);
+    }
+
+    let v: Vec<_> = ctx
+        .dirs
+        .iter()
+        .filter_map(|(path, stat, comp_algo)| {
+            get_suggestion(stat, *comp_algo).map(|s| format!("{path}: {s}"))
+        })
+        .collect();
+    if !v.is_empty() {
+        println!();
+        for s in &v {
+            println!("{s}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_get_extension() {
+        assert_eq!(super::get_extension(b"a.txt"), ".txt");
+        assert_eq!(super::get_extension(b"a.b.TGZ"), ".tgz");
+        assert_eq!(super::get_extension(b"a"), "");
+        assert_eq!(super::get_extension(b".profile"), "");
+        assert_eq!(super::get_extension(b"a."), "");
+        assert_eq!(super::get_extension(b""), "");
+    }
+
+    #[test]
+    fn test_get_suggestion() {
+        let lz4 = libhammer2::fs::HAMMER2_COMP_LZ4;
+        let mut stat = super::CompStat::new();
+        assert!(super::get_suggestion(&stat, lz4).is_none());
+
+        stat.none = 8;
+        stat.lz4 = 2;
+        assert!(super::get_suggestion(&stat, lz4).is_some());
+        assert!(super::get_suggestion(&stat, libhammer2::fs::HAMMER2_COMP_NONE).is_none());
+        assert!(super::get_suggestion(&stat, libhammer2::fs::HAMMER2_COMP_AUTOZERO).is_none());
+        let zlib = libhammer2::fs::enc_algo(libhammer2::fs::HAMMER2_COMP_ZLIB);
+        assert!(super::get_suggestion(&stat, zlib).is_some());
+
+        stat.none = 0;
+        stat.lz4_logical = 65536 * 2;
+        stat.lz4_physical = 16384 * 2;
+        assert!(super::get_suggestion(&stat, lz4).is_some());
+        stat.lz4_physical = 65536 * 2;
+        assert!(super::get_suggestion(&stat, lz4).is_none());
+    }
+}
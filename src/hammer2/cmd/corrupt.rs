@@ -0,0 +1,412 @@
+// Developer directive to inject corruption into a filesystem image.
+// All changes are logged as key=value lines so that results of
+// fsck_hammer2 and recover can be asserted by automated tests.
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Target {
+    Volhdr(usize, usize), // volume id, header index
+    DataOff(u64),
+    Inum(u64),
+    Path(String, String), // PFS label, path
+    Type(u8, usize),      // blockref type, n'th in walk order
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Action {
+    Flip(usize), // bit offset
+    Zero,
+    Check,
+    Truncate,
+    Swap,
+}
+
+pub(crate) fn parse_target(s: &str) -> hammer2_utils::Result<Target> {
+    let Some((k, v)) = s.split_once('=') else {
+        log::error!("Invalid target {s}");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    };
+    match k {
+        "volhdr" => {
+            let (id, index) = match v.split_once(':') {
+                Some((a, b)) => (a.parse()?, b.parse()?),
+                None => (libhammer2::fs::HAMMER2_ROOT_VOLUME.into(), v.parse()?),
+            };
+            if index >= libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+                log::error!("Invalid volume header index {index}");
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            }
+            Ok(Target::Volhdr(id, index))
+        }
+        "data_off" => Ok(Target::DataOff(super::parse_u64(v)?)),
+        "inum" => Ok(Target::Inum(super::parse_u64(v)?)),
+        "path" => {
+            let v = v.trim_start_matches('/');
+            let (label, path) = v.split_once('/').unwrap_or((v, ""));
+            if label.is_empty() {
+                log::error!("Path requires PFS label {s}");
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            }
+            Ok(Target::Path(label.to_string(), path.to_string()))
+        }
+        "type" => {
+            let (t, n) = match v.split_once(':') {
+                Some((a, b)) => (a, b.parse()?),
+                None => (v, 0),
+            };
+            let Some(t) = crate::walk::get_blockref_type(t) else {
+                log::error!("Invalid blockref type {t}");
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            };
+            Ok(Target::Type(t, n))
+        }
+        _ => {
+            log::error!("Invalid target {s}");
+            Err(Box::new(nix::errno::Errno::EINVAL))
+        }
+    }
+}
+
+pub(crate) fn parse_action(s: &str) -> hammer2_utils::Result<Action> {
+    let (k, v) = s.split_once(':').unwrap_or((s, ""));
+    match k {
+        "flip" => Ok(Action::Flip(if v.is_empty() { 0 } else { v.parse()? })),
+        "zero" if v.is_empty() => Ok(Action::Zero),
+        "check" if v.is_empty() => Ok(Action::Check),
+        "truncate" if v.is_empty() => Ok(Action::Truncate),
+        "swap" if v.is_empty() => Ok(Action::Swap),
+        _ => {
+            log::error!("Invalid action {s}");
+            Err(Box::new(nix::errno::Errno::EINVAL))
+        }
+    }
+}
+
+fn find_target(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    target: &Target,
+) -> hammer2_utils::Result<Option<crate::walk::Found>> {
+    match target {
+        Target::Volhdr(..) => unreachable!(),
+        Target::DataOff(data_off) => crate::walk::lookup_data_off(fso, *data_off),
+        Target::Inum(inum) => {
+            for (_, pfs) in &crate::walk::get_pfs_roots(fso)? {
+                if let Some(v) = crate::walk::lookup_inum(fso, pfs, *inum)? {
+                    return Ok(Some(v));
+                }
+            }
+            Ok(None)
+        }
+        Target::Path(label, path) => {
+            let pfs = crate::walk::get_pfs_root(fso, label)?;
+            crate::walk::lookup_path(fso, &pfs, path)
+        }
+        Target::Type(t, n) => {
+            let typ = if *t == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE
+                || *t == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF
+            {
+                libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP
+            } else {
+                libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME
+            };
+            let broot = crate::walk::get_root_blockref(fso, typ)?;
+            let mut found = None;
+            let mut count = 0;
+            crate::walk::walk(
+                fso,
+                &broot,
+                &mut vec![],
+                &mut std::collections::HashSet::new(),
+                &mut |_, bref, _, parents| {
+                    if bref.typ == *t && crate::walk::get_radix(bref) != 0 {
+                        if count == *n {
+                            found = Some(crate::walk::Found {
+                                bref: *bref,
+                                parents: parents.to_vec(),
+                            });
+                            return Ok(crate::walk::Walk::Stop);
+                        }
+                        count += 1;
+                    }
+                    Ok(crate::walk::Walk::Continue)
+                },
+            )?;
+            Ok(found)
+        }
+    }
+}
+
+// Store bref into media of the parents, and print the parents written.
+fn update_parents(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    parents: &[crate::walk::Location],
+) -> hammer2_utils::Result<()> {
+    for x in crate::walk::update_parents(fso, bref, parents)? {
+        if x.typ == libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME
+            || x.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP
+        {
+            println!("parent=volhdr:{:016x}", crate::walk::get_io_offset(&x));
+        } else {
+            println!("parent={:016x}", x.data_off);
+        }
+    }
+    Ok(())
+}
+
+fn flip_bit(media: &mut [u8], bit: usize) -> hammer2_utils::Result<()> {
+    let i = bit / 8;
+    if i >= media.len() {
+        log::error!("Bit {bit} out of range");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let old = media[i];
+    media[i] ^= 1 << (bit % 8);
+    println!("byte={i}");
+    println!("bit={}", bit % 8);
+    println!("old={old:#04x}");
+    println!("new={:#04x}", media[i]);
+    Ok(())
+}
+
+// Swap the first two non-empty child blockrefs.
+fn swap_children(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: &mut [u8],
+) -> hammer2_utils::Result<()> {
+    let v: Vec<usize> = crate::walk::get_blockref_offsets(bref, media)
+        .into_iter()
+        .filter(|x| {
+            crate::walk::get_blockref_at(media, *x).typ != libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY
+        })
+        .take(2)
+        .collect();
+    if v.len() != 2 {
+        log::error!("{:016x} has less than 2 children", bref.data_off);
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let a = crate::walk::get_blockref_at(media, v[0]);
+    let b = crate::walk::get_blockref_at(media, v[1]);
+    *libfs::cast::align_head_to_mut(&mut media[v[0]..]) = b;
+    *libfs::cast::align_head_to_mut(&mut media[v[1]..]) = a;
+    println!("child0={:016x}", a.data_off);
+    println!("child1={:016x}", b.data_off);
+    Ok(())
+}
+
+fn corrupt_volhdr(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    id: usize,
+    index: usize,
+    action: &Action,
+) -> hammer2_utils::Result<()> {
+    let mut vol = None;
+    for i in 0..fso.get_nvolumes() {
+        if fso[i].get_id() == id {
+            vol = Some(i);
+        }
+    }
+    let Some(i) = vol else {
+        log::error!("No such volume {id}");
+        return Err(Box::new(nix::errno::Errno::ENODEV));
+    };
+    let vol = &mut fso[i];
+    let offset = libhammer2::volume::get_volume_data_offset(index);
+    if offset >= vol.get_size() {
+        log::error!("No volume header {index} in volume {id}");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let mut media = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+    println!("type=volume");
+    println!("vol={id}");
+    println!("volhdr={index}");
+    println!("poff={offset:016x}");
+    println!("size={}", media.len());
+    match action {
+        Action::Flip(bit) => {
+            println!("action=flip");
+            flip_bit(&mut media, *bit)?;
+        }
+        Action::Zero => {
+            println!("action=zero");
+            media.fill(0);
+        }
+        Action::Check => {
+            println!("action=check");
+            let voldata = crate::volume::media_as_volume_data_mut(&mut media);
+            let old = voldata.icrc_volheader;
+            voldata.icrc_volheader = !old;
+            println!("old={old:08x}");
+            println!("new={:08x}", voldata.icrc_volheader);
+        }
+        Action::Swap => {
+            println!("action=swap");
+            let mut bref =
+                libhammer2::fs::Hammer2Blockref::new(libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME);
+            bref.data_off = offset;
+            swap_children(&bref, &mut media)?;
+            hammer2_utils::volume::update_crc(crate::volume::media_as_volume_data_mut(&mut media));
+        }
+        Action::Truncate => {
+            log::error!("Volume header can't be truncated");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+    }
+    vol.pwrite(&media, offset)?;
+    Ok(vol.fsync()?)
+}
+
+pub(crate) fn run(image: &str, target: &str, action: &str) -> hammer2_utils::Result<()> {
+    let t = parse_target(target)?;
+    let action = parse_action(action)?;
+    let mut fso = libhammer2::ondisk::init(image, false)?;
+    println!("image={image}");
+    println!("target={target}");
+    if let Target::Volhdr(id, index) = t {
+        return corrupt_volhdr(&mut fso, id, index, &action);
+    }
+
+    let Some(found) = find_target(&mut fso, &t)? else {
+        log::error!("Target {target} not found");
+        return Err(Box::new(nix::errno::Errno::ENOENT));
+    };
+    let bref = found.bref;
+    let radix = crate::walk::get_radix(&bref);
+    if radix == 0 {
+        log::error!("{target} has no media");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let vol = fso
+        .get_volume(bref.data_off)
+        .ok_or(nix::errno::Errno::ENODEV)?;
+    println!(
+        "type={}",
+        libhammer2::subs::get_blockref_type_string(bref.typ)
+    );
+    println!("data_off={:016x}", bref.data_off);
+    println!("key={:016x}", bref.key);
+    println!("vol={}", vol.get_id());
+    println!(
+        "poff={:016x}",
+        crate::walk::get_io_offset(&bref) - vol.get_offset()
+    );
+    println!("size={}", 1u64 << radix);
+    println!(
+        "check={}",
+        libhammer2::subs::get_check_mode_string(libhammer2::fs::dec_check(bref.methods))
+    );
+    println!(
+        "comp={}",
+        libhammer2::subs::get_comp_mode_string(libhammer2::fs::dec_comp(bref.methods))
+    );
+
+    let mut media = crate::walk::read_raw(&mut fso, &bref)?;
+    match action {
+        Action::Flip(bit) => {
+            println!("action=flip");
+            flip_bit(&mut media, bit)?;
+            crate::walk::write_raw(&mut fso, &bref, &media)?;
+        }
+        Action::Zero => {
+            println!("action=zero");
+            media.fill(0);
+            crate::walk::write_raw(&mut fso, &bref, &media)?;
+        }
+        Action::Check => {
+            // Only the check code of the target is broken.
+            println!("action=check");
+            let mut nbref = bref;
+            for x in &mut nbref.check[..8] {
+                *x = !*x;
+            }
+            update_parents(&mut fso, &nbref, &found.parents)?;
+        }
+        Action::Truncate => {
+            // The check code is valid, but decompression fails.
+            if !crate::walk::is_compressed(&bref) {
+                log::error!("{target} isn't compressed");
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            }
+            println!("action=truncate");
+            let n = media.len() / 2;
+            media[n..].fill(0);
+            println!("length={n}");
+            crate::walk::write_raw(&mut fso, &bref, &media)?;
+            let mut nbref = bref;
+            crate::walk::update_check(&mut nbref, &media)?;
+            update_parents(&mut fso, &nbref, &found.parents)?;
+        }
+        Action::Swap => {
+            // The check code is valid, but keys are out of order.
+            if crate::walk::is_compressed(&bref) {
+                log::error!("{target} is compressed");
+                return Err(Box::new(nix::errno::Errno::EOPNOTSUPP));
+            }
+            println!("action=swap");
+            swap_children(&bref, &mut media)?;
+            crate::walk::write_raw(&mut fso, &bref, &media)?;
+            let mut nbref = bref;
+            crate::walk::update_check(&mut nbref, &media)?;
+            update_parents(&mut fso, &nbref, &found.parents)?;
+        }
+    }
+    for i in 0..fso.get_nvolumes() {
+        fso[i].fsync()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Action;
+    use super::Target;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            super::parse_target("volhdr=1").unwrap(),
+            Target::Volhdr(0, 1)
+        );
+        assert_eq!(
+            super::parse_target("volhdr=2:3").unwrap(),
+            Target::Volhdr(2, 3)
+        );
+        assert!(super::parse_target("volhdr=4").is_err());
+        assert_eq!(
+            super::parse_target("data_off=0x1000a").unwrap(),
+            Target::DataOff(0x1000a)
+        );
+        assert_eq!(super::parse_target("inum=123").unwrap(), Target::Inum(123));
+        assert_eq!(
+            super::parse_target("path=DATA/a/b").unwrap(),
+            Target::Path("DATA".to_string(), "a/b".to_string())
+        );
+        assert_eq!(
+            super::parse_target("path=/DATA").unwrap(),
+            Target::Path("DATA".to_string(), String::new())
+        );
+        assert_eq!(
+            super::parse_target("type=indirect").unwrap(),
+            Target::Type(libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT, 0)
+        );
+        assert_eq!(
+            super::parse_target("type=freemap_leaf:2").unwrap(),
+            Target::Type(libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF, 2)
+        );
+        assert!(super::parse_target("type=xxx").is_err());
+        assert!(super::parse_target("path=").is_err());
+        assert!(super::parse_target("inode").is_err());
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(super::parse_action("flip").unwrap(), Action::Flip(0));
+        assert_eq!(super::parse_action("flip:77").unwrap(), Action::Flip(77));
+        assert_eq!(super::parse_action("zero").unwrap(), Action::Zero);
+        assert_eq!(super::parse_action("check").unwrap(), Action::Check);
+        assert_eq!(super::parse_action("truncate").unwrap(), Action::Truncate);
+        assert_eq!(super::parse_action("swap").unwrap(), Action::Swap);
+        assert!(super::parse_action("zero:1").is_err());
+        assert!(super::parse_action("flip:x").is_err());
+        assert!(super::parse_action("xxx").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+// Offline space accounting per PFS (including snapshots).  A block
+// reachable from more than one PFS root is shared, i.e. destroying
+// one of the PFSs doesn't free it.
+
+#[derive(Debug, Default)]
+struct PfsStat {
+    label: String,
+    pfs_type: u8,
+    pfs_subtype: u8,
+    inodes: u64,
+    logical: u64,
+    comp: std::collections::BTreeMap<u8, u64>,
+    check: std::collections::BTreeMap<u8, u64>,
+    blocks: std::collections::HashMap<u64, u64>, // data_off, bytes
+}
+
+impl PfsStat {
+    fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn get_physical(&self) -> u64 {
+        self.blocks.values().sum()
+    }
+}
+
+fn get_pfs_stat(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    label: &str,
+    pfs: &crate::walk::Found,
+) -> hammer2_utils::Result<PfsStat> {
+    let mut stat = PfsStat::new(label);
+    let mut parents = pfs.parents.clone();
+    crate::walk::walk(
+        fso,
+        &pfs.bref,
+        &mut parents,
+        &mut std::collections::HashSet::new(),
+        &mut |fso, bref, media, parents| {
+            let radix = crate::walk::get_radix(bref);
+            if radix != 0 {
+                let bytes = 1 << radix;
+                if stat.blocks.insert(bref.data_off, bytes).is_none() {
+                    *stat
+                        .comp
+                        .entry(libhammer2::fs::dec_comp(bref.methods))
+                        .or_insert(0) += bytes;
+                    *stat
+                        .check
+                        .entry(libhammer2::fs::dec_check(bref.methods))
+                        .or_insert(0) += bytes;
+                }
+            }
+            if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_INODE {
+                stat.inodes += 1;
+                if let Some(ipdata) = hammer2_utils::media::media_as_inode_data(media.get(fso)) {
+                    if parents.len() == pfs.parents.len() {
+                        stat.pfs_type = ipdata.meta.pfs_type;
+                        stat.pfs_subtype = ipdata.meta.pfs_subtype;
+                    }
+                    if ipdata.meta.typ == libhammer2::fs::HAMMER2_OBJTYPE_REGFILE {
+                        stat.logical += ipdata.meta.size;
+                    }
+                }
+            }
+            Ok(crate::walk::Walk::Continue)
+        },
+    )?;
+    Ok(stat)
+}
+
+pub(crate) fn run(devpath: &str) -> hammer2_utils::Result<()> {
+    let mut fso = libhammer2::ondisk::init(devpath, true)?;
+    let mut v = vec![];
+    for (label, pfs) in crate::walk::get_pfs_roots(&mut fso)? {
+        v.push(get_pfs_stat(&mut fso, &label, &pfs)?);
+    }
+    // number of PFSs which refer to each block
+    let mut owners = std::collections::HashMap::new();
+    for stat in &v {
+        for (off, bytes) in &stat.blocks {
+            owners.entry(*off).or_insert((*bytes, 0)).1 += 1;
+        }
+    }
+
+    let w = v.iter().map(|x| x.label.len()).max().unwrap_or(0).max(16);
+    println!(
+        "{:<w$} type        subtype   inodes   logical  physical    unique    shared",
+        "PFS"
+    );
+    for stat in &v {
+        let physical = stat.get_physical();
+        let unique = stat
+            .blocks
+            .keys()
+            .filter(|x| owners[*x].1 == 1)
+            .map(|x| owners[x].0)
+            .sum::<u64>();
+        println!(
+            "{:<w$} {:<11} {:<8} {:>7} {:>9} {:>9} {:>9} {:>9}",
+            stat.label,
+            libhammer2::subs::get_pfs_type_string(stat.pfs_type),
+            libhammer2::subs::get_pfs_subtype_string(stat.pfs_subtype),
+            libhammer2::subs::get_count_string(stat.inodes),
+            libhammer2::subs::get_size_string(stat.logical),
+            libhammer2::subs::get_size_string(physical),
+            libhammer2::subs::get_size_string(unique),
+            libhammer2::subs::get_size_string(physical - unique)
+        );
+    }
+    println!(
+        "Total physical storage: {}",
+        libhammer2::subs::get_size_string(owners.values().map(|x| x.0).sum())
+    );
+
+    // physical bytes by compression and check algorithm
+    for stat in &v {
+        println!();
+        println!("{}", stat.label);
+        for (k, bytes) in &stat.comp {
+            println!(
+                "    comp  {:<12} {:>9}",
+                libhammer2::subs::get_comp_mode_string(*k),
+                libhammer2::subs::get_size_string(*bytes)
+            );
+        }
+        for (k, bytes) in &stat.check {
+            println!(
+                "    check {:<12} {:>9}",
+                libhammer2::subs::get_check_mode_string(*k),
+                libhammer2::subs::get_size_string(*bytes)
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,312 @@
+// Dump media of a single block given its data_off.  The blockref is
+// discovered from the parent with --bref-from-parent, otherwise data_off
+// must contain radix, and the block is verified against the check code given
+// by --check if any.  Volume headers are verified by their icrc sections.
+
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Format {
+    #[default]
+    Hex,
+    Raw,
+    Decode,
+}
+
+// Get a blockref of a volume header located at data_off, if any.
+// Volume headers aren't referred to by blockrefs.
+fn get_volume_blockref(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    data_off: u64,
+) -> hammer2_utils::Result<Option<libhammer2::fs::Hammer2Blockref>> {
+    let io_offset = data_off & !libhammer2::fs::HAMMER2_OFF_MASK_RADIX;
+    for i in 0..fso.get_nvolumes() {
+        let vol = &mut fso[i];
+        for j in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+            let offset = libhammer2::volume::get_volume_data_offset(j);
+            if offset < vol.get_size() && vol.get_offset() + offset == io_offset {
+                let mut bref =
+                    libhammer2::fs::Hammer2Blockref::new(libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME);
+                bref.data_off = io_offset | u64::try_from(libhammer2::fs::HAMMER2_PBUFRADIX)?;
+                return Ok(Some(bref));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Parse <algo>[:<hex>] into a blockref carrying only the check code.
+// Algorithms other than none and disabled require a check code.
+pub(crate) fn parse_check(s: &str) -> hammer2_utils::Result<libhammer2::fs::Hammer2Blockref> {
+    let (algo, code) = match s.split_once(':') {
+        Some((algo, code)) => (algo, Some(code)),
+        None => (s, None),
+    };
+    let check_algo = super::setcheck::parse_check_algo(algo)?;
+    let mut bref = libhammer2::fs::Hammer2Blockref::new_empty();
+    bref.methods = libhammer2::fs::enc_check(check_algo);
+    let code = match (check_algo, code) {
+        (libhammer2::fs::HAMMER2_CHECK_NONE | libhammer2::fs::HAMMER2_CHECK_DISABLED, None) => {
+            return Ok(bref)
+        }
+        (libhammer2::fs::HAMMER2_CHECK_NONE | libhammer2::fs::HAMMER2_CHECK_DISABLED, Some(_))
+        | (_, None) => return Err(Box::new(nix::errno::Errno::EINVAL)),
+        (_, Some(code)) => code.strip_prefix("0x").unwrap_or(code),
+    };
+    match check_algo {
+        libhammer2::fs::HAMMER2_CHECK_ISCSI32 => {
+            bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckIscsi>()
+                .value = u32::from_str_radix(code, 16)?;
+        }
+        libhammer2::fs::HAMMER2_CHECK_XXHASH64 => {
+            bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckXxhash64>()
+                .value = u64::from_str_radix(code, 16)?;
+        }
+        libhammer2::fs::HAMMER2_CHECK_SHA192 => {
+            let data = &mut bref
+                .check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckSha256>()
+                .data;
+            if code.len() != data.len() * 2 {
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            }
+            for (i, x) in data.iter_mut().enumerate() {
+                let Some(b) = code.get(i * 2..i * 2 + 2) else {
+                    return Err(Box::new(nix::errno::Errno::EINVAL));
+                };
+                *x = u8::from_str_radix(b, 16)?;
+            }
+        }
+        libhammer2::fs::HAMMER2_CHECK_FREEMAP => {
+            bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckFreemap>()
+                .icrc32 = u32::from_str_radix(code, 16)?;
+        }
+        _ => return Err(Box::new(nix::errno::Errno::EINVAL)),
+    }
+    Ok(bref)
+}
+
+// Volume headers have no blockref check code, but each of the icrc sections
+// must match.
+fn get_volume_check_string(media: &[u8]) -> &'static str {
+    let voldata = libhammer2::ondisk::media_as_volume_data(media);
+    let (sect0, sect1, volhdr) = super::volhdr::get_icrc_sects(voldata);
+    if [sect0, sect1, volhdr].iter().all(|x| x.ends_with(" OK")) {
+        "ok"
+    } else {
+        "failed"
+    }
+}
+
+fn get_check_string(bref: &libhammer2::fs::Hammer2Blockref, media: &[u8]) -> &'static str {
+    match libhammer2::fs::dec_check(bref.methods) {
+        libhammer2::fs::HAMMER2_CHECK_NONE | libhammer2::fs::HAMMER2_CHECK_DISABLED => "none",
+        _ => match libhammer2::ondisk::verify_media(bref, media) {
+            Ok(true) => "ok",
+            Ok(false) => "failed",
+            Err(_) => "unknown",
+        },
+    }
+}
+
+// Decompress media of bref if compressed.  Decompressed size is logical
+// size of the block, which never exceeds HAMMER2_PBUFSIZE.
+fn decompress(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: Vec<u8>,
+) -> hammer2_utils::Result<Vec<u8>> {
+    let nsize = 1u64
+        .checked_shl(bref.keybits.into())
+        .unwrap_or(u64::MAX)
+        .min(libhammer2::fs::HAMMER2_PBUFSIZE);
+    Ok(match libhammer2::fs::dec_comp(bref.methods) {
+        libhammer2::fs::HAMMER2_COMP_LZ4 => libhammer2::lz4::decompress(&media, nsize.try_into()?)?,
+        libhammer2::fs::HAMMER2_COMP_ZLIB => {
+            libhammer2::zlib::decompress(&media, nsize.try_into()?)?
+        }
+        _ => media,
+    })
+}
+
+// Format buf in "hexdump -C" style.  Repeated lines are collapsed into "*".
+fn get_hexdump(buf: &[u8]) -> Vec<String> {
+    let mut v = vec![];
+    let mut prev = None;
+    let mut collapsed = false;
+    for (i, x) in buf.chunks(16).enumerate() {
+        if prev == Some(x) {
+            if !collapsed {
+                v.push("*".to_string());
+                collapsed = true;
+            }
+            continue;
+        }
+        prev = Some(x);
+        collapsed = false;
+        let mut s = format!("{:08x} ", i * 16);
+        for j in 0..16 {
+            if j == 8 {
+                s.push(' ');
+            }
+            match x.get(j) {
+                Some(b) => s.push_str(&format!(" {b:02x}")),
+                None => s.push_str("   "),
+            }
+        }
+        s.push_str("  |");
+        for b in x {
+            s.push(if b.is_ascii_graphic() || *b == b' ' {
+                char::from(*b)
+            } else {
+                '.'
+            });
+        }
+        s.push('|');
+        v.push(s);
+    }
+    v.push(format!("{:08x}", buf.len()));
+    v
+}
+
+pub(crate) fn run(devpath: &str, data_off: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
+    let Ok(data_off) = super::parse_u64(data_off) else {
+        log::error!("Invalid data_off {data_off}");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    };
+    let mut fso = libhammer2::ondisk::init(devpath, true)?;
+    let (bref, media) = if let Some(bref) = get_volume_blockref(&mut fso, data_off)? {
+        let media = crate::walk::read_raw(&mut fso, &bref)?;
+        (bref, media)
+    } else {
+        let bref = if opt.bref_from_parent {
+            let Some(found) = crate::walk::lookup_data_off(&mut fso, data_off)? else {
+                log::error!("No blockref refers to {data_off:#018x}");
+                return Err(Box::new(nix::errno::Errno::ENOENT));
+            };
+            found.bref
+        } else {
+            let mut bref = libhammer2::fs::Hammer2Blockref::new_empty();
+            bref.data_off = data_off;
+            if let Some(v) = &opt.dump_check {
+                bref.methods = v.methods;
+                bref.check = v.check;
+            }
+            bref
+        };
+        if crate::walk::get_radix(&bref) == 0 {
+            log::error!("No radix in data_off {data_off:#018x}");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        let media = fso.read_media(&bref)?;
+        (bref, media)
+    };
+
+    // The check code is computed against media before decompression.
+    let check = if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME {
+        get_volume_check_string(&media)
+    } else {
+        get_check_string(&bref, &media)
+    };
+    if check == "failed" {
+        log::warn!("Check code mismatch for {:#018x}", bref.data_off);
+    }
+    let psize = media.len();
+    let media = match decompress(&bref, media) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to decompress {:#018x}: {e}", bref.data_off);
+            return Err(e);
+        }
+    };
+    if opt.dump_format == Format::Raw {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(&media)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    println!(
+        "{} data_off={:016x} key={:016x}/{} meth={}|{} check={check}",
+        libhammer2::subs::get_blockref_type_string(bref.typ),
+        bref.data_off,
+        bref.key,
+        bref.keybits,
+        libhammer2::subs::get_check_mode_string(libhammer2::fs::dec_check(bref.methods)),
+        libhammer2::subs::get_comp_mode_string(libhammer2::fs::dec_comp(bref.methods))
+    );
+    if media.len() != psize {
+        println!("decompressed {psize} -> {} bytes", media.len());
+    }
+    // Types without a decoder fall back to hexdump.
+    let v = if opt.dump_format == Format::Decode {
+        hammer2_utils::media::format_media(0, &bref, &media)?
+    } else {
+        vec![]
+    };
+    if v.is_empty() {
+        for s in get_hexdump(&media) {
+            println!("{s}");
+        }
+    } else {
+        for s in &v {
+            print!("{s}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse_check() {
+        let bref = super::parse_check("xxhash64:0x0123456789abcdef").unwrap();
+        assert_eq!(
+            libhammer2::fs::dec_check(bref.methods),
+            libhammer2::fs::HAMMER2_CHECK_XXHASH64
+        );
+        assert_eq!(
+            bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckXxhash64>()
+                .value,
+            0x0123_4567_89ab_cdef
+        );
+        let bref = super::parse_check("crc32:deadbeef").unwrap();
+        assert_eq!(
+            bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckIscsi>()
+                .value,
+            0xdead_beef
+        );
+        let bref = super::parse_check("none").unwrap();
+        assert_eq!(
+            libhammer2::fs::dec_check(bref.methods),
+            libhammer2::fs::HAMMER2_CHECK_NONE
+        );
+        assert!(super::parse_check("none:0").is_err());
+        assert!(super::parse_check("xxhash64").is_err());
+        assert!(super::parse_check("xxhash64:").is_err());
+        assert!(super::parse_check("xxhash64:xyz").is_err());
+        assert!(super::parse_check("sha192:00").is_err());
+        assert!(super::parse_check("unknown:00").is_err());
+    }
+
+    #[test]
+    fn test_get_hexdump() {
+        assert_eq!(super::get_hexdump(&[]), ["00000000"]);
+        assert_eq!(
+            super::get_hexdump(b"hello\n"),
+            [
+                "00000000  68 65 6c 6c 6f 0a                                 |hello.|",
+                "00000006"
+            ]
+        );
+        let mut buf = vec![0; 64];
+        buf[48] = b'A';
+        assert_eq!(
+            super::get_hexdump(&buf),
+            [
+                "00000000  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|",
+                "*",
+                "00000030  41 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |A...............|",
+                "00000040"
+            ]
+        );
+    }
+}
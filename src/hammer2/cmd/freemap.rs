@@ -1,9 +1,14 @@
 pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
-    let t = crate::env::init();
-    let sopt = crate::show::ShowOptions::new(t.0, t.1, t.2, t.3, t.4, 0);
+    let sopt = crate::show::ShowOptions::new(opt, 0);
 
     let mut fso = libhammer2::ondisk::init(devpath, true)?;
     let best = fso.get_best_volume_data()?[libhammer2::fs::HAMMER2_ROOT_VOLUME as usize];
+    if opt.histogram {
+        return run_histogram(&mut fso, best.0);
+    }
+    if opt.map {
+        return run_map(&mut fso, best.0, opt);
+    }
     let mut stat = Some(crate::show::FreemapStat::new());
 
     println!(
@@ -32,6 +37,7 @@ pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()>
                     &mut fso,
                     voldata,
                     sopt.init_tab,
+                    0,
                     i,
                     &broot,
                     false,
@@ -73,3 +79,483 @@ pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()>
     }
     Ok(())
 }
+
+const FREEMAP_BLOCK_SIZE: usize = 16384; // 2 bits per 16KB in bitmap
+
+// Number of bins for free run lengths, i.e. 16KB, 32KB, ..., 4MB.
+const HISTOGRAM_BINS: usize = 9;
+
+#[derive(Debug, Default)]
+struct Histogram {
+    runs: [u64; HISTOGRAM_BINS],
+    bytes: [u64; HISTOGRAM_BINS],
+    classes: std::collections::BTreeMap<u64, (u64, u64)>, // chunks, avail
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, bmdata: &libhammer2::fs::Hammer2BmapData) -> hammer2_utils::Result<()> {
+        for n in get_free_runs(&bmdata.bitmapq) {
+            let i = usize::try_from(n.ilog2())?.min(HISTOGRAM_BINS - 1);
+            self.runs[i] += 1;
+            self.bytes[i] += u64::try_from(n * FREEMAP_BLOCK_SIZE)?;
+        }
+        let v = self.classes.entry(bmdata.class.into()).or_insert((0, 0));
+        v.0 += 1;
+        v.1 += u64::from(bmdata.avail);
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for i in 0..HISTOGRAM_BINS {
+            self.runs[i] += other.runs[i];
+            self.bytes[i] += other.bytes[i];
+        }
+        for (k, x) in &other.classes {
+            let v = self.classes.entry(*k).or_insert((0, 0));
+            v.0 += x.0;
+            v.1 += x.1;
+        }
+    }
+
+    fn print(&self, tab: usize) {
+        let free = self.bytes.iter().sum::<u64>();
+        hammer2_utils::tab::println!(tab, "free {:.3}GB", free as f64 / libhammer2::subs::G_F64);
+        for i in 0..HISTOGRAM_BINS {
+            let size = FREEMAP_BLOCK_SIZE << i;
+            let size = if size >= libhammer2::subs::M {
+                format!("{}MB", size / libhammer2::subs::M)
+            } else {
+                format!("{}KB", size / libhammer2::subs::K)
+            };
+            hammer2_utils::tab::println!(
+                tab + 4,
+                "{size:<6}runs={:<8} {:10.3}MB {:5.1}%",
+                self.runs[i],
+                self.bytes[i] as f64 / libhammer2::subs::M_F64,
+                if free == 0 {
+                    0.0
+                } else {
+                    self.bytes[i] as f64 * 100.0 / free as f64
+                }
+            );
+        }
+        for (class, (chunks, avail)) in &self.classes {
+            hammer2_utils::tab::println!(
+                tab + 4,
+                "class={class:04x} chunks={chunks:<6} avail={:10.3}MB",
+                *avail as f64 / libhammer2::subs::M_F64
+            );
+        }
+    }
+}
+
+// Get lengths of contiguous free runs in 16KB blocks.  Runs never span
+// LEVEL0 chunks, as allocations don't.
+fn get_free_runs(bitmapq: &[u64]) -> Vec<usize> {
+    let mut v = vec![];
+    let mut n = 0;
+    for bm in bitmapq {
+        for j in (0..64).step_by(2) {
+            if (bm >> j) & 0x03 == 0 {
+                n += 1;
+            } else if n > 0 {
+                v.push(n);
+                n = 0;
+            }
+        }
+    }
+    if n > 0 {
+        v.push(n);
+    }
+    v
+}
+
+// Call f for each LEVEL0 chunk within the allocatable range with its
+// bitmap data, using the freemap of the given volume header.  Zones
+// without a freemap leaf are entirely free, so their chunks are passed
+// as free, except for the reserved segment at the beginning of each LEVEL1
+// zone and a partial chunk at the end, which are never allocatable.
+fn walk_leaf<F>(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    zone: usize,
+    f: &mut F,
+) -> hammer2_utils::Result<()>
+where
+    F: FnMut(u64, &libhammer2::fs::Hammer2BmapData) -> hammer2_utils::Result<()>,
+{
+    let vol = fso.get_root_volume_mut().ok_or(nix::errno::Errno::ENODEV)?;
+    let offset = libhammer2::volume::get_volume_data_offset(zone);
+    let buf = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+    let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+    let aux_end = voldata.aux_end;
+    let mut broot = libhammer2::fs::Hammer2Blockref::new(libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP);
+    broot.mirror_tid = voldata.mirror_tid;
+    broot.data_off =
+        (vol.get_offset() + offset) | u64::try_from(libhammer2::fs::HAMMER2_PBUFRADIX)?;
+    let total_size = fso.get_total_size();
+
+    let mut seen = std::collections::HashSet::new();
+    crate::walk::walk(
+        fso,
+        &broot,
+        &mut vec![],
+        &mut std::collections::HashSet::new(),
+        &mut |fso, bref, media, _| {
+            if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF {
+                return Ok(crate::walk::Walk::Continue);
+            }
+            let Some(bmdata) = hammer2_utils::media::media_as_bmap_data(media.get(fso)) else {
+                log::warn!("{:016x}: bad freemap leaf", bref.data_off);
+                return Ok(crate::walk::Walk::Skip);
+            };
+            for (i, bmdata) in bmdata.iter().enumerate() {
+                let data_off = bref
+                    .key
+                    .wrapping_add(u64::try_from(i)? * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE);
+                if data_off >= aux_end && data_off < total_size && seen.insert(data_off) {
+                    f(data_off, bmdata)?;
+                }
+            }
+            Ok(crate::walk::Walk::Skip)
+        },
+    )?;
+
+    let size = std::mem::size_of::<libhammer2::fs::Hammer2BmapData>();
+    let mut free = libhammer2::fs::media_as::<libhammer2::fs::Hammer2BmapData>(&vec![0; size]);
+    free[0].avail = libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE.try_into()?;
+    let mut data_off = aux_end.next_multiple_of(libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE);
+    while data_off + libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE <= total_size {
+        if data_off % libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE
+            >= libhammer2::fs::HAMMER2_ZONE_SEG
+            && !seen.contains(&data_off)
+        {
+            f(data_off, &free[0])?;
+        }
+        data_off += libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
+    }
+    Ok(())
+}
+
+// Print free run length histogram and allocation class breakdown,
+// per LEVEL1 zone and overall.
+fn run_histogram(fso: &mut libhammer2::ondisk::Ondisk, zone: usize) -> hammer2_utils::Result<()> {
+    println!(
+        "{}",
+        fso.get_root_volume()
+            .ok_or(nix::errno::Errno::ENODEV)?
+            .get_path()
+    );
+    let mut zones = std::collections::BTreeMap::new();
+    walk_leaf(fso, zone, &mut |data_off, bmdata| {
+        zones
+            .entry(data_off / libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE)
+            .or_insert_with(Histogram::new)
+            .add(bmdata)
+    })?;
+
+    let mut total = Histogram::new();
+    for (i, h) in &zones {
+        println!(
+            "zone {i} {:016x}",
+            i * libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE
+        );
+        h.print(4);
+        total.merge(h);
+    }
+    println!("total");
+    total.print(4);
+    Ok(())
+}
+
+// Cell states of freemap usage map, in order of precedence on ties.
+const MAP_FREE: usize = 0;
+const MAP_POSSIBLY_FREE: usize = 1;
+const MAP_ALLOCATED: usize = 2;
+const MAP_RESERVED: usize = 3;
+const MAP_STATES: usize = 4;
+
+const MAP_CHARS: [char; MAP_STATES] = ['.', ':', '#', 'R'];
+const MAP_COLORS: [&str; MAP_STATES] = ["#e0e0e0", "#ffd27f", "#4a78c2", "#707070"];
+const MAP_LABELS: [&str; MAP_STATES] = ["free", "possibly free", "allocated", "reserved"];
+
+const MAP_MAX_ROWS: usize = 64;
+const MAP_OFFSET_WIDTH: usize = 17; // "%016x "
+
+// Count 16KB blocks of each state in bitmap.
+fn count_states(bitmapq: &[u64]) -> [u64; MAP_STATES] {
+    let mut v = [0; MAP_STATES];
+    for bm in bitmapq {
+        for j in (0..64).step_by(2) {
+            match (bm >> j) & 0x03 {
+                0 => v[MAP_FREE] += 1,
+                3 => v[MAP_ALLOCATED] += 1,
+                _ => v[MAP_POSSIBLY_FREE] += 1,
+            }
+        }
+    }
+    v
+}
+
+// A cell is of the state of majority of its 16KB blocks.
+fn get_cell_state(counts: &[u64; MAP_STATES]) -> usize {
+    let mut state = MAP_FREE;
+    for (i, n) in counts.iter().enumerate() {
+        if *n > 0 && *n >= counts[state] {
+            state = i;
+        }
+    }
+    state
+}
+
+#[derive(Debug)]
+struct MapRow {
+    vol: Option<usize>, // set if the row starts a volume
+    offset: u64,
+    cells: Vec<usize>,
+}
+
+// Build rows of cells for each volume.  A cell is a LEVEL0 chunk, or a
+// LEVEL1 zone if LEVEL0 chunks don't fit in MAP_MAX_ROWS rows.  A row
+// never spans volumes.  Areas below aux_end and the reserved segment at
+// the beginning of each LEVEL1 zone are reserved.
+fn get_map_rows(
+    fso: &libhammer2::ondisk::Ondisk,
+    chunks: &std::collections::HashMap<u64, [u64; MAP_STATES]>,
+    aux_end: u64,
+    ncells: usize,
+) -> hammer2_utils::Result<(u64, Vec<MapRow>)> {
+    let total = fso.get_total_size() / libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
+    let cell_size = if total <= u64::try_from(ncells * MAP_MAX_ROWS)? {
+        libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE
+    } else {
+        libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE
+    };
+    let blocks = libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE / u64::try_from(FREEMAP_BLOCK_SIZE)?;
+
+    let mut rows = vec![];
+    for i in 0..fso.get_nvolumes() {
+        let vol = &fso[i];
+        let beg = vol.get_offset();
+        let end = beg + vol.get_size();
+        let mut offset = beg;
+        let mut row = MapRow {
+            vol: Some(i),
+            offset,
+            cells: vec![],
+        };
+        while offset < end {
+            let next = ((offset / cell_size + 1) * cell_size).min(end);
+            let mut counts = [0; MAP_STATES];
+            let mut x = offset / libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE
+                * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
+            while x < next {
+                if x < aux_end
+                    || x % libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE
+                        < libhammer2::fs::HAMMER2_ZONE_SEG
+                {
+                    counts[MAP_RESERVED] += blocks;
+                } else if let Some(v) = chunks.get(&x) {
+                    for (j, n) in v.iter().enumerate() {
+                        counts[j] += n;
+                    }
+                } else {
+                    counts[MAP_FREE] += blocks;
+                }
+                x += libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
+            }
+            if row.cells.len() == ncells {
+                rows.push(std::mem::replace(
+                    &mut row,
+                    MapRow {
+                        vol: None,
+                        offset,
+                        cells: vec![],
+                    },
+                ));
+            }
+            row.cells.push(get_cell_state(&counts));
+            offset = next;
+        }
+        rows.push(row);
+    }
+    Ok((cell_size, rows))
+}
+
+fn get_map_svg(fso: &libhammer2::ondisk::Ondisk, cell_size: &str, rows: &[MapRow]) -> String {
+    const CELL: usize = 8;
+    const LEFT: usize = 160;
+    const LINE: usize = 16;
+    let ncells = rows.iter().map(|r| r.cells.len()).max().unwrap_or(0);
+    let nvols = rows.iter().filter(|r| r.vol.is_some()).count();
+    let width = LEFT + ncells * CELL + CELL;
+    let height = (rows.len() + 1) * CELL + (nvols + 2) * LINE;
+
+    let mut v = vec![
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+            font-family=\"monospace\" font-size=\"12\">"
+        ),
+        format!("<text x=\"0\" y=\"{}\">cell={cell_size}</text>", LINE - 4),
+    ];
+    let mut y = LINE;
+    for r in rows {
+        if let Some(i) = r.vol {
+            // Mark volume boundary with a line and label.
+            y += LINE;
+            v.push(format!(
+                "<line x1=\"0\" y1=\"{y}\" x2=\"{width}\" y2=\"{y}\" stroke=\"red\"/>"
+            ));
+            v.push(format!(
+                "<text x=\"0\" y=\"{}\">vol={i} {}</text>",
+                y - 4,
+                xml_escape(fso[i].get_path())
+            ));
+        }
+        v.push(format!(
+            "<text x=\"0\" y=\"{}\">{:016x}</text>",
+            y + CELL,
+            r.offset
+        ));
+        for (j, state) in r.cells.iter().enumerate() {
+            v.push(format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                LEFT + j * CELL,
+                y + 1,
+                CELL - 1,
+                CELL - 1,
+                MAP_COLORS[*state]
+            ));
+        }
+        y += CELL;
+    }
+    y += LINE;
+    for (i, label) in MAP_LABELS.iter().enumerate() {
+        let x = i * LEFT;
+        v.push(format!(
+            "<rect x=\"{x}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            y - CELL,
+            CELL - 1,
+            CELL - 1,
+            MAP_COLORS[i]
+        ));
+        v.push(format!("<text x=\"{}\" y=\"{y}\">{label}</text>", x + LINE));
+    }
+    v.push("</svg>".to_string());
+    v.join("\n") + "\n"
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Render freemap usage as a grid of cells, and optionally write it to
+// a SVG file.
+fn run_map(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    zone: usize,
+    opt: &crate::Opt,
+) -> hammer2_utils::Result<()> {
+    let vol = fso.get_root_volume_mut().ok_or(nix::errno::Errno::ENODEV)?;
+    println!("{}", vol.get_path());
+    let offset = libhammer2::volume::get_volume_data_offset(zone);
+    let buf = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+    let aux_end = libhammer2::ondisk::media_as_volume_data(&buf).aux_end;
+
+    let mut chunks = std::collections::HashMap::new();
+    walk_leaf(fso, zone, &mut |data_off, bmdata| {
+        chunks.insert(data_off, count_states(&bmdata.bitmapq));
+        Ok(())
+    })?;
+    let w = hammer2_utils::util::get_chars_per_line().saturating_sub(MAP_OFFSET_WIDTH);
+    let ncells = (w / 16 * 16).max(16);
+    let (cell_size, rows) = get_map_rows(fso, &chunks, aux_end, ncells)?;
+    let cell_size = format!("{}MB", cell_size / u64::try_from(libhammer2::subs::M)?);
+
+    println!("cell={cell_size}");
+    for r in &rows {
+        if let Some(i) = r.vol {
+            println!("vol={i} {}", fso[i].get_path());
+        }
+        println!(
+            "{:016x} {}",
+            r.offset,
+            r.cells.iter().map(|x| MAP_CHARS[*x]).collect::<String>()
+        );
+    }
+    println!(
+        "{}",
+        MAP_CHARS
+            .iter()
+            .zip(MAP_LABELS)
+            .map(|(c, s)| format!("{c} {s}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+    );
+    if let Some(f) = &opt.svg {
+        std::fs::write(f, get_map_svg(fso, &cell_size, &rows))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_get_free_runs() {
+        assert_eq!(super::get_free_runs(&[0; 8]), [256]);
+        assert!(super::get_free_runs(&[u64::MAX; 8]).is_empty());
+        // 16KB allocated at head, and 64KB possibly free at tail
+        let mut v = [0; 8];
+        v[0] = 0x3;
+        v[7] = 0xAA << 56;
+        assert_eq!(super::get_free_runs(&v), [251]);
+        // free runs of 16KB and 32KB
+        assert_eq!(super::get_free_runs(&[!0xF0C, u64::MAX]), [1, 2]);
+        assert_eq!(super::get_free_runs(&[!0xC, !0]), [1]);
+        assert_eq!(super::get_free_runs(&[0, !0]), [32]);
+    }
+
+    #[test]
+    fn test_count_states() {
+        assert_eq!(super::count_states(&[0; 8]), [256, 0, 0, 0]);
+        assert_eq!(super::count_states(&[u64::MAX; 8]), [0, 0, 256, 0]);
+        assert_eq!(super::count_states(&[0x1B]), [29, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_get_cell_state() {
+        assert_eq!(super::get_cell_state(&[0; 4]), super::MAP_FREE);
+        assert_eq!(super::get_cell_state(&[256, 0, 0, 0]), super::MAP_FREE);
+        assert_eq!(
+            super::get_cell_state(&[100, 0, 156, 0]),
+            super::MAP_ALLOCATED
+        );
+        assert_eq!(super::get_cell_state(&[156, 0, 100, 0]), super::MAP_FREE);
+        assert_eq!(
+            super::get_cell_state(&[128, 0, 128, 0]),
+            super::MAP_ALLOCATED
+        );
+        assert_eq!(
+            super::get_cell_state(&[0, 10, 0, 0]),
+            super::MAP_POSSIBLY_FREE
+        );
+        assert_eq!(super::get_cell_state(&[0, 0, 0, 256]), super::MAP_RESERVED);
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(super::xml_escape("/dev/da0"), "/dev/da0");
+        assert_eq!(
+            super::xml_escape("<a&\"b\">"),
+            "&lt;a&amp;&quot;b&quot;&gt;"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
 
 pub(crate) fn run(args: &[&str]) -> hammer2_utils::Result<()> {
     for f in args {
@@ -13,3 +14,149 @@ pub(crate) fn run(args: &[&str]) -> hammer2_utils::Result<()> {
     }
     Ok(())
 }
+
+// Grow an unmounted filesystem into the resized last volume.
+// Besides the new reserved areas and the volume headers, only a freemap
+// leaf covering the old end needs an update, see
+// crate::volume::free_leaves().
+#[allow(clippy::too_many_lines)]
+pub(crate) fn run_offline(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
+    let mut fso = libhammer2::ondisk::init(devpath, true)?;
+    let bests = fso.get_best_volume_data()?;
+    let n = fso.get_nvolumes();
+    let root = usize::from(libhammer2::fs::HAMMER2_ROOT_VOLUME);
+
+    // Get current sizes from the best root volume header.
+    let fp = std::fs::File::open(fso[root].get_path())?;
+    let v = crate::volume::read_volume_headers(&fp, fso[root].get_size())?;
+    let voldata = libhammer2::ondisk::media_as_volume_data(&v[bests[root].0].1);
+    let old_total_size = crate::volume::get_total_size(voldata);
+
+    // Only the last volume can grow.
+    let vol = &fso[n - 1];
+    let path = vol.get_path().to_string();
+    let offset = vol.get_offset();
+    let old_size = vol.get_size();
+    if offset + old_size != old_total_size {
+        log::error!(
+            "{path}: size {old_size:016x} at {offset:016x} doesn't match \
+            total size {old_total_size:016x}"
+        );
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let new_size = libhammer2::subs::get_volume_size_from_path(&path)?
+        & !libhammer2::fs::HAMMER2_VOLUME_ALIGNMASK;
+    if new_size <= old_size {
+        println!("{path} no size change - {old_size:016x}");
+        return Ok(());
+    }
+    if n > 1 && voldata.version < libhammer2::fs::HAMMER2_VOL_VERSION_MULTI_VOLUMES {
+        log::error!("Unsupported version {}", voldata.version);
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let delta = new_size - old_size;
+    let new_total_size = old_total_size + delta;
+
+    // Newly covered level1 areas each reserve HAMMER2_ZONE_SEG.
+    let reserved_delta = crate::volume::get_reserved_size(new_total_size)
+        - crate::volume::get_reserved_size(old_total_size);
+    if delta <= reserved_delta {
+        log::error!("{path}: not enough space to grow");
+        return Err(Box::new(nix::errno::Errno::ENOSPC));
+    }
+    let free_delta = delta - reserved_delta;
+
+    println!(
+        "{path} grow {} -> {}",
+        libhammer2::subs::get_size_string(old_size),
+        libhammer2::subs::get_size_string(new_size)
+    );
+    println!("volu_size        {old_size:016x} -> {new_size:016x}");
+    println!("total_size       {old_total_size:016x} -> {new_total_size:016x}");
+    println!(
+        "allocator_size   {:016x} -> {:016x}",
+        voldata.allocator_size,
+        voldata.allocator_size + free_delta
+    );
+    println!(
+        "allocator_free   {:016x} -> {:016x}",
+        voldata.allocator_free,
+        voldata.allocator_free + free_delta
+    );
+
+    // Reserved areas start at level1 boundaries not covered yet.
+    let reserved = crate::volume::get_reserved_offsets(old_total_size, new_total_size, offset);
+    for x in &reserved {
+        println!("reserve          {:016x}", offset + x);
+    }
+    if opt.dry_run {
+        println!("Dry run, nothing written");
+        return Ok(());
+    }
+    drop(fp);
+
+    // Zero new reserved areas in the last volume.
+    let fp = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)?;
+    crate::volume::zero_reserved(&fp, &reserved, new_size)?;
+    fp.sync_all()?;
+    drop(fp);
+
+    // Release the grown range in freemap leaves.  The best root volume
+    // header gets a new freemap_blockset, which other header copies
+    // sharing the old one also get below.
+    let fset = crate::volume::get_freemap_blockset_range();
+    let old_fset = v[bests[root].0].1[fset.clone()].to_vec();
+    drop(fso);
+    let mut fso = libhammer2::ondisk::init(devpath, false)?;
+    let new_fset =
+        crate::volume::free_leaves(&mut fso, bests[root].0, old_total_size, new_total_size)?;
+
+    // Update all volume header copies.  The last volume also gets
+    // new copies for zones which didn't exist before.
+    for i in 0..n {
+        let vol = &fso[i];
+        let fp = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(vol.get_path())?;
+        let mut v = crate::volume::read_volume_headers(&fp, vol.get_size())?;
+        for (j, (offset, buf)) in v.iter_mut().enumerate() {
+            if vol.get_id() == root && buf[fset.clone()] == old_fset[..] {
+                buf[fset.clone()].copy_from_slice(&new_fset);
+            }
+            let voldata = crate::volume::media_as_volume_data_mut(buf);
+            if voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_HBO {
+                log::warn!("{}: skip header {j} at {offset:016x}", vol.get_path());
+                continue;
+            }
+            if i == n - 1 {
+                voldata.volu_size = new_size;
+            }
+            if voldata.version >= libhammer2::fs::HAMMER2_VOL_VERSION_MULTI_VOLUMES {
+                voldata.total_size = new_total_size;
+            }
+            voldata.allocator_size += free_delta;
+            if vol.get_id() == root {
+                voldata.allocator_free += free_delta;
+            }
+            hammer2_utils::volume::update_crc(voldata);
+            fp.write_all_at(buf, *offset)?;
+        }
+        if i == n - 1 {
+            let buf = &v[bests[i].0].1;
+            for j in v.len()..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+                let offset = libhammer2::volume::get_volume_data_offset(j);
+                if offset >= new_size {
+                    break;
+                }
+                fp.write_all_at(buf, offset)?;
+            }
+        }
+        fp.sync_all()?;
+    }
+    println!("{path} grown to {new_total_size:016x}");
+    Ok(())
+}
@@ -0,0 +1,190 @@
+// Timeline of inodes of a PFS grouped by modify_tid, with TIDs of
+// snapshot creation and volume header flushes as reference points.
+
+#[derive(Debug)]
+struct Inode {
+    iparent: u64,
+    name: String,
+    modify_tid: u64,
+}
+
+// Get a path of inum by following iparent up to the root (inode 1).
+// A path whose ancestor is missing starts with "?".
+fn get_path(inodes: &std::collections::HashMap<u64, Inode>, inum: u64) -> String {
+    let mut v = vec![];
+    let mut cur = inum;
+    while cur != 1 {
+        let Some(inode) = inodes.get(&cur) else {
+            v.push("?");
+            break;
+        };
+        if v.len() > inodes.len() {
+            v.push("?"); // loop
+            break;
+        }
+        v.push(&inode.name);
+        cur = inode.iparent;
+    }
+    if v.is_empty() {
+        return "/".to_string();
+    }
+    v.reverse();
+    let s = v.join("/");
+    if s.starts_with('?') {
+        s
+    } else {
+        format!("/{s}")
+    }
+}
+
+fn get_inodes(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    pfs: &crate::walk::Found,
+) -> hammer2_utils::Result<std::collections::HashMap<u64, Inode>> {
+    let mut inodes = std::collections::HashMap::new();
+    let mut parents = pfs.parents.clone();
+    crate::walk::walk(
+        fso,
+        &pfs.bref,
+        &mut parents,
+        &mut std::collections::HashSet::new(),
+        &mut |fso, bref, media, parents| {
+            if parents.len() == pfs.parents.len() {
+                return Ok(crate::walk::Walk::Continue); // PFS root
+            }
+            match bref.typ {
+                libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => Ok(crate::walk::Walk::Continue),
+                libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+                    if let Some(ipdata) = hammer2_utils::media::media_as_inode_data(media.get(fso))
+                    {
+                        let inum = ipdata.meta.inum;
+                        inodes.insert(
+                            inum,
+                            Inode {
+                                iparent: ipdata.meta.iparent,
+                                name: ipdata
+                                    .get_filename_string()
+                                    .unwrap_or_else(|_| format!("#{inum:x}")),
+                                modify_tid: bref.modify_tid,
+                            },
+                        );
+                    }
+                    Ok(crate::walk::Walk::Skip)
+                }
+                _ => Ok(crate::walk::Walk::Skip),
+            }
+        },
+    )?;
+    Ok(inodes)
+}
+
+// Get TIDs of snapshot creation of the PFS and volume header flushes.
+// Snapshots of the PFS share its pfs_clid.  Volume headers which failed
+// to be written are ignored.
+fn get_markers(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    target: &crate::walk::Found,
+) -> hammer2_utils::Result<Vec<(u64, String)>> {
+    let media = fso.read_media(&target.bref)?;
+    let Some(ipdata) = hammer2_utils::media::media_as_inode_data(&media) else {
+        log::error!("Bad PFS root inode");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    };
+    let pfs_clid = ipdata.meta.pfs_clid;
+
+    let mut v = vec![];
+    for (label, pfs) in crate::walk::get_pfs_roots(fso)? {
+        let media = fso.read_media(&pfs.bref)?;
+        let Some(ipdata) = hammer2_utils::media::media_as_inode_data(&media) else {
+            continue;
+        };
+        if ipdata.meta.pfs_subtype == libhammer2::fs::HAMMER2_PFSSUBTYPE_SNAPSHOT
+            && ipdata.meta.pfs_clid == pfs_clid
+        {
+            v.push((ipdata.meta.pfs_lsnap_tid, format!("snapshot {label}")));
+        }
+    }
+    let vol = fso.get_root_volume_mut().ok_or(nix::errno::Errno::ENODEV)?;
+    for i in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+        let offset = libhammer2::volume::get_volume_data_offset(i);
+        if offset < vol.get_size() {
+            let buf = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+            let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+            if !hammer2_utils::volume::is_valid(voldata) {
+                continue;
+            }
+            v.push((voldata.mirror_tid, format!("volume header {i}")));
+        }
+    }
+    v.sort();
+    Ok(v)
+}
+
+pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
+    let (devpath, target) = super::parse_devpath(devpath);
+    let Some((label, _)) = target else {
+        log::error!("Requires <devpath>@<label>");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    };
+    let mut fso = libhammer2::ondisk::init(devpath, true)?;
+    let pfs = crate::walk::get_pfs_root(&mut fso, label)?;
+    let inodes = get_inodes(&mut fso, &pfs)?;
+    let markers = get_markers(&mut fso, &pfs)?;
+
+    let mut tids = std::collections::BTreeMap::new();
+    for (inum, inode) in &inodes {
+        tids.entry(inode.modify_tid)
+            .or_insert_with(Vec::new)
+            .push(get_path(&inodes, *inum));
+    }
+    // Only show the last N transactions if specified.
+    let skip = opt.last.map_or(0, |n| tids.len().saturating_sub(n));
+    let beg = tids.keys().nth(skip).copied().unwrap_or(u64::MAX);
+
+    let mut markers = markers.iter().peekable();
+    for (tid, paths) in tids.iter_mut().skip(skip) {
+        while let Some((x, s)) = markers.next_if(|x| x.0 < *tid) {
+            if *x >= beg {
+                println!("---- {x:016x} {s}");
+            }
+        }
+        paths.sort();
+        println!("modify_tid {tid:016x}");
+        for s in paths {
+            println!("    {s}");
+        }
+    }
+    for (x, s) in markers {
+        if *x >= beg {
+            println!("---- {x:016x} {s}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    fn new_inode(iparent: u64, name: &str) -> super::Inode {
+        super::Inode {
+            iparent,
+            name: name.to_string(),
+            modify_tid: 0,
+        }
+    }
+
+    #[test]
+    fn test_get_path() {
+        let mut inodes = std::collections::HashMap::new();
+        inodes.insert(2, new_inode(1, "a"));
+        inodes.insert(3, new_inode(2, "b"));
+        inodes.insert(4, new_inode(9, "c"));
+        inodes.insert(5, new_inode(6, "x"));
+        inodes.insert(6, new_inode(5, "y"));
+        assert_eq!(super::get_path(&inodes, 1), "/");
+        assert_eq!(super::get_path(&inodes, 2), "/a");
+        assert_eq!(super::get_path(&inodes, 3), "/a/b");
+        assert_eq!(super::get_path(&inodes, 4), "?/c");
+        assert_eq!(super::get_path(&inodes, 7), "?");
+        assert!(super::get_path(&inodes, 5).starts_with('?'));
+    }
+}
@@ -0,0 +1,535 @@
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+
+// Image file layout (little-endian).
+//
+// [header][volume 0]...[volume n-1][record]...[terminator]
+//
+// header     magic (8), version (4), nvolumes (4), flags (8)
+// volume     id (8), offset (8), size (8)
+// record     loff (8), length (8), length bytes of media
+// terminator loff u64::MAX, length 0
+//
+// Records contain volume headers and metadata blocks only.
+// DATA blocks are not recorded and read back as zero after restore.
+const IMAGE_MAGIC: &[u8; 8] = b"H2IMAGE\0";
+const IMAGE_VERSION: u32 = 1;
+const IMAGE_FLAG_SCRAMBLED: u64 = 1;
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ImageHeader {
+    pub(crate) flags: u64,
+    pub(crate) volumes: Vec<(u64, u64, u64)>, // (id, offset, size)
+}
+
+impl ImageHeader {
+    pub(crate) fn write(&self, w: &mut impl Write) -> hammer2_utils::Result<()> {
+        w.write_all(IMAGE_MAGIC)?;
+        w.write_all(&IMAGE_VERSION.to_le_bytes())?;
+        w.write_all(&u32::try_from(self.volumes.len())?.to_le_bytes())?;
+        w.write_all(&self.flags.to_le_bytes())?;
+        for (id, offset, size) in &self.volumes {
+            w.write_all(&id.to_le_bytes())?;
+            w.write_all(&offset.to_le_bytes())?;
+            w.write_all(&size.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read(r: &mut impl Read) -> hammer2_utils::Result<Self> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != IMAGE_MAGIC {
+            log::error!("Bad image magic {magic:?}");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        let version = read_u32(r)?;
+        if version != IMAGE_VERSION {
+            log::error!("Unsupported image version {version}");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        let nvolumes = read_u32(r)?;
+        if nvolumes == 0 || nvolumes > libhammer2::fs::HAMMER2_MAX_VOLUMES.into() {
+            log::error!("Bad number of volumes {nvolumes}");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        let flags = read_u64(r)?;
+        let mut volumes = vec![];
+        for _ in 0..nvolumes {
+            volumes.push((read_u64(r)?, read_u64(r)?, read_u64(r)?));
+        }
+        Ok(Self { flags, volumes })
+    }
+}
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn write_record(w: &mut impl Write, loff: u64, buf: &[u8]) -> hammer2_utils::Result<()> {
+    w.write_all(&loff.to_le_bytes())?;
+    w.write_all(&u64::try_from(buf.len())?.to_le_bytes())?;
+    w.write_all(buf)?;
+    Ok(())
+}
+
+// Upper bits of the directory hash to be kept by scrambled names.
+const SCRAMBLE_HASH_BITS: u32 = 12;
+const SCRAMBLE_TRIES: u64 = 1 << 16;
+
+// Deterministically scramble a name into a same length name.
+// The same name always results in the same scrambled name, so that
+// inode filenames and directory entries remain consistent.
+//
+// Directory entries and name_key of inodes get the directory hash of the
+// scrambled name, so that path lookups work on a scrambled image.  Blockrefs
+// are re-sorted by the new keys, but can't move across indirect blocks,
+// so a scrambled name whose hash keeps upper SCRAMBLE_HASH_BITS bits of the
+// original hash is preferred.  Short names may not have one.
+pub(crate) fn scramble_name(name: &[u8]) -> hammer2_utils::Result<Vec<u8>> {
+    let mask = !(u64::MAX >> SCRAMBLE_HASH_BITS);
+    let hash = libhammer2::subs::dirhash(name) & mask;
+    let mut first = None;
+    for salt in 0..SCRAMBLE_TRIES {
+        let v = scramble_name_salt(name, salt)?;
+        if libhammer2::subs::dirhash(&v) & mask == hash {
+            return Ok(v);
+        }
+        if first.is_none() {
+            first = Some(v);
+        }
+    }
+    Ok(first.unwrap_or_default())
+}
+
+fn scramble_name_salt(name: &[u8], salt: u64) -> hammer2_utils::Result<Vec<u8>> {
+    const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+    let mut x = libhammer2::xxhash::xxh64(name) ^ salt.wrapping_mul(0xd6e8_feb8_6659_fd93);
+    let mut v = Vec::with_capacity(name.len());
+    for _ in 0..name.len() {
+        // splitmix64
+        x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = x;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        v.push(CHARS[usize::try_from(z % u64::try_from(CHARS.len())?)?]);
+    }
+    Ok(v)
+}
+
+// Get a directory entry key or name_key for a scrambled name.
+// Low 15 bits of the original key which resolve collisions are kept.
+fn get_scrambled_key(key: u64, name: &[u8]) -> u64 {
+    (libhammer2::subs::dirhash(name) & !0x7FFF) | (key & 0x7FFF)
+}
+
+// Sort non-empty blockrefs of media by key after keys of directory entries
+// have changed.  Empty slots are kept as is.
+fn sort_blockrefs(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: &mut [u8],
+    offsets: &[usize],
+) -> hammer2_utils::Result<()> {
+    let mut slots = vec![];
+    let mut v = vec![];
+    for x in offsets {
+        let child = crate::walk::get_blockref_at(media, *x);
+        if child.typ != libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
+            slots.push(*x);
+            v.push(child);
+        }
+    }
+    v.sort_by_key(|x| x.key);
+    let size = std::mem::size_of::<libhammer2::fs::Hammer2Blockref>();
+    for (x, child) in slots.iter().zip(&v) {
+        if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT
+            && child.typ == libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT
+            && !crate::walk::has_key(bref, child.key)
+        {
+            log::error!(
+                "{:016x}: Scrambled key {:016x} out of range",
+                bref.data_off,
+                child.key
+            );
+            return Err(Box::new(nix::errno::Errno::EOPNOTSUPP));
+        }
+        media[*x..*x + size].copy_from_slice(libfs::cast::as_u8_slice(child));
+    }
+    Ok(())
+}
+
+struct ImageContext<W: Write> {
+    out: W,
+    scramble: bool,
+    done: std::collections::HashMap<u64, Option<libhammer2::fs::Hammer2Blockref>>,
+    names: std::collections::HashMap<Vec<u8>, Vec<u8>>,
+    nblocks: u64,
+    nbytes: u64,
+}
+
+impl<W: Write> ImageContext<W> {
+    fn scramble_name(&mut self, name: &[u8]) -> hammer2_utils::Result<Vec<u8>> {
+        if let Some(v) = self.names.get(name) {
+            return Ok(v.clone());
+        }
+        let v = scramble_name(name)?;
+        self.names.insert(name.to_vec(), v.clone());
+        Ok(v)
+    }
+
+    fn record(&mut self, loff: u64, buf: &[u8]) -> hammer2_utils::Result<()> {
+        write_record(&mut self.out, loff, buf)?;
+        self.nblocks += 1;
+        self.nbytes += u64::try_from(buf.len())?;
+        Ok(())
+    }
+}
+
+// Scramble and copy child blockrefs of media, and return true if media
+// was modified.
+fn image_children<W: Write>(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: &mut [u8],
+    ctx: &mut ImageContext<W>,
+) -> hammer2_utils::Result<bool> {
+    let mut changed = false;
+    let mut rekeyed = false;
+    let offsets = crate::walk::get_blockref_offsets(bref, media);
+    for x in &offsets {
+        let child =
+            libfs::cast::align_head_to_mut::<libhammer2::fs::Hammer2Blockref>(&mut media[*x..]);
+        if child.typ == libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
+            continue;
+        }
+        if ctx.scramble && child.typ == libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT {
+            let namelen = usize::from(child.embed_as::<libhammer2::fs::Hammer2DirentHead>().namlen);
+            if namelen <= child.check.len() {
+                let name = ctx.scramble_name(&child.check[..namelen])?;
+                child.check[..namelen].copy_from_slice(&name);
+                child.key = get_scrambled_key(child.key, &name);
+                changed = true;
+                rekeyed = true;
+            }
+        }
+        let tmp = *child;
+        if let Some(v) = image_blockref(fso, &tmp, ctx)? {
+            rekeyed |= child.key != v.key;
+            child.key = v.key;
+            child.check = v.check;
+            changed = true;
+        }
+    }
+    if rekeyed {
+        sort_blockrefs(bref, media, &offsets)?;
+    }
+    Ok(changed)
+}
+
+// Copy metadata block of bref and its children, and return bref with
+// updated check code if the block was modified.
+fn image_blockref<W: Write>(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    ctx: &mut ImageContext<W>,
+) -> hammer2_utils::Result<Option<libhammer2::fs::Hammer2Blockref>> {
+    match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE
+        | libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT
+        | libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT
+        | libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE
+        | libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF => (),
+        _ => return Ok(None),
+    }
+    if crate::walk::get_radix(bref) == 0 {
+        return Ok(None);
+    }
+    if let Some(v) = ctx.done.get(&bref.data_off) {
+        return Ok(*v);
+    }
+    ctx.done.insert(bref.data_off, None);
+
+    let mut media = match crate::walk::read_raw(fso, bref) {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("{:016x}: {e}", bref.data_off);
+            return Ok(None);
+        }
+    };
+
+    // Compressed metadata is recorded as is, which can't be scrambled.
+    if crate::walk::is_compressed(bref) {
+        if ctx.scramble {
+            log::error!("{:016x}: Can't scramble compressed metadata", bref.data_off);
+            return Err(Box::new(nix::errno::Errno::EOPNOTSUPP));
+        }
+        if let Ok(v) = fso.read_media(bref) {
+            for x in &libhammer2::ondisk::media_as_blockref_safe(bref, &v) {
+                image_blockref(fso, x, ctx)?;
+            }
+        }
+        ctx.record(crate::walk::get_io_offset(bref), &media)?;
+        return Ok(None);
+    }
+
+    let mut nbref = *bref;
+    let mut changed = image_children(fso, bref, &mut media, ctx)?;
+    if ctx.scramble {
+        match bref.typ {
+            libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+                if media.len() >= std::mem::size_of::<libhammer2::fs::Hammer2InodeData>() {
+                    changed |= scramble_inode(&mut media, ctx)?;
+                }
+            }
+            libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+                let namelen =
+                    usize::from(bref.embed_as::<libhammer2::fs::Hammer2DirentHead>().namlen);
+                if namelen <= media.len() {
+                    let name = ctx.scramble_name(&media[..namelen])?;
+                    media[..namelen].copy_from_slice(&name);
+                    nbref.key = get_scrambled_key(bref.key, &name);
+                    changed = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    ctx.record(crate::walk::get_io_offset(bref), &media)?;
+    if !changed {
+        return Ok(None);
+    }
+    crate::walk::update_check(&mut nbref, &media)?;
+    ctx.done.insert(bref.data_off, Some(nbref));
+    Ok(Some(nbref))
+}
+
+// PFS labels are kept, and embedded data is zeroed.
+fn scramble_inode<W: Write>(
+    media: &mut [u8],
+    ctx: &mut ImageContext<W>,
+) -> hammer2_utils::Result<bool> {
+    let ipdata = libfs::cast::align_head_to_mut::<libhammer2::fs::Hammer2InodeData>(media);
+    let mut changed = false;
+    if !ipdata.meta.is_pfs_root() && !ipdata.meta.is_sup_root() {
+        let n = std::cmp::min(usize::from(ipdata.meta.name_len), ipdata.filename.len());
+        let name = ctx.scramble_name(&ipdata.filename[..n])?;
+        ipdata.filename[..n].copy_from_slice(&name);
+        ipdata.meta.name_key = get_scrambled_key(ipdata.meta.name_key, &name);
+        changed = true;
+    }
+    if ipdata.meta.has_direct_data() {
+        let base = std::mem::offset_of!(libhammer2::fs::Hammer2InodeData, u);
+        let size = usize::try_from(libhammer2::fs::HAMMER2_EMBEDDED_BYTES)?;
+        media[base..base + size].fill(0);
+        changed = true;
+    }
+    Ok(changed)
+}
+
+pub(crate) fn run(devpath: &str, output: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
+    let mut fso = libhammer2::ondisk::init(devpath, true)?;
+    let n = fso.get_nvolumes();
+
+    let mut hdr = ImageHeader {
+        flags: if opt.scramble {
+            IMAGE_FLAG_SCRAMBLED
+        } else {
+            0
+        },
+        ..Default::default()
+    };
+    for i in 0..n {
+        let vol = &fso[i];
+        hdr.volumes
+            .push((vol.get_id().try_into()?, vol.get_offset(), vol.get_size()));
+    }
+    let mut ctx = ImageContext {
+        out: std::io::BufWriter::new(std::fs::File::create(output)?),
+        scramble: opt.scramble,
+        done: std::collections::HashMap::new(),
+        names: std::collections::HashMap::new(),
+        nblocks: 0,
+        nbytes: 0,
+    };
+    hdr.write(&mut ctx.out)?;
+    if let Err(e) = image_volumes(&mut fso, &mut ctx) {
+        drop(ctx);
+        std::fs::remove_file(output)?;
+        return Err(e);
+    }
+    ctx.out.flush()?;
+
+    println!(
+        "{output}: {} blocks, {}{}",
+        ctx.nblocks,
+        libhammer2::subs::get_size_string(ctx.nbytes),
+        if opt.scramble { ", scrambled" } else { "" }
+    );
+    Ok(())
+}
+
+// Walk the topology and the freemap from all root volume headers,
+// and record volume headers of all volumes.
+fn image_volumes<W: Write>(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    ctx: &mut ImageContext<W>,
+) -> hammer2_utils::Result<()> {
+    let n = fso.get_nvolumes();
+    let root = usize::from(libhammer2::fs::HAMMER2_ROOT_VOLUME);
+    for i in 0..n {
+        for j in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+            let vol = &mut fso[i];
+            let offset = libhammer2::volume::get_volume_data_offset(j);
+            if offset >= vol.get_size() {
+                break;
+            }
+            let loff = vol.get_offset() + offset;
+            let mut media = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+            if i == root
+                && libhammer2::ondisk::media_as_volume_data(&media).magic
+                    == libhammer2::fs::HAMMER2_VOLUME_ID_HBO
+            {
+                let mut changed = false;
+                for typ in [
+                    libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME,
+                    libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP,
+                ] {
+                    let mut broot = libhammer2::fs::Hammer2Blockref::new(typ);
+                    broot.data_off = offset | u64::try_from(libhammer2::fs::HAMMER2_PBUFRADIX)?;
+                    changed |= image_children(fso, &broot, &mut media, ctx)?;
+                }
+                if changed {
+                    hammer2_utils::volume::update_crc(crate::volume::media_as_volume_data_mut(
+                        &mut media,
+                    ));
+                }
+            }
+            ctx.record(loff, &media)?;
+        }
+    }
+    write_record(&mut ctx.out, u64::MAX, &[])
+}
+
+fn get_restore_path(output: &str, hdr: &ImageHeader, index: usize) -> String {
+    if hdr.volumes.len() == 1 {
+        output.to_string()
+    } else {
+        format!("{output}.{index}")
+    }
+}
+
+pub(crate) fn run_restore(input: &str, output: &str) -> hammer2_utils::Result<()> {
+    let mut r = std::io::BufReader::new(std::fs::File::open(input)?);
+    let hdr = ImageHeader::read(&mut r)?;
+
+    // Create sparse files for all volumes.
+    let mut v = vec![];
+    for (i, (_, offset, size)) in hdr.volumes.iter().enumerate() {
+        let f = get_restore_path(output, &hdr, i);
+        let fp = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&f)?;
+        fp.set_len(*size)?;
+        v.push((f, fp, *offset, *size));
+    }
+
+    let mut nblocks = 0;
+    loop {
+        let loff = read_u64(&mut r)?;
+        let len = read_u64(&mut r)?;
+        if loff == u64::MAX && len == 0 {
+            break;
+        }
+        let mut buf = vec![0; len.try_into()?];
+        r.read_exact(&mut buf)?;
+        let Some((_, fp, offset, _)) = v
+            .iter()
+            .find(|(_, _, offset, size)| loff >= *offset && loff + len <= *offset + *size)
+        else {
+            log::error!("{loff:016x}/{len} out of range");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        };
+        fp.write_all_at(&buf, loff - offset)?;
+        nblocks += 1;
+    }
+
+    for (f, fp, _, size) in &v {
+        fp.sync_all()?;
+        println!("{f} {}", libhammer2::subs::get_size_string(*size));
+    }
+    println!(
+        "{nblocks} blocks restored{}",
+        if (hdr.flags & IMAGE_FLAG_SCRAMBLED) != 0 {
+            " (scrambled)"
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_image_header() {
+        let hdr = super::ImageHeader {
+            flags: super::IMAGE_FLAG_SCRAMBLED,
+            volumes: vec![(0, 0, 1 << 30), (1, 1 << 30, 1 << 29)],
+        };
+        let mut buf = vec![];
+        hdr.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 24 + 24 * 2);
+        match super::ImageHeader::read(&mut buf.as_slice()) {
+            Ok(v) => assert_eq!(v, hdr),
+            Err(e) => panic!("{e}"),
+        }
+
+        buf[0] = b'X';
+        assert!(super::ImageHeader::read(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_scramble_name() {
+        for s in ["", "a", "LOCAL", "file.txt", "some long directory name"] {
+            let v = super::scramble_name(s.as_bytes()).unwrap();
+            assert_eq!(v.len(), s.len());
+            assert_eq!(v, super::scramble_name(s.as_bytes()).unwrap());
+            assert!(v.iter().all(u8::is_ascii_alphanumeric));
+        }
+        assert_ne!(
+            super::scramble_name(b"abc").unwrap(),
+            super::scramble_name(b"abd").unwrap()
+        );
+        for s in ["file.txt", "some long directory name"] {
+            let v = super::scramble_name(s.as_bytes()).unwrap();
+            assert_ne!(v, s.as_bytes());
+            let x = libhammer2::subs::dirhash(s.as_bytes());
+            let y = libhammer2::subs::dirhash(&v);
+            assert_eq!(
+                x >> (64 - super::SCRAMBLE_HASH_BITS),
+                y >> (64 - super::SCRAMBLE_HASH_BITS)
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_scrambled_key() {
+        let key = libhammer2::subs::dirhash(b"abc") + 2;
+        let v = super::get_scrambled_key(key, b"xyz");
+        assert_eq!(v & !0x7FFF, libhammer2::subs::dirhash(b"xyz") & !0x7FFF);
+        assert_eq!(v & 0x7FFF, key & 0x7FFF);
+    }
+}
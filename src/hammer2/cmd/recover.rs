@@ -28,6 +28,11 @@ use std::io::Write;
 // The command checks ALL PFSs and snapshots.  Redundant files with the same
 // path are ignored.  You basically get everything that can possibly be
 // recovered from the media.
+//
+// Regular files can also be selected by inode number, modify_tid or mtime
+// (--inum, --modify-tid-range, --mtime-range) in addition to <path>.
+// Selected files which aren't reachable by path are recovered under
+// <destdir>/by-inum/<inum>.
 
 const HTABLE_SIZE: u64 = 4 * 1024 * 1024;
 const HTABLE_MASK: u64 = HTABLE_SIZE - 1;
@@ -35,7 +40,10 @@ const HTABLE_MASK: u64 = HTABLE_SIZE - 1;
 const MAX_RADIX_MASK: u64 = 0x1F; // not HAMMER2_OFF_MASK_RADIX (0x3F)
 
 // Rust: unique id's to substitute pointer comparison used in C
-static mut INODE_ENTRY_ID_NEXT: u64 = 0;
+// Inode entries are also created by media pass threads, hence thread local.
+thread_local! {
+    static INODE_ENTRY_ID_NEXT: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
 static mut TOPOLOGY_ENTRY_ID_NEXT: u64 = 0;
 
 #[derive(Clone, Debug, Default)]
@@ -44,27 +52,30 @@ struct InodeEntry {
     typ: u8,   // from inode meta
     inum: u64, // from bref or inode meta
     data_off: u64,
+    modify_tid: u64, // from bref
     crc: u32,
     encountered: bool, // copies limit w/REPINODEDEPTH
     loopcheck: bool,   // recursion loop check
     link_file_path: String,
+    pfs_root: bool, // Rust: unvalidated PFS root candidate
 }
 
 impl InodeEntry {
     fn init() {
-        unsafe {
-            INODE_ENTRY_ID_NEXT = 0;
-        }
+        INODE_ENTRY_ID_NEXT.with(|x| x.set(0));
+    }
+
+    fn get_next_id() -> u64 {
+        INODE_ENTRY_ID_NEXT.with(|x| {
+            let id = x.get();
+            x.set(id + 1);
+            id
+        })
     }
 
     fn new(typ: u8, inum: u64, data_off: u64, crc: u32) -> Self {
-        let id;
-        unsafe {
-            id = INODE_ENTRY_ID_NEXT;
-            INODE_ENTRY_ID_NEXT += 1;
-        }
         Self {
-            id,
+            id: Self::get_next_id(),
             typ,
             inum,
             data_off,
@@ -79,6 +90,8 @@ struct TopologyEntry {
     id: u64, // Rust
     path: String,
     iterator: usize,
+    versions: Vec<(u64, String)>, // Rust: content hash and file (--dedup)
+    deduped: std::collections::HashSet<u64>, // Rust: inode entry id's in manifest
 }
 
 impl TopologyEntry {
@@ -98,6 +111,8 @@ impl TopologyEntry {
             id,
             path: path.to_string(),
             iterator: 1,
+            versions: vec![],
+            deduped: std::collections::HashSet::new(),
         }
     }
 }
@@ -137,6 +152,57 @@ impl NegativeEntry {
     }
 }
 
+// A directory entry found by the media pass (--scan-dirents).
+#[derive(Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+struct DirentEntry {
+    inum: u64,
+    name: String,
+    typ: u8,
+}
+
+// A version of a file found by the restoration pass (--list).
+#[derive(Debug, Default)]
+struct Version {
+    path: String, // without suffix
+    iterator: usize,
+    inum: u64,
+    size: u64,
+    mtime: u64,
+    modify_tid: u64,
+    data_off: u64,
+    valid: bool,
+}
+
+// Selection of regular files other than by path (--inum,
+// --modify-tid-range, --mtime-range).  Ranges are inclusive.
+#[derive(Debug, Default)]
+struct Selection {
+    inums: Vec<u64>,
+    modify_tid_range: Option<(u64, u64)>,
+    mtime_range: Option<(u64, u64)>,
+}
+
+impl Selection {
+    fn new(opt: &crate::Opt) -> Self {
+        Self {
+            inums: opt.inums.clone(),
+            modify_tid_range: opt.modify_tid_range,
+            mtime_range: opt.mtime_range,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inums.is_empty() && self.modify_tid_range.is_none() && self.mtime_range.is_none()
+    }
+
+    fn is_selected(&self, inum: u64, modify_tid: u64, mtime: u64) -> bool {
+        let in_range = |r: Option<(u64, u64)>, x| r.is_none_or(|(beg, end)| beg <= x && x <= end);
+        (self.inums.is_empty() || self.inums.contains(&inum))
+            && in_range(self.modify_tid_range, modify_tid)
+            && in_range(self.mtime_range, mtime)
+    }
+}
+
 #[derive(Debug, Default)]
 struct Stats {
     inode: usize,
@@ -165,6 +231,55 @@ type NegativeEntryHash = std::collections::HashMap<u64, Vec<NegativeEntry>>;
 
 type InodeEntryHashId = (u64, usize);
 
+// Tables built by the media pass, either for the whole media or for
+// a range of it scanned by a thread.
+#[derive(Debug, Default)]
+struct MediaScan {
+    ihash1: InodeEntryHash,
+    ihash2: InodeEntryHash,
+    nhash: NegativeEntryHash,
+    dirents: std::collections::BTreeSet<DirentEntry>,
+    scan_dirents: bool,
+    stats: Stats,
+}
+
+impl MediaScan {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    // Merge tables of the next range.  Entries are appended in the order
+    // they were found, and duplicates of entries from preceding ranges are
+    // dropped, so that the result is identical to a single sequential scan.
+    // Inode entry id's are renumbered in that order as well.  Negative
+    // entries are merged likewise, but hit counts may differ from a single
+    // scan as threads don't share the negative cache.
+    fn merge(&mut self, other: Self) {
+        let mut v: Vec<InodeEntry> = other.ihash1.into_values().flatten().collect();
+        v.sort_by_key(|x| x.id);
+        for mut scan in v {
+            if !has_inode(&self.ihash2, scan.inum, scan.data_off) {
+                scan.id = InodeEntry::get_next_id();
+                insert_inode(&mut self.ihash1, &mut self.ihash2, scan);
+                self.stats.inode += 1;
+            }
+        }
+        for (hv, v) in other.nhash {
+            for neg in v {
+                if find_negative(&self.nhash, &mut self.stats, &neg.bref) {
+                    continue;
+                }
+                self.nhash.entry(hv).or_default().push(neg);
+                self.stats.negative += 1;
+            }
+        }
+        self.stats.negative_hits += other.stats.negative_hits;
+        self.dirents.extend(other.dirents);
+    }
+}
+
 macro_rules! get_entry {
     ($h:expr, $hid:expr) => {
         &$h.get(&$hid.0).unwrap()[$hid.1]
@@ -197,15 +312,7 @@ fn check_filename(
     if flen <= 64 {
         // Filename is embedded in bref.
         let buf = libfs::string::b2s(&bref.check[..flen])?;
-        if let Some(filename) = filename {
-            if filename[..flen] == buf {
-                Ok(Some(buf))
-            } else {
-                Ok(None)
-            }
-        } else {
-            Ok(Some(buf))
-        }
+        Ok(match_filename(filename, flen, buf))
     } else {
         // Filename requires media access.
         // bref must represent a data reference to a 1KB block or smaller.
@@ -241,15 +348,20 @@ fn check_filename(
             return Ok(None);
         }
         let buf = libfs::string::b2s(&data[..flen])?;
-        if let Some(filename) = filename {
-            if filename[..flen] == buf {
-                Ok(Some(buf))
-            } else {
-                Ok(None)
-            }
+        Ok(match_filename(filename, flen, buf))
+    }
+}
+
+// filename may be shorter than flen, or not split at a char boundary.
+fn match_filename(filename: Option<&str>, flen: usize, buf: String) -> Option<String> {
+    if let Some(filename) = filename {
+        if filename.as_bytes().get(..flen) == Some(buf.as_bytes()) {
+            Some(buf)
         } else {
-            Ok(Some(buf))
+            None
         }
+    } else {
+        Some(buf)
     }
 }
 
@@ -345,32 +457,22 @@ fn topology_check_duplicate_indirect(
 // Valid and record an inode found on media.  There can be many versions
 // of the same inode number present on the media.
 // Note: Modified DragonFly's inefficient hv2.
-#[allow(clippy::too_many_arguments)]
 fn enter_inode(
     fso: &mut libhammer2::ondisk::Ondisk,
-    ihash1: &mut InodeEntryHash,
-    ihash2: &mut InodeEntryHash,
-    nhash: &mut NegativeEntryHash,
-    stats: &mut Stats,
+    scan: &mut MediaScan,
     sdc: &mut SdcCache,
     bref: &libhammer2::fs::Hammer2Blockref,
     strict: bool,
 ) -> hammer2_utils::Result<()> {
-    let hv1 = (bref.key ^ (bref.key >> 16)) & HTABLE_MASK;
-    let hv2 = ((bref.key ^ (bref.key >> 16)) | (bref.data_off >> 10)) & HTABLE_MASK;
     // Ignore duplicate inodes, use the secondary inode hash table's
     // better spread to reduce cpu consumption (there can be many
     // copies of the same inode so the primary hash table can have
     // very long chains in it).
-    if let Some(v) = ihash2.get(&hv2) {
-        for scan in v {
-            if bref.key == scan.inum && bref.data_off == scan.data_off {
-                return Ok(());
-            }
-        }
+    if has_inode(&scan.ihash2, bref.key, bref.data_off) {
+        return Ok(());
     }
     // Ignore brefs which we have already determined to be bad.
-    if find_negative(nhash, stats, bref) {
+    if find_negative(&scan.nhash, &mut scan.stats, bref) {
         return Ok(());
     }
     // Validate the potential blockref.  Note that this might not be a
@@ -400,19 +502,19 @@ fn enter_inode(
     // should enter the bref in the negative cache to avoid unnecessary
     // guaranteed-to-fil reissuances of the same (bref, data_off) combo.
     if data.is_empty() || psize != libhammer2::fs::HAMMER2_INODE_BYTES {
-        enter_negative(nhash, stats, bref);
+        enter_negative(&mut scan.nhash, &mut scan.stats, bref);
         return Ok(());
     }
     // The blockref looks ok but the real test is whether the
     // inode data it references passes the CRC check.  If it
     // does, it is highly likely that we have a valid inode.
     if !validate_crc(bref, &data[..psize.try_into()?], strict)? {
-        enter_negative(nhash, stats, bref);
+        enter_negative(&mut scan.nhash, &mut scan.stats, bref);
         return Ok(());
     }
     let inode = libhammer2::ondisk::media_as_inode_data(data);
     if inode.meta.inum != bref.key {
-        enter_negative(nhash, stats, bref);
+        enter_negative(&mut scan.nhash, &mut scan.stats, bref);
         return Ok(());
     }
     // Record the inode.  For now we do not record the actual content
@@ -421,25 +523,15 @@ fn enter_inode(
     //
     // Instead, the inode will be re-read from media in the recovery
     // pass.
-    let scan1 = InodeEntry::new(
+    let mut entry = InodeEntry::new(
         inode.meta.typ,
         bref.key,
         bref.data_off,
         icrc32::iscsi_crc32(&data[..psize.try_into()?]),
     );
-    let scan2 = scan1.clone();
-
-    if let Some(v) = ihash1.get_mut(&hv1) {
-        v.push(scan1);
-    } else {
-        ihash1.insert(hv1, vec![scan1]);
-    }
-    if let Some(v) = ihash2.get_mut(&hv2) {
-        v.push(scan2);
-    } else {
-        ihash2.insert(hv2, vec![scan2]);
-    }
-    stats.inode += 1;
+    entry.modify_tid = bref.modify_tid;
+    insert_inode(&mut scan.ihash1, &mut scan.ihash2, entry);
+    scan.stats.inode += 1;
     Ok(())
 }
 
@@ -449,21 +541,9 @@ fn enter_inode(
 // bref we can't really validate that the content is ok.  But we need
 // these inodes as part of our path searches.
 // Note: Modified DragonFly's inefficient hv2.
-fn enter_inode_untested(
-    ihash1: &mut InodeEntryHash,
-    ihash2: &mut InodeEntryHash,
-    stats: &mut Stats,
-    inode: &libhammer2::fs::Hammer2InodeData,
-    loff: u64,
-) {
-    let hv1 = (inode.meta.inum ^ (inode.meta.inum >> 16)) & HTABLE_MASK;
-    let hv2 = ((inode.meta.inum ^ (inode.meta.inum >> 16)) | (loff >> 10)) & HTABLE_MASK;
-    if let Some(v) = ihash2.get(&hv2) {
-        for scan in v {
-            if inode.meta.inum == scan.inum && loff == scan.data_off {
-                return;
-            }
-        }
+fn enter_inode_untested(scan: &mut MediaScan, inode: &libhammer2::fs::Hammer2InodeData, loff: u64) {
+    if has_inode(&scan.ihash2, inode.meta.inum, loff) {
+        return;
     }
     // Record the inode.  For now we do not record the actual content
     // of the inode because if there are more than few million of them
@@ -471,29 +551,44 @@ fn enter_inode_untested(
     //
     // Instead, the inode will be re-read from media in the recovery
     // pass.
-    let scan1 = InodeEntry::new(
+    let mut entry = InodeEntry::new(
         inode.meta.typ,
         inode.meta.inum,
         loff,
         icrc32::iscsi_crc32(libfs::cast::as_u8_slice(inode)),
     );
-    let scan2 = scan1.clone();
+    entry.pfs_root = true;
+    insert_inode(&mut scan.ihash1, &mut scan.ihash2, entry);
+    scan.stats.inode += 1;
+}
 
-    if let Some(v) = ihash1.get_mut(&hv1) {
-        v.push(scan1);
-    } else {
-        ihash1.insert(hv1, vec![scan1]);
-    }
-    if let Some(v) = ihash2.get_mut(&hv2) {
-        v.push(scan2);
-    } else {
-        ihash2.insert(hv2, vec![scan2]);
-    }
-    stats.inode += 1;
+fn get_inode_hv1(inum: u64) -> u64 {
+    (inum ^ (inum >> 16)) & HTABLE_MASK
+}
+
+fn get_inode_hv2(inum: u64, data_off: u64) -> u64 {
+    ((inum ^ (inum >> 16)) | (data_off >> 10)) & HTABLE_MASK
+}
+
+fn has_inode(ihash2: &InodeEntryHash, inum: u64, data_off: u64) -> bool {
+    ihash2
+        .get(&get_inode_hv2(inum, data_off))
+        .is_some_and(|v| v.iter().any(|x| x.inum == inum && x.data_off == data_off))
+}
+
+fn insert_inode(ihash1: &mut InodeEntryHash, ihash2: &mut InodeEntryHash, entry: InodeEntry) {
+    ihash1
+        .entry(get_inode_hv1(entry.inum))
+        .or_default()
+        .push(entry.clone());
+    ihash2
+        .entry(get_inode_hv2(entry.inum, entry.data_off))
+        .or_default()
+        .push(entry);
 }
 
 fn find_first_inode(ihash1: &InodeEntryHash, inum: u64) -> Option<(InodeEntryHashId, usize)> {
-    let hv1 = (inum ^ (inum >> 16)) & HTABLE_MASK;
+    let hv1 = get_inode_hv1(inum);
     if let Some(v) = ihash1.get(&hv1) {
         for (i, entry) in v.iter().enumerate() {
             if entry.inum == inum {
@@ -563,6 +658,7 @@ fn dump_tree(
     tbhash: &mut TopologyBlockrefEntryHash,
     stats: &mut Stats,
     sdc: &mut SdcCache,
+    list: &mut Option<Vec<Version>>,
     hid: InodeEntryHashId,
     dest: &str,
     remain: &str,
@@ -570,24 +666,14 @@ fn dump_tree(
     path_depth: usize,
     isafile: bool,
     strict: bool,
+    dedup: bool,
+    select: &Selection,
 ) -> hammer2_utils::Result<()> {
     const REPINODEDEPTH: usize = 256;
     let iscan = get_entry!(ihash1, hid);
-    // Re-read the already-validated inode instead of saving it in
-    // memory from the media pass.  Even though we already validated
-    // it, the content may have changed if scanning live media, so
-    // check against a simple crc we recorded earlier.
-    let (data, psize) = sdc.cache_read(fso, iscan.data_off)?;
-    if psize == 0 {
-        return Ok(());
-    }
-    if data.is_empty() || psize != libhammer2::fs::HAMMER2_INODE_BYTES {
-        return Ok(());
-    }
-    if iscan.crc != icrc32::iscsi_crc32(&data[..psize.try_into()?]) {
+    let Some(inode) = read_inode(fso, sdc, iscan)? else {
         return Ok(());
-    }
-    let inode = libhammer2::ondisk::media_as_inode_data(data);
+    };
     // Try to limit potential infinite loops.
     if depth > REPINODEDEPTH && iscan.encountered {
         return Ok(());
@@ -618,8 +704,10 @@ fn dump_tree(
             // it might already exist.
             //
             // Do not do this for the destination base directory
-            // (depth 1).
-            if depth != 1 {
+            // (depth 1), or when only listing files (--list).
+            if depth != 1 && list.is_some() {
+                get_entry_mut!(ihash1, hid).encountered = true;
+            } else if depth != 1 {
                 if std::fs::metadata(dest).is_err() {
                     std::fs::create_dir(dest)?;
                     get_entry_mut!(ihash1, hid).encountered = true;
@@ -648,6 +736,7 @@ fn dump_tree(
                 tbhash,
                 stats,
                 sdc,
+                list,
                 dest,
                 remain,
                 inode
@@ -658,9 +747,11 @@ fn dump_tree(
                 path_depth + 1,
                 isafile,
                 strict,
+                dedup,
+                select,
             )?;
             // Final adjustment to directory inode.
-            if depth != 1 {
+            if depth != 1 && list.is_none() {
                 unsafe {
                     let dest = libfs::string::new_cstring!(dest)?;
                     let pdest = dest.as_ptr();
@@ -680,11 +771,31 @@ fn dump_tree(
             }
         }
         libhammer2::fs::HAMMER2_OBJTYPE_REGFILE => {
-            // If no more path to match, dump the file contents.
-            if remain.is_empty() {
+            // If no more path to match, dump the file contents,
+            // unless it's deselected by other criteria.
+            if remain.is_empty()
+                && select.is_selected(iscan.inum, iscan.modify_tid, inode.meta.mtime)
+            {
                 let topo = get_entry_mut!(thash, topo_hid);
-                let path = format!("{}.{:05}", dest, topo.iterator);
+                let iterator = topo.iterator;
+                let path = format!("{dest}.{iterator:05}");
                 topo.iterator += 1;
+                // Only validate the file with --list.
+                if let Some(list) = list {
+                    let iscan = get_entry_mut!(ihash1, hid);
+                    iscan.encountered = true;
+                    list.push(Version {
+                        path: dest.to_string(),
+                        iterator,
+                        inum: iscan.inum,
+                        size: inode.meta.size,
+                        mtime: inode.meta.mtime,
+                        modify_tid: iscan.modify_tid,
+                        data_off: iscan.data_off,
+                        valid: validate_inum_file(fso, inode, strict)?,
+                    });
+                    return Ok(());
+                }
                 let mut st = libfs::os::new_stat();
                 unsafe {
                     let path = libfs::string::new_cstring!(&*path)?;
@@ -699,7 +810,16 @@ fn dump_tree(
                     }
                 }
                 get_entry_mut!(ihash1, hid).encountered = true;
-                dump_inum_file(fso, ihash1, hid, inode, &path, strict)?;
+                let topo = get_entry_mut!(thash, topo_hid);
+                dump_inum_file(
+                    fso,
+                    ihash1,
+                    hid,
+                    inode,
+                    &path,
+                    strict,
+                    dedup.then_some(topo),
+                )?;
             }
         }
         libhammer2::fs::HAMMER2_OBJTYPE_SOFTLINK => {
@@ -729,6 +849,28 @@ fn dump_tree(
     Ok(())
 }
 
+// Re-read the already-validated inode instead of saving it in
+// memory from the media pass.  Even though we already validated
+// it, the content may have changed if scanning live media, so
+// check against a simple crc we recorded earlier.
+fn read_inode<'a>(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    sdc: &'a mut SdcCache,
+    iscan: &InodeEntry,
+) -> hammer2_utils::Result<Option<&'a libhammer2::fs::Hammer2InodeData>> {
+    let (data, psize) = sdc.cache_read(fso, iscan.data_off)?;
+    if psize == 0 {
+        return Ok(None);
+    }
+    if data.is_empty() || psize != libhammer2::fs::HAMMER2_INODE_BYTES {
+        return Ok(None);
+    }
+    if iscan.crc != icrc32::iscsi_crc32(&data[..psize.try_into()?]) {
+        return Ok(None);
+    }
+    Ok(Some(libhammer2::ondisk::media_as_inode_data(data)))
+}
+
 // Scan the directory for a match against the next component in
 // the (remain) path.
 //
@@ -743,6 +885,7 @@ fn dump_dir_data(
     tbhash: &mut TopologyBlockrefEntryHash,
     stats: &mut Stats,
     sdc: &mut SdcCache,
+    list: &mut Option<Vec<Version>>,
     dest: &str,
     remain: &str,
     base: &[&libhammer2::fs::Hammer2Blockref],
@@ -750,6 +893,8 @@ fn dump_dir_data(
     path_depth: usize,
     isafile: bool,
     strict: bool,
+    dedup: bool,
+    select: &Selection,
 ) -> hammer2_utils::Result<()> {
     // Scan the brefs associated with the directory.
     for bref in base {
@@ -795,6 +940,7 @@ fn dump_dir_data(
                     tbhash,
                     stats,
                     sdc,
+                    list,
                     dest,
                     remain,
                     &libhammer2::fs::media_as(&data),
@@ -802,6 +948,8 @@ fn dump_dir_data(
                     path_depth,
                     isafile,
                     strict,
+                    dedup,
+                    select,
                 )?;
             }
             libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
@@ -845,6 +993,7 @@ fn dump_dir_data(
                                     tbhash,
                                     stats,
                                     sdc,
+                                    list,
                                     hid,
                                     &path,
                                     &remain[flen..],
@@ -852,6 +1001,8 @@ fn dump_dir_data(
                                     path_depth,
                                     isafile,
                                     strict,
+                                    dedup,
+                                    select,
                                 )?;
                                 // Clear loop check.
                                 get_entry_mut!(ihash1, hid).loopcheck = false;
@@ -866,12 +1017,132 @@ fn dump_dir_data(
     Ok(())
 }
 
+// Recover a regular file.  If deduplicating, the recovered file is
+// removed if its content is identical to a version already recovered
+// to the same path.
+fn dump_inum_file(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    ihash1: &mut InodeEntryHash,
+    hid: InodeEntryHashId,
+    inode: &libhammer2::fs::Hammer2InodeData,
+    path1: &str,
+    strict: bool,
+    dedup: Option<&mut TopologyEntry>,
+) -> hammer2_utils::Result<bool> {
+    let res = write_inum_file(fso, ihash1, hid, inode, path1, strict)?;
+    if let Some(topo) = dedup {
+        if res {
+            dedup_inum_file(ihash1, hid, topo, inode, path1)?;
+        }
+    }
+    Ok(res)
+}
+
+// Hash of file content, which is xxh64 of xxh64 of each HAMMER2_PBUFSIZE
+// chunk, so that a large file isn't read into memory at once.
+fn get_file_hash(path: &str) -> hammer2_utils::Result<u64> {
+    let mut fp = std::fs::File::open(path)?;
+    let mut buf = vec![];
+    let mut v = vec![];
+    loop {
+        buf.clear();
+        std::io::Read::read_to_end(
+            &mut std::io::Read::take(&mut fp, libhammer2::fs::HAMMER2_PBUFSIZE),
+            &mut buf,
+        )?;
+        if buf.is_empty() {
+            break;
+        }
+        v.extend_from_slice(&libhammer2::xxhash::xxh64(&buf).to_le_bytes());
+    }
+    Ok(libhammer2::xxhash::xxh64(&v))
+}
+
+// Compare content of two files.
+fn is_file_equal(path1: &str, path2: &str) -> hammer2_utils::Result<bool> {
+    let mut fp1 = std::fs::File::open(path1)?;
+    let mut fp2 = std::fs::File::open(path2)?;
+    if fp1.metadata()?.len() != fp2.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut buf1 = vec![];
+    let mut buf2 = vec![];
+    loop {
+        buf1.clear();
+        buf2.clear();
+        std::io::Read::read_to_end(
+            &mut std::io::Read::take(&mut fp1, libhammer2::fs::HAMMER2_PBUFSIZE),
+            &mut buf1,
+        )?;
+        std::io::Read::read_to_end(
+            &mut std::io::Read::take(&mut fp2, libhammer2::fs::HAMMER2_PBUFSIZE),
+            &mut buf2,
+        )?;
+        if buf1 != buf2 {
+            return Ok(false);
+        }
+        if buf1.is_empty() {
+            return Ok(true);
+        }
+    }
+}
+
+// Remove a recovered version if it's a duplicate of a version already
+// recovered to the same path.  Content is compared when hashes match.
+// The version number is reused, and the duplicate is recorded in
+// <path>.dedup manifest next to the versions, once per inode entry.
+fn dedup_inum_file(
+    ihash1: &mut InodeEntryHash,
+    hid: InodeEntryHashId,
+    topo: &mut TopologyEntry,
+    inode: &libhammer2::fs::Hammer2InodeData,
+    path1: &str,
+) -> hammer2_utils::Result<()> {
+    let hash = get_file_hash(path1)?;
+    let mut found = None;
+    for (x, path2) in &topo.versions {
+        if *x == hash && is_file_equal(path1, path2)? {
+            found = Some(path2.clone());
+            break;
+        }
+    }
+    let Some(path2) = found else {
+        topo.versions.push((hash, path1.to_string()));
+        return Ok(());
+    };
+    unsafe {
+        let path1 = libfs::string::new_cstring!(path1)?;
+        let _ = libfs::os::chflags(path1.as_ptr(), 0);
+    }
+    std::fs::remove_file(path1)?;
+    topo.iterator -= 1;
+    // Hardlink this inode to the remaining version from now on.
+    let iscan = get_entry_mut!(ihash1, hid);
+    iscan.link_file_path.clone_from(&path2);
+    if !topo.deduped.insert(iscan.id) {
+        return Ok(());
+    }
+    let mut fp = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{}.dedup", topo.path))?;
+    writeln!(
+        fp,
+        "{} inum={:#x} data_off={:016x} mtime={}",
+        path2.rsplit('/').next().unwrap_or(&path2),
+        iscan.inum,
+        iscan.data_off,
+        libhammer2::subs::get_local_time_string(inode.meta.mtime)
+    )?;
+    Ok(())
+}
+
 // [re]create a regular file and attempt to restore the originanl perms,
 // modes, flags, times, uid, and gid if successful.
 //
 // If the data block recursion fails the file will be renamed .corrupted.
 #[allow(clippy::similar_names)]
-fn dump_inum_file(
+fn write_inum_file(
     fso: &mut libhammer2::ondisk::Ondisk,
     ihash1: &mut InodeEntryHash,
     hid: InodeEntryHashId,
@@ -920,7 +1191,7 @@ fn dump_inum_file(
         // file content, indirect blockrefs
         dump_file_data(
             fso,
-            &mut fp,
+            Some(&mut fp),
             inode.meta.size,
             &inode
                 .u_as::<libhammer2::fs::Hammer2Blockset>()
@@ -961,11 +1232,33 @@ fn dump_inum_file(
     Ok(res)
 }
 
+// Validate a regular file the same way as dump_inum_file() without
+// writing it, returns TRUE if it would be recovered without corruption.
+fn validate_inum_file(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    inode: &libhammer2::fs::Hammer2InodeData,
+    strict: bool,
+) -> hammer2_utils::Result<bool> {
+    if inode.meta.has_direct_data() {
+        return Ok(true);
+    }
+    dump_file_data(
+        fso,
+        None,
+        inode.meta.size,
+        &inode
+            .u_as::<libhammer2::fs::Hammer2Blockset>()
+            .as_blockref(),
+        strict,
+    )
+}
+
 // Dumps the data records for an inode to the target file, returns
 // TRUE on success, FALSE if corruption was detected.
+// Nothing is written if the target file is None.
 fn dump_file_data(
     fso: &mut libhammer2::ondisk::Ondisk,
-    fp: &mut std::fs::File,
+    mut fp: Option<&mut std::fs::File>,
     fsize: u64,
     base: &[&libhammer2::fs::Hammer2Blockref],
     strict: bool,
@@ -994,25 +1287,38 @@ fn dump_file_data(
         }
         match bref.typ {
             libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => {
-                dump_file_data(fso, fp, fsize, &libhammer2::fs::media_as(&data), strict)?;
+                dump_file_data(
+                    fso,
+                    fp.as_deref_mut(),
+                    fsize,
+                    &libhammer2::fs::media_as(&data),
+                    strict,
+                )?;
             }
             libhammer2::fs::HAMMER2_BREF_TYPE_DATA => 'data: {
-                let nsize = 1 << bref.keybits;
-                if nsize > libhammer2::fs::HAMMER2_PBUFSIZE {
+                let nsize = 1u64.checked_shl(bref.keybits.into()).unwrap_or(u64::MAX);
+                if nsize > libhammer2::fs::HAMMER2_PBUFSIZE || bref.key >= fsize {
                     res = false;
                     break 'data;
                 }
                 let dbuf = match libhammer2::fs::dec_comp(bref.methods) {
                     libhammer2::fs::HAMMER2_COMP_LZ4 => {
-                        libhammer2::lz4::decompress(&data, nsize.try_into()?)?
+                        libhammer2::lz4::decompress(&data, nsize.try_into()?)
                     }
                     libhammer2::fs::HAMMER2_COMP_ZLIB => {
-                        libhammer2::zlib::decompress(&data, nsize.try_into()?)?
+                        libhammer2::zlib::decompress(&data, nsize.try_into()?)
                     }
-                    _ => data, // leave in current form
+                    _ => Ok(data), // leave in current form
+                };
+                let Ok(dbuf) = dbuf else {
+                    res = false;
+                    break 'data;
+                };
+                let Some(fp) = fp.as_deref_mut() else {
+                    break 'data;
                 };
                 libfs::fs::seek_set(fp, bref.key)?;
-                if bref.key + u64::try_from(dbuf.len())? > fsize {
+                if bref.key.saturating_add(u64::try_from(dbuf.len())?) > fsize {
                     fp.write_all(&dbuf[..(fsize - bref.key).try_into()?])?;
                 } else {
                     fp.write_all(&dbuf)?;
@@ -1138,6 +1444,645 @@ impl SdcCacheEntry {
     }
 }
 
+// Directory entries found by the media pass have no parent directory to
+// validate them, so reject names which can't be a filename or don't hash
+// to the key.  Low 15 bits of the key are used to resolve collisions.
+fn is_dirent_name_valid(key: u64, name: &[u8]) -> bool {
+    !name.is_empty()
+        && name != b"."
+        && name != b".."
+        && !name.iter().any(|x| *x == b'/' || *x == 0)
+        && std::str::from_utf8(name).is_ok()
+        && (key & !0x7FFF) == (libhammer2::subs::dirhash(name) & !0x7FFF)
+}
+
+// Only filenames embedded in bref are considered.
+fn get_dirent_entry(bref: &libhammer2::fs::Hammer2Blockref) -> Option<DirentEntry> {
+    let dirent = bref.embed_as::<libhammer2::fs::Hammer2DirentHead>();
+    let namlen = usize::from(dirent.namlen);
+    if dirent.inum == 0 || namlen > bref.check.len() {
+        return None;
+    }
+    let name = &bref.check[..namlen];
+    if !is_dirent_name_valid(bref.key, name) {
+        return None;
+    }
+    Some(DirentEntry {
+        inum: dirent.inum,
+        name: std::str::from_utf8(name).ok()?.to_string(),
+        typ: dirent.typ,
+    })
+}
+
+// Dump inodes of directory entries found by the media pass, which weren't
+// reached by the restoration pass, e.g. their parent directory inode is
+// lost.  They are placed under <destdir>/lost+found/<inum>-<name>.  If a
+// path was specified, only entries whose name matches its last component
+// are dumped.
+//
+// Neither directory entries nor inodes found by the media pass record
+// which PFS they belong to, and inode numbers are only unique within
+// a PFS.  Inodes are matched on the filename stored in the inode as well
+// as the inode number and type, so that inodes of other PFSes are only
+// dumped if they also have the same filename.  Hardlinks whose name
+// differs from the inode filename are missed instead.
+#[allow(clippy::similar_names)]
+#[allow(clippy::too_many_arguments)]
+fn dump_lost_found(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    ihash1: &mut InodeEntryHash,
+    thash: &mut TopologyEntryHash,
+    tihash: &mut TopologyInodeEntryHash,
+    tbhash: &mut TopologyBlockrefEntryHash,
+    stats: &mut Stats,
+    sdc: &mut SdcCache,
+    list: &mut Option<Vec<Version>>,
+    dirents: &std::collections::BTreeSet<DirentEntry>,
+    destdir: &str,
+    pathname: &str,
+    isafile: bool,
+    strict: bool,
+    dedup: bool,
+    select: &Selection,
+) -> hammer2_utils::Result<usize> {
+    let name = pathname
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or("");
+    let lost = format!("{}/lost+found", destdir.trim_end_matches('/'));
+    let mut count = 0;
+    for dirent in dirents {
+        if !name.is_empty() && dirent.name != name {
+            continue;
+        }
+        // The target inode must have been found by the media pass.
+        let Some(((hv, start), end)) = find_first_inode(ihash1, dirent.inum) else {
+            continue;
+        };
+        let mut v = vec![];
+        for i in start..end {
+            let hid = (hv, i);
+            let iscan = get_entry!(ihash1, hid);
+            if iscan.inum != dirent.inum || iscan.typ != dirent.typ {
+                continue;
+            }
+            let Some(inode) = read_inode(fso, sdc, iscan)? else {
+                continue;
+            };
+            if inode.get_filename_string().ok().as_deref() == Some(dirent.name.as_str()) {
+                v.push(hid);
+            }
+        }
+        if v.is_empty() || v.iter().any(|hid| get_entry!(ihash1, hid).encountered) {
+            continue; // not found or already recovered
+        }
+        if count == 0 && list.is_none() && std::fs::metadata(&lost).is_err() {
+            std::fs::create_dir(&lost)?;
+        }
+        count += 1;
+        let path = format!("{lost}/{}-{}", dirent.inum, dirent.name);
+        for hid in v {
+            if !get_entry!(ihash1, hid).loopcheck {
+                get_entry_mut!(ihash1, hid).loopcheck = true;
+                dump_tree(
+                    fso, ihash1, thash, tihash, tbhash, stats, sdc, list, hid, &path, "", 2, 1,
+                    isafile, strict, dedup, select,
+                )?;
+                // Clear loop check.
+                get_entry_mut!(ihash1, hid).loopcheck = false;
+            }
+        }
+    }
+    Ok(count)
+}
+
+// Dump selected regular files which weren't reached by the preceding
+// passes, e.g. their directory entry is destroyed.  They are placed under
+// <destdir>/by-inum/<inum>.  If a path was specified, only files whose
+// inode filename matches its last component are dumped.
+#[allow(clippy::similar_names)]
+#[allow(clippy::too_many_arguments)]
+fn dump_by_inum(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    ihash1: &mut InodeEntryHash,
+    thash: &mut TopologyEntryHash,
+    tihash: &mut TopologyInodeEntryHash,
+    tbhash: &mut TopologyBlockrefEntryHash,
+    stats: &mut Stats,
+    sdc: &mut SdcCache,
+    list: &mut Option<Vec<Version>>,
+    destdir: &str,
+    pathname: &str,
+    strict: bool,
+    dedup: bool,
+    select: &Selection,
+) -> hammer2_utils::Result<usize> {
+    let name = pathname
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or("");
+    let dir = format!("{}/by-inum", destdir.trim_end_matches('/'));
+    // In the order found for reproducible version numbers.
+    let mut v = vec![];
+    for (hv, l) in ihash1.iter() {
+        for (i, iscan) in l.iter().enumerate() {
+            if iscan.typ == libhammer2::fs::HAMMER2_OBJTYPE_REGFILE
+                && !iscan.encountered
+                && (select.inums.is_empty() || select.inums.contains(&iscan.inum))
+            {
+                v.push((iscan.id, (*hv, i)));
+            }
+        }
+    }
+    v.sort_unstable();
+    let mut count = 0;
+    for (_, hid) in v {
+        let iscan = get_entry!(ihash1, hid);
+        let inum = iscan.inum;
+        let Some(inode) = read_inode(fso, sdc, iscan)? else {
+            continue;
+        };
+        if !select.is_selected(inum, iscan.modify_tid, inode.meta.mtime) {
+            continue;
+        }
+        if !name.is_empty() && inode.get_filename_string().ok().as_deref() != Some(name) {
+            continue;
+        }
+        if count == 0 && list.is_none() && std::fs::metadata(&dir).is_err() {
+            std::fs::create_dir(&dir)?;
+        }
+        count += 1;
+        dump_tree(
+            fso,
+            ihash1,
+            thash,
+            tihash,
+            tbhash,
+            stats,
+            sdc,
+            list,
+            hid,
+            &format!("{dir}/{inum}"),
+            "",
+            2,
+            1,
+            false,
+            strict,
+            dedup,
+            select,
+        )?;
+    }
+    Ok(count)
+}
+
+// Progress of the media pass shared by threads.
+#[derive(Debug, Default)]
+struct MediaProgress {
+    inodes: std::sync::atomic::AtomicUsize,
+    media_bytes: std::sync::atomic::AtomicU64,
+}
+
+impl MediaProgress {
+    fn print(&self, total_size: u64) -> hammer2_utils::Result<()> {
+        print!(
+            "{} inodes scanned, media {:6.2}/{:<3.2}G\r",
+            self.inodes.load(std::sync::atomic::Ordering::Relaxed),
+            self.media_bytes.load(std::sync::atomic::Ordering::Relaxed) as f64 / 1_000_000_000_f64,
+            total_size as f64 / 1_000_000_000_f64
+        );
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+}
+
+// Split logical offset space of volumes into ranges for the media pass.
+// A range doesn't straddle volumes and is HAMMER2_PBUFSIZE aligned within
+// a volume, so that media is read in the same chunks regardless of the
+// number of threads.  More ranges than threads balance the load.
+fn get_scan_ranges(vols: &[(u64, u64)], jobs: usize) -> Vec<(u64, u64)> {
+    let chunk = if jobs <= 1 {
+        u64::MAX
+    } else {
+        let total = vols.iter().map(|x| x.1).sum::<u64>();
+        let n = u64::try_from(jobs.saturating_mul(8)).unwrap_or(u64::MAX);
+        (total / n)
+            .next_multiple_of(libhammer2::fs::HAMMER2_PBUFSIZE)
+            .max(libhammer2::fs::HAMMER2_PBUFSIZE)
+    };
+    let mut v = vec![];
+    for &(offset, size) in vols {
+        let mut beg = 0;
+        while beg < size {
+            let end = beg.saturating_add(chunk).min(size);
+            v.push((offset + beg, offset + end));
+            beg = end;
+        }
+    }
+    v
+}
+
+// Scan logical offset range [beg, end) within a volume.
+fn scan_range(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    sdc: &mut SdcCache,
+    scan: &mut MediaScan,
+    range: (u64, u64),
+    progress: &MediaProgress,
+    strict: bool,
+    scan_dirents: bool,
+) -> hammer2_utils::Result<()> {
+    let (beg, end) = range;
+    let offset = fso
+        .get_volume(beg)
+        .ok_or(nix::errno::Errno::ENODEV)?
+        .get_offset();
+    let mut poff = beg - offset;
+    while poff < end - offset {
+        let vol = fso.get_volume_mut(beg).ok_or(nix::errno::Errno::ENODEV)?;
+        let Ok(data) = vol.preadx(libhammer2::fs::HAMMER2_PBUFSIZE, poff) else {
+            // Try to skip possible I/O error.
+            poff += libhammer2::fs::HAMMER2_PBUFSIZE;
+            continue;
+        };
+        let brefs = libhammer2::fs::media_as::<libhammer2::fs::Hammer2Blockref>(&data);
+        if brefs.len() != libhammer2::fs::HAMMER2_IND_COUNT_MAX {
+            // Short read.
+            poff += libhammer2::fs::HAMMER2_PBUFSIZE;
+            continue;
+        }
+        let ninodes = scan.stats.inode;
+        for bref in brefs {
+            // Found a possible inode.
+            match bref.typ {
+                libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+                    // Note: preliminary bref filter is inside enter_inode().
+                    enter_inode(fso, scan, sdc, bref, strict)?;
+                }
+                libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+                    // Go overboard and try to index
+                    // anything that looks like a
+                    // directory entry.  This might find
+                    // entries whos inodes are no longer
+                    // available, but will also generate
+                    // a lot of false files.
+                    // Only with --scan-dirents.
+                    if scan_dirents {
+                        if let Some(dirent) = get_dirent_entry(bref) {
+                            scan.dirents.insert(dirent);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        // Look for possible root inodes.  We generally can't
+        // find these by finding BREFs pointing to them because
+        // the BREFs often hang off the volume header.
+        //
+        // These "inodes" could be seriously corrupt, but if
+        // the bref tree is intact that is what we need to
+        // get top-level directory entries.
+        let inodes = libhammer2::fs::media_as::<libhammer2::fs::Hammer2InodeData>(&data);
+        for (i, &inode) in inodes.iter().enumerate() {
+            if inode.meta.inum == 1
+                && inode.meta.iparent == 0
+                && inode.meta.typ == libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY
+                && inode.meta.is_pfs_root()
+            {
+                enter_inode_untested(
+                    scan,
+                    inode,
+                    poff + offset + u64::try_from(i * std::mem::size_of_val(inode))?,
+                );
+            }
+        }
+        let n = u64::try_from(data.len())?;
+        poff += n;
+        progress
+            .media_bytes
+            .fetch_add(n, std::sync::atomic::Ordering::Relaxed);
+        progress.inodes.fetch_add(
+            scan.stats.inode - ninodes,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+    Ok(())
+}
+
+// Run the media pass over ranges with threads, each with its own Ondisk
+// handle and SdcCache.  Tables of each range are merged in range order.
+fn scan_media(
+    devpath: &str,
+    ranges: &[(u64, u64)],
+    total_size: u64,
+    strict: bool,
+    quiet: bool,
+    opt: &crate::Opt,
+) -> hammer2_utils::Result<MediaScan> {
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let progress = MediaProgress::default();
+    // Errors are converted to String as they are sent across threads.
+    let worker = || -> Result<Vec<(usize, MediaScan)>, String> {
+        let mut fso = libhammer2::ondisk::init(devpath, true).map_err(|e| e.to_string())?;
+        let mut sdc = SdcCache::new();
+        let mut v = vec![];
+        loop {
+            let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let Some(&range) = ranges.get(i) else {
+                break;
+            };
+            InodeEntry::init();
+            let mut scan = MediaScan::new();
+            scan_range(
+                &mut fso,
+                &mut sdc,
+                &mut scan,
+                range,
+                &progress,
+                strict,
+                opt.scan_dirents,
+            )
+            .map_err(|e| e.to_string())?;
+            v.push((i, scan));
+        }
+        Ok(v)
+    };
+    let jobs = opt.jobs.clamp(1, ranges.len().max(1));
+    let mut v = std::thread::scope(|s| -> hammer2_utils::Result<Vec<(usize, MediaScan)>> {
+        let handles: Vec<_> = (0..jobs).map(|_| s.spawn(&worker)).collect();
+        while !handles
+            .iter()
+            .all(std::thread::ScopedJoinHandle::is_finished)
+        {
+            if !quiet {
+                progress.print(total_size)?;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        let mut v = vec![];
+        for h in handles {
+            match h.join() {
+                Ok(x) => v.extend(x?),
+                Err(e) => std::panic::resume_unwind(e),
+            }
+        }
+        Ok(v)
+    })?;
+    if !quiet {
+        progress.print(total_size)?;
+    }
+    v.sort_by_key(|x| x.0);
+    let mut scan = MediaScan::new();
+    for (_, x) in v {
+        scan.merge(x);
+    }
+    scan.scan_dirents = opt.scan_dirents;
+    Ok(scan)
+}
+
+// Format versions of files found with --list, either grouped by path,
+// or one JSON object per version.
+fn get_list_strings(v: &[Version], json: bool) -> Vec<String> {
+    let mut l = vec![];
+    for (i, x) in v.iter().enumerate() {
+        if json {
+            l.push(
+                hammer2_utils::json::Object::new()
+                    .add_str("path", &x.path)
+                    .add_u64("version", u64::try_from(x.iterator).unwrap_or(u64::MAX))
+                    .add_u64("inum", x.inum)
+                    .add_u64("size", x.size)
+                    .add_u64("mtime", x.mtime)
+                    .add_hex("modify_tid", x.modify_tid)
+                    .add_hex("data_off", x.data_off)
+                    .add_bool("valid", x.valid)
+                    .to_string(),
+            );
+            continue;
+        }
+        if i == 0 || v[i - 1].path != x.path {
+            l.push(x.path.clone());
+        }
+        l.push(format!(
+            "    .{:05} {} inum={:#x} size={} modify_tid={:016x} data_off={:016x} mtime={}",
+            x.iterator,
+            if x.valid { "ok" } else { "corrupted" },
+            x.inum,
+            x.size,
+            x.modify_tid,
+            x.data_off,
+            libhammer2::subs::get_local_time_string(x.mtime)
+        ));
+    }
+    l
+}
+
+// Identity of a volume recorded in an index file, so that the index
+// isn't loaded against a different device.
+#[derive(Debug, PartialEq)]
+struct VolumeIdentity {
+    volu_id: u64,
+    offset: u64,
+    size: u64,
+    fsid: String,
+}
+
+fn get_volume_identities(
+    fso: &mut libhammer2::ondisk::Ondisk,
+) -> hammer2_utils::Result<Vec<VolumeIdentity>> {
+    let bests = fso.get_best_volume_data()?;
+    let mut v = vec![];
+    for (i, best) in bests.iter().enumerate().take(fso.get_nvolumes()) {
+        let vol = &mut fso[i];
+        let buf = vol.preadx(
+            libhammer2::fs::HAMMER2_VOLUME_BYTES,
+            libhammer2::volume::get_volume_data_offset(best.0),
+        )?;
+        let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+        v.push(VolumeIdentity {
+            volu_id: u64::from(voldata.volu_id),
+            offset: vol.get_offset(),
+            size: vol.get_size(),
+            fsid: libhammer2::subs::get_uuid_string_from_bytes(&voldata.fsid),
+        });
+    }
+    Ok(v)
+}
+
+// Index file of the media pass (--save-index, --index) is line oriented
+// text.  Inode entries are written in the order found, so that loading
+// the index results in the same tables as the media pass.  Filenames of
+// directory entries are hex encoded as they may contain any byte but
+// '/' and '\0'.  The first line is the magic followed by the version,
+// which is bumped when the format changes.  The second line records
+// whether directory entries were scanned (--scan-dirents), as an index
+// without them can't be used for the lost+found pass.
+const INDEX_MAGIC: &str = "hammer2-recover-index";
+const INDEX_VERSION: &str = "3";
+
+fn write_index<W: Write>(
+    w: &mut W,
+    volumes: &[VolumeIdentity],
+    scan: &MediaScan,
+) -> hammer2_utils::Result<()> {
+    writeln!(w, "{INDEX_MAGIC} {INDEX_VERSION}")?;
+    writeln!(w, "scan_dirents {}", u8::from(scan.scan_dirents))?;
+    for x in volumes {
+        writeln!(
+            w,
+            "volume {} {:016x} {:016x} {}",
+            x.volu_id, x.offset, x.size, x.fsid
+        )?;
+    }
+    let mut v: Vec<&InodeEntry> = scan.ihash1.values().flatten().collect();
+    v.sort_by_key(|x| x.id);
+    for x in v {
+        writeln!(
+            w,
+            "{} {} {:016x} {:016x} {:08x} {:016x}",
+            if x.pfs_root { "root" } else { "inode" },
+            x.typ,
+            x.inum,
+            x.data_off,
+            x.crc,
+            x.modify_tid
+        )?;
+    }
+    for x in &scan.dirents {
+        let mut name = String::new();
+        for b in x.name.bytes() {
+            name.push_str(&format!("{b:02x}"));
+        }
+        writeln!(w, "dirent {} {:016x} {name}", x.typ, x.inum)?;
+    }
+    Ok(())
+}
+
+fn parse_index_line(
+    scan: &mut MediaScan,
+    volumes: &mut Vec<VolumeIdentity>,
+    s: &str,
+) -> Option<()> {
+    let v: Vec<&str> = s.split(' ').collect();
+    let hex = |x: &str| u64::from_str_radix(x, 16).ok();
+    match (v[0], v.len()) {
+        ("volume", 5) => volumes.push(VolumeIdentity {
+            volu_id: v[1].parse().ok()?,
+            offset: hex(v[2])?,
+            size: hex(v[3])?,
+            fsid: v[4].to_string(),
+        }),
+        ("inode" | "root", 6) => {
+            let mut entry = InodeEntry::new(
+                v[1].parse().ok()?,
+                hex(v[2])?,
+                hex(v[3])?,
+                u32::from_str_radix(v[4], 16).ok()?,
+            );
+            entry.modify_tid = hex(v[5])?;
+            entry.pfs_root = v[0] == "root";
+            if has_inode(&scan.ihash2, entry.inum, entry.data_off) {
+                return None;
+            }
+            insert_inode(&mut scan.ihash1, &mut scan.ihash2, entry);
+            scan.stats.inode += 1;
+        }
+        ("dirent", 4) => {
+            let name = v[3].as_bytes();
+            if name.len() % 2 != 0 {
+                return None;
+            }
+            let mut buf = vec![];
+            for x in name.chunks(2) {
+                buf.push(u8::from_str_radix(std::str::from_utf8(x).ok()?, 16).ok()?);
+            }
+            scan.dirents.insert(DirentEntry {
+                inum: hex(v[2])?,
+                name: String::from_utf8(buf).ok()?,
+                typ: v[1].parse().ok()?,
+            });
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+fn read_index<R: std::io::BufRead>(
+    r: R,
+) -> hammer2_utils::Result<(Vec<VolumeIdentity>, MediaScan)> {
+    let mut volumes = vec![];
+    let mut scan = MediaScan::new();
+    for (i, s) in r.lines().enumerate() {
+        let s = s?;
+        let valid = if i == 0 {
+            match s.split_once(' ') {
+                Some((INDEX_MAGIC, INDEX_VERSION)) => true,
+                Some((INDEX_MAGIC, v)) => {
+                    log::error!(
+                        "Unsupported index version {v}, expected {INDEX_VERSION}, \
+                        save the index again with --save-index"
+                    );
+                    return Err(Box::new(nix::errno::Errno::EINVAL));
+                }
+                _ => false,
+            }
+        } else if i == 1 {
+            match s.split_once(' ') {
+                Some(("scan_dirents", "0")) => true,
+                Some(("scan_dirents", "1")) => {
+                    scan.scan_dirents = true;
+                    true
+                }
+                _ => false,
+            }
+        } else {
+            parse_index_line(&mut scan, &mut volumes, &s).is_some()
+        };
+        if !valid {
+            log::error!("Invalid index line {}: {s}", i + 1);
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+    }
+    if volumes.is_empty() {
+        log::error!("No volumes in index");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    Ok((volumes, scan))
+}
+
+fn save_index(f: &str, volumes: &[VolumeIdentity], scan: &MediaScan) -> hammer2_utils::Result<()> {
+    let mut w = std::io::BufWriter::new(std::fs::File::create(f)?);
+    write_index(&mut w, volumes, scan)?;
+    w.flush()?;
+    Ok(())
+}
+
+fn load_index(
+    f: &str,
+    volumes: &[VolumeIdentity],
+    scan_dirents: bool,
+) -> hammer2_utils::Result<MediaScan> {
+    let (v, mut scan) = read_index(std::io::BufReader::new(std::fs::File::open(f)?))?;
+    if v != volumes {
+        log::error!("{f}: index doesn't match the device");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    if scan_dirents && !scan.scan_dirents {
+        log::error!(
+            "{f}: index was saved without --scan-dirents, \
+            save the index again with --scan-dirents"
+        );
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    } else if !scan_dirents && scan.scan_dirents {
+        log::warn!("{f}: index was saved with --scan-dirents, directory entries are ignored");
+        scan.dirents.clear();
+        scan.scan_dirents = false;
+    }
+    Ok(scan)
+}
+
 // Recover the specified file.
 //
 // Basically do a raw scan of the drive image looking for directory entries
@@ -1156,8 +2101,6 @@ pub(crate) fn run(
     isafile: bool,
     opt: &crate::Opt,
 ) -> hammer2_utils::Result<()> {
-    const INODES_PER_BLOCK: usize =
-        (libhammer2::fs::HAMMER2_PBUFSIZE / libhammer2::fs::HAMMER2_INODE_BYTES) as usize;
     const DISPMODULO: u64 = HTABLE_SIZE / 32768;
 
     if !std::fs::metadata(destdir)?.file_type().is_dir() {
@@ -1166,14 +2109,16 @@ pub(crate) fn run(
     InodeEntry::init();
     TopologyEntry::init();
     let mut fso = libhammer2::ondisk::init(devpath, true)?;
-    let mut ihash1 = InodeEntryHash::new();
-    let mut ihash2 = InodeEntryHash::new();
     let mut thash = TopologyEntryHash::new();
     let mut tihash = TopologyInodeEntryHash::new();
     let mut tbhash = TopologyBlockrefEntryHash::new();
-    let mut nhash = NegativeEntryHash::new();
-    let mut stats = Stats::new();
     let mut sdc = SdcCache::new();
+    // Versions of files are collected instead of written with --list,
+    // and only they are printed with --list --json.
+    let mut list = if opt.list { Some(vec![]) } else { None };
+    let status = !(opt.list && opt.json);
+    let quiet = opt.quiet || !status;
+    let select = Selection::new(opt);
 
     // Media Pass
     //
@@ -1190,97 +2135,43 @@ pub(crate) fn run(
     // At the moment we do not try to enter unvalidated directory
     // entries, since this will result in a massive number of false
     // hits.
-    println!("MEDIA PASS");
-    let mut media_bytes = 0;
-    let mut loff = 0;
-    while let Some(vol) = fso.get_volume(loff) {
-        let offset = vol.get_offset();
-        let size = vol.get_size();
-        let next_loff = offset + size;
-        let mut poff = loff - offset;
-        let mut xdisp = 0;
-        while poff < size {
-            let vol = fso.get_volume_mut(loff).ok_or(nix::errno::Errno::ENODEV)?;
-            let Ok(data) = vol.preadx(libhammer2::fs::HAMMER2_PBUFSIZE, poff) else {
-                // Try to skip possible I/O error.
-                poff += libhammer2::fs::HAMMER2_PBUFSIZE;
-                continue;
-            };
-            let brefs = libhammer2::fs::media_as::<libhammer2::fs::Hammer2Blockref>(&data);
-            assert_eq!(brefs.len(), libhammer2::fs::HAMMER2_IND_COUNT_MAX);
-            for bref in brefs {
-                // Found a possible inode.
-                match bref.typ {
-                    libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
-                        // Note: preliminary bref filter is inside enter_inode().
-                        enter_inode(
-                            &mut fso,
-                            &mut ihash1,
-                            &mut ihash2,
-                            &mut nhash,
-                            &mut stats,
-                            &mut sdc,
-                            bref,
-                            strict,
-                        )?;
-                    }
-                    libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
-                        // Go overboard and try to index
-                        // anything that looks like a
-                        // directory entry.  This might find
-                        // entries whos inodes are no longer
-                        // available, but will also generate
-                        // a lot of false files.
-                        // (Not implemented)
-                    }
-                    _ => (),
-                }
-            }
-            // Look for possible root inodes.  We generally can't
-            // find these by finding BREFs pointing to them because
-            // the BREFs often hang off the volume header.
-            //
-            // These "inodes" could be seriously corrupt, but if
-            // the bref tree is intact that is what we need to
-            // get top-level directory entries.
-            let inodes = libhammer2::fs::media_as::<libhammer2::fs::Hammer2InodeData>(&data);
-            assert_eq!(inodes.len(), INODES_PER_BLOCK);
-            for (i, &inode) in inodes.iter().enumerate() {
-                if inode.meta.inum == 1
-                    && inode.meta.iparent == 0
-                    && inode.meta.typ == libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY
-                    && inode.meta.is_pfs_root()
-                {
-                    enter_inode_untested(
-                        &mut ihash1,
-                        &mut ihash2,
-                        &mut stats,
-                        inode,
-                        poff + offset + u64::try_from(i * std::mem::size_of_val(inode))?,
-                    );
-                }
-            }
-            let n = u64::try_from(data.len())?;
-            poff += n;
-            media_bytes += n;
-            // Update progress
-            if !opt.quiet {
-                let vol = fso.get_volume(loff).ok_or(nix::errno::Errno::ENODEV)?;
-                xdisp += 1;
-                if xdisp == DISPMODULO || poff == vol.get_size() - n {
-                    xdisp = 0;
-                    print!(
-                        "{} inodes scanned, media {:6.2}/{:<3.2}G\r",
-                        stats.inode,
-                        media_bytes as f64 / 1_000_000_000_f64,
-                        fso.get_total_size() as f64 / 1_000_000_000_f64
-                    );
-                    std::io::stdout().flush()?;
-                }
-            }
+    // The media is split into ranges scanned by threads (-j).
+    //
+    // The media pass is skipped if an index saved by a previous run
+    // is specified (--index).
+    let volumes = if opt.index.is_some() || opt.save_index.is_some() {
+        get_volume_identities(&mut fso)?
+    } else {
+        vec![]
+    };
+    let scan = if let Some(f) = &opt.index {
+        if status {
+            println!("MEDIA PASS (index {f})");
+        }
+        load_index(f, &volumes, opt.scan_dirents)?
+    } else {
+        if status {
+            println!("MEDIA PASS");
         }
-        loff = next_loff;
+        let mut vols = vec![];
+        let mut loff = 0;
+        while let Some(vol) = fso.get_volume(loff) {
+            vols.push((vol.get_offset(), vol.get_size()));
+            loff = vol.get_offset() + vol.get_size();
+        }
+        let ranges = get_scan_ranges(&vols, opt.jobs);
+        scan_media(devpath, &ranges, fso.get_total_size(), strict, quiet, opt)?
+    };
+    if let Some(f) = &opt.save_index {
+        save_index(f, &volumes, &scan)?;
     }
+    let MediaScan {
+        mut ihash1,
+        ihash2,
+        nhash,
+        dirents,
+        mut stats,
+    } = scan;
 
     // Restoration Pass
     //
@@ -1301,11 +2192,13 @@ pub(crate) fn run(
     // often hang off of the volume header and might not have COW'd
     // references to them, so we use the "iparent" field in the inode
     // to detect top-level directories under those roots.
-    println!(
-        "\nInodes={}, Invalid_brefs={}, Invalid_hits={}",
-        stats.inode, stats.negative, stats.negative_hits
-    );
-    println!("RESTORATION PASS");
+    if status {
+        println!(
+            "\nInodes={}, Invalid_brefs={}, Invalid_hits={}",
+            stats.inode, stats.negative, stats.negative_hits
+        );
+        println!("RESTORATION PASS");
+    }
     // Check for absolute path, else relative.
     let (pathname, abspath) = if pathname.starts_with('/') {
         (pathname.trim_start_matches('/'), true)
@@ -1341,7 +2234,7 @@ pub(crate) fn run(
                 }
                 // Progress down root inodes can be slow,
                 // so print progress for each root inode.
-                if i == 1 && iscan.inum == 1 && !opt.quiet {
+                if i == 1 && iscan.inum == 1 && !quiet {
                     root_count += 1;
                     print!(
                         "scan roots {:#x} {:#018x} (count {}/{})\r",
@@ -1361,6 +2254,7 @@ pub(crate) fn run(
                     &mut tbhash,
                     &mut stats,
                     &mut sdc,
+                    &mut list,
                     (i, j),
                     destdir,
                     pathname,
@@ -1368,10 +2262,12 @@ pub(crate) fn run(
                     1,
                     isafile,
                     strict,
+                    opt.dedup,
+                    &select,
                 )?;
             }
         }
-        if !opt.quiet && (i & (DISPMODULO - 1)) == DISPMODULO - 1 {
+        if !quiet && (i & (DISPMODULO - 1)) == DISPMODULO - 1 {
             if i == DISPMODULO - 1 {
                 println!();
             }
@@ -1379,7 +2275,69 @@ pub(crate) fn run(
             std::io::stdout().flush()?;
         }
     }
-    println!();
+    if status {
+        println!();
+    }
+    if opt.scan_dirents {
+        if status {
+            println!("LOST+FOUND PASS");
+        }
+        let count = dump_lost_found(
+            &mut fso,
+            &mut ihash1,
+            &mut thash,
+            &mut tihash,
+            &mut tbhash,
+            &mut stats,
+            &mut sdc,
+            &mut list,
+            &dirents,
+            destdir,
+            pathname,
+            isafile,
+            strict,
+            opt.dedup,
+            &select,
+        )?;
+        if status {
+            println!("Dirents={}, Lost+found={count}", dirents.len());
+        }
+    }
+    if !select.is_empty() {
+        if status {
+            println!("BY-INUM PASS");
+        }
+        let count = dump_by_inum(
+            &mut fso,
+            &mut ihash1,
+            &mut thash,
+            &mut tihash,
+            &mut tbhash,
+            &mut stats,
+            &mut sdc,
+            &mut list,
+            destdir,
+            pathname,
+            strict,
+            opt.dedup,
+            &select,
+        )?;
+        if status {
+            println!("By-inum={count}");
+        }
+    }
+    if let Some(v) = &mut list {
+        if status {
+            println!("LIST");
+        }
+        v.sort_by(|a, b| (&a.path, a.iterator).cmp(&(&b.path, b.iterator)));
+        for s in get_list_strings(v, opt.json) {
+            println!("{s}");
+        }
+    }
+    if !status {
+        return Ok(());
+    }
     println!("CLEANUP");
     println!(
         "TopoBRef stats: count={} dups={}",
@@ -1438,6 +2396,19 @@ mod tests {
         assert_eq!("//path//to//x".trim_start_matches('/'), "path//to//x");
     }
 
+    #[test]
+    fn test_match_filename() {
+        let buf = || "abc".to_string();
+        assert_eq!(super::match_filename(None, 3, buf()), Some(buf()));
+        assert_eq!(super::match_filename(Some("abc"), 3, buf()), Some(buf()));
+        assert_eq!(super::match_filename(Some("abc/x"), 3, buf()), Some(buf()));
+        assert_eq!(super::match_filename(Some("abd"), 3, buf()), None);
+        // filename shorter than flen
+        assert_eq!(super::match_filename(Some("ab"), 3, buf()), None);
+        // flen not at a char boundary
+        assert_eq!(super::match_filename(Some("ab\u{e9}"), 3, buf()), None);
+    }
+
     #[test]
     fn test_inode_entry_id() {
         super::InodeEntry::init();
@@ -1455,6 +2426,265 @@ mod tests {
         assert_eq!(super::InodeEntry::new(0, 0, 0, 0).id, 0);
     }
 
+    #[test]
+    fn test_get_scan_ranges() {
+        let pbufsize = libhammer2::fs::HAMMER2_PBUFSIZE;
+        let vols = [(0, pbufsize * 10), (pbufsize * 10, pbufsize * 5 + 1)];
+        assert_eq!(
+            super::get_scan_ranges(&vols, 1),
+            [(0, pbufsize * 10), (pbufsize * 10, pbufsize * 15 + 1)]
+        );
+        for jobs in [2, 3, 64] {
+            let v = super::get_scan_ranges(&vols, jobs);
+            assert!(v.len() >= jobs.min(15), "{v:?}");
+            assert_eq!(v[0].0, 0);
+            assert_eq!(v[v.len() - 1].1, pbufsize * 15 + 1);
+            for (i, x) in v.iter().enumerate() {
+                assert!(x.0 < x.1, "{v:?}");
+                assert_eq!(x.0 % pbufsize, 0, "{v:?}");
+                if i > 0 {
+                    assert_eq!(v[i - 1].1, x.0, "{v:?}");
+                }
+                // don't straddle volumes
+                assert!(x.1 <= pbufsize * 10 || x.0 >= pbufsize * 10, "{v:?}");
+            }
+        }
+        assert!(super::get_scan_ranges(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn test_media_scan_merge() {
+        let new_scan = |v: &[(u64, u64)]| {
+            super::InodeEntry::init();
+            let mut scan = super::MediaScan::new();
+            for &(inum, data_off) in v {
+                let entry = super::InodeEntry::new(0, inum, data_off, 0);
+                super::insert_inode(&mut scan.ihash1, &mut scan.ihash2, entry);
+                scan.stats.inode += 1;
+            }
+            scan
+        };
+        let a = new_scan(&[(2, 0x400a), (3, 0x800a)]);
+        let b = new_scan(&[(3, 0x800a), (2, 0xc00a), (1, 0x10000)]);
+        super::InodeEntry::init();
+        let mut scan = super::MediaScan::new();
+        scan.merge(a);
+        scan.merge(b);
+        assert_eq!(scan.stats.inode, 4);
+        let mut v: Vec<_> = scan
+            .ihash1
+            .values()
+            .flatten()
+            .map(|x| (x.id, x.inum, x.data_off))
+            .collect();
+        v.sort_unstable();
+        assert_eq!(
+            v,
+            [
+                (0, 2, 0x400a),
+                (1, 3, 0x800a),
+                (2, 2, 0xc00a),
+                (3, 1, 0x10000)
+            ]
+        );
+        // entries of the same inode in the order found
+        let (hid, n) = super::find_first_inode(&scan.ihash1, 2).unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(scan.ihash1[&hid.0][hid.1].data_off, 0x400a);
+        assert_eq!(scan.ihash2.values().map(Vec::len).sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_index() {
+        super::InodeEntry::init();
+        let mut scan = super::MediaScan::new();
+        for (inum, data_off) in [(2, 0x400a), (1, 0x10000), (2, 0xc00a)] {
+            let mut entry = super::InodeEntry::new(1, inum, data_off, 0x1234_5678);
+            entry.modify_tid = data_off >> 10;
+            entry.pfs_root = inum == 1;
+            super::insert_inode(&mut scan.ihash1, &mut scan.ihash2, entry);
+        }
+        scan.dirents.insert(super::DirentEntry {
+            inum: 2,
+            name: "a b\n".to_string(),
+            typ: 1,
+        });
+        scan.scan_dirents = true;
+        let volumes = vec![super::VolumeIdentity {
+            volu_id: 0,
+            offset: 0,
+            size: 0x4000_0000,
+            fsid: "01234567-89ab-cdef-0123-456789abcdef".to_string(),
+        }];
+        let mut buf = vec![];
+        super::write_index(&mut buf, &volumes, &scan).unwrap();
+
+        super::InodeEntry::init();
+        let (v, x) = super::read_index(buf.as_slice()).unwrap();
+        assert_eq!(v, volumes);
+        assert_eq!(x.stats.inode, 3);
+        assert_eq!(x.dirents, scan.dirents);
+        assert!(x.scan_dirents);
+        let mut v: Vec<_> = x
+            .ihash1
+            .values()
+            .flatten()
+            .map(|x| (x.id, x.inum, x.data_off, x.crc, x.modify_tid, x.pfs_root))
+            .collect();
+        v.sort_unstable();
+        assert_eq!(
+            v,
+            [
+                (0, 2, 0x400a, 0x1234_5678, 0x10, false),
+                (1, 1, 0x10000, 0x1234_5678, 0x40, true),
+                (2, 2, 0xc00a, 0x1234_5678, 0x30, false)
+            ]
+        );
+        assert_eq!(x.ihash2.values().map(Vec::len).sum::<usize>(), 3);
+
+        assert!(super::read_index(&b""[..]).is_err());
+        assert!(super::read_index(&b"hammer2-recover-index 2\n"[..]).is_err());
+        assert!(super::read_index(&b"hammer2-recover-index\n"[..]).is_err());
+        let s = String::from_utf8(buf).unwrap();
+        assert!(super::read_index(s.replacen("scan_dirents 1\n", "", 1).as_bytes()).is_err());
+        assert!(super::read_index(
+            s.replacen("scan_dirents 1\n", "scan_dirents 2\n", 1)
+                .as_bytes()
+        )
+        .is_err());
+        let (_, x) = super::read_index(
+            s.replacen("scan_dirents 1\n", "scan_dirents 0\n", 1)
+                .as_bytes(),
+        )
+        .unwrap();
+        assert!(!x.scan_dirents);
+        assert!(super::read_index(format!("{s}inode 1 2\n").as_bytes()).is_err());
+        assert!(super::read_index(format!("{s}inode 1 2 3 4 5\n").as_bytes()).is_err());
+        assert!(super::read_index(format!("{s}dirent 1 2 6\n").as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_get_list_strings() {
+        let new_version = |path: &str, iterator, valid| super::Version {
+            path: path.to_string(),
+            iterator,
+            inum: 2,
+            size: 3,
+            modify_tid: 0x10,
+            data_off: 0x400a,
+            valid,
+            ..Default::default()
+        };
+        let v = [
+            new_version("/d/a", 1, true),
+            new_version("/d/a", 2, false),
+            new_version("/d/b", 1, true),
+        ];
+        let l = super::get_list_strings(&v, false);
+        assert_eq!(l.len(), 5);
+        assert_eq!(l[0], "/d/a");
+        assert!(
+            l[1].starts_with("    .00001 ok inum=0x2 size=3 "),
+            "{}",
+            l[1]
+        );
+        assert!(l[2].starts_with("    .00002 corrupted "), "{}", l[2]);
+        assert_eq!(l[3], "/d/b");
+        let l = super::get_list_strings(&v, true);
+        assert_eq!(l.len(), 3);
+        assert_eq!(
+            l[1],
+            "{\"path\":\"/d/a\",\"version\":2,\"inum\":2,\"size\":3,\"mtime\":0,\
+            \"modify_tid\":\"0x0000000000000010\",\"data_off\":\"0x000000000000400a\",\
+            \"valid\":false}"
+        );
+    }
+
+    // Same as TempDir of integration tests.
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let d =
+                std::env::temp_dir().join(format!("hammer2-utils-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&d);
+            std::fs::create_dir_all(&d).unwrap();
+            Self(d)
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_get_file_hash() {
+        let d = TempDir::new("hash");
+        let pbufsize = usize::try_from(libhammer2::fs::HAMMER2_PBUFSIZE).unwrap();
+        let mut hashes = vec![];
+        for (name, buf) in [
+            ("a", vec![]),
+            ("b", vec![1; pbufsize * 2 + 1]),
+            ("c", vec![1; pbufsize * 2 + 1]),
+            ("d", vec![1; pbufsize * 2]),
+            ("e", vec![2; pbufsize * 2 + 1]),
+        ] {
+            let f = d.path(name);
+            std::fs::write(&f, buf).unwrap();
+            hashes.push(super::get_file_hash(&f).unwrap());
+        }
+        assert_eq!(hashes[1], hashes[2]);
+        assert_ne!(hashes[0], hashes[1]);
+        assert_ne!(hashes[1], hashes[3]);
+        assert_ne!(hashes[1], hashes[4]);
+        assert!(super::get_file_hash("/nonexistent").is_err());
+
+        assert!(super::is_file_equal(&d.path("b"), &d.path("c")).unwrap());
+        assert!(!super::is_file_equal(&d.path("b"), &d.path("d")).unwrap());
+        assert!(!super::is_file_equal(&d.path("b"), &d.path("e")).unwrap());
+        assert!(super::is_file_equal(&d.path("a"), &d.path("a")).unwrap());
+        assert!(super::is_file_equal(&d.path("a"), "/nonexistent").is_err());
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut select = super::Selection::default();
+        assert!(select.is_empty());
+        assert!(select.is_selected(2, 0, 0));
+        select.inums = vec![2, 3];
+        assert!(!select.is_empty());
+        assert!(select.is_selected(3, 0, 0));
+        assert!(!select.is_selected(4, 0, 0));
+        select.modify_tid_range = Some((0x10, 0x20));
+        assert!(select.is_selected(2, 0x10, 0));
+        assert!(select.is_selected(2, 0x20, 0));
+        assert!(!select.is_selected(2, 0x21, 0));
+        select.mtime_range = Some((100, u64::MAX));
+        assert!(select.is_selected(2, 0x10, 100));
+        assert!(!select.is_selected(2, 0x10, 99));
+        select.inums.clear();
+        assert!(select.is_selected(4, 0x10, 100));
+    }
+
+    #[test]
+    fn test_is_dirent_name_valid() {
+        let key = libhammer2::subs::dirhash(b"abc");
+        assert!(super::is_dirent_name_valid(key, b"abc"));
+        assert!(super::is_dirent_name_valid(key | 0x7FFF, b"abc"));
+        assert!(!super::is_dirent_name_valid(key ^ 0x8000, b"abc"));
+        assert!(!super::is_dirent_name_valid(key, b"abd"));
+        for name in [&b""[..], b".", b"..", b"a/b", b"a\0b", b"\xff"] {
+            let key = libhammer2::subs::dirhash(name);
+            assert!(!super::is_dirent_name_valid(key, name), "{name:?}");
+        }
+    }
+
     #[test]
     fn test_topology_entry_id() {
         super::TopologyEntry::init();
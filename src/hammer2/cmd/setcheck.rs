@@ -1,6 +1,6 @@
 use std::os::fd::AsRawFd;
 
-fn parse_check_algo(s: &str) -> hammer2_utils::Result<u8> {
+pub(crate) fn parse_check_algo(s: &str) -> hammer2_utils::Result<u8> {
     if let Ok(v) = s.parse::<u8>() {
         Ok(v)
     } else {
@@ -1,9 +1,24 @@
 pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
-    let t = crate::env::init();
-    let sopt = crate::show::ShowOptions::new(t.0, t.1, t.2, t.3, t.4, 0);
+    let sopt = crate::show::ShowOptions::new(opt, 0);
+    let (devpath, target) = super::parse_devpath(devpath);
+    if opt.inums.len() > 1 {
+        log::error!("Only one inode number allowed");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let inum = opt.inums.first().copied();
 
     let mut fso = libhammer2::ondisk::init(devpath, true)?;
     let best = fso.get_best_volume_data()?[libhammer2::fs::HAMMER2_ROOT_VOLUME as usize];
+    if target.is_some() || inum.is_some() {
+        let found = find_inode(&mut fso, target, inum)?;
+        return run_inode(&mut fso, best.0, &found.bref, &sopt, opt);
+    }
+    if opt.json {
+        return run_json(&mut fso, best.0, &sopt, opt);
+    }
+    if opt.dot {
+        return run_dot(&mut fso, best.0, &sopt, opt);
+    }
 
     println!(
         "{}",
@@ -31,6 +46,7 @@ pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()>
                     &mut fso,
                     voldata,
                     sopt.init_tab,
+                    0,
                     i,
                     &broot,
                     false,
@@ -46,3 +62,141 @@ pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()>
     }
     Ok(())
 }
+
+// Find an inode by PFS label and path, or by inode number within
+// the PFS if specified, otherwise within any PFS.
+fn find_inode(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    target: Option<(&str, &str)>,
+    inum: Option<u64>,
+) -> hammer2_utils::Result<crate::walk::Found> {
+    let found = match (target, inum) {
+        (Some((label, path)), None) => {
+            let pfs = crate::walk::get_pfs_root(fso, label)?;
+            crate::walk::lookup_path(fso, &pfs, path)?
+        }
+        (Some((label, path)), Some(inum)) => {
+            if !path.is_empty() {
+                log::error!("Can't specify both path and inode number");
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            }
+            let pfs = crate::walk::get_pfs_root(fso, label)?;
+            crate::walk::lookup_inum(fso, &pfs, inum)?
+        }
+        (None, Some(inum)) => {
+            let mut found = None;
+            for (_, pfs) in &crate::walk::get_pfs_roots(fso)? {
+                found = crate::walk::lookup_inum(fso, pfs, inum)?;
+                if found.is_some() {
+                    break;
+                }
+            }
+            found
+        }
+        (None, None) => return Err(Box::new(nix::errno::Errno::EINVAL)),
+    };
+    let Some(found) = found else {
+        log::error!("No such inode");
+        return Err(Box::new(nix::errno::Errno::ENOENT));
+    };
+    Ok(found)
+}
+
+// Show an inode and its children, instead of the entire topology.
+fn run_inode(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    zone: usize,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    sopt: &crate::show::ShowOptions,
+    opt: &crate::Opt,
+) -> hammer2_utils::Result<()> {
+    if opt.json {
+        return crate::show::show_blockref_json(fso, zone, 0, 0, bref, false, sopt, opt);
+    }
+    if opt.dot {
+        print_dot_header();
+        crate::show::show_blockref_dot(
+            fso,
+            0,
+            None,
+            None,
+            0,
+            bref,
+            &mut std::collections::HashSet::new(),
+            sopt,
+            opt,
+        )?;
+        println!("}}");
+        return Ok(());
+    }
+
+    let vol = fso.get_root_volume_mut().ok_or(nix::errno::Errno::ENODEV)?;
+    println!("{}", vol.get_path());
+    let offset = libhammer2::volume::get_volume_data_offset(zone);
+    let buf = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+    let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+    crate::show::show_blockref(
+        fso,
+        voldata,
+        sopt.init_tab,
+        0,
+        0,
+        bref,
+        false,
+        &mut None,
+        sopt,
+        opt,
+    )
+}
+
+fn run_json(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    zone: usize,
+    sopt: &crate::show::ShowOptions,
+    opt: &crate::Opt,
+) -> hammer2_utils::Result<()> {
+    for i in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+        let vol = fso.get_root_volume_mut().ok_or(nix::errno::Errno::ENODEV)?;
+        let offset = libhammer2::volume::get_volume_data_offset(i);
+        if offset < vol.get_size() && (sopt.all_volume_data || zone == i) {
+            let buf = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+            let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+            let mut broot =
+                libhammer2::fs::Hammer2Blockref::new(libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME);
+            broot.mirror_tid = voldata.mirror_tid;
+            broot.data_off = offset | u64::try_from(libhammer2::fs::HAMMER2_PBUFRADIX)?;
+            crate::show::show_blockref_json(fso, i, 0, i, &broot, false, sopt, opt)?;
+        }
+    }
+    Ok(())
+}
+
+fn run_dot(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    zone: usize,
+    sopt: &crate::show::ShowOptions,
+    opt: &crate::Opt,
+) -> hammer2_utils::Result<()> {
+    let mut visited = std::collections::HashSet::new();
+    print_dot_header();
+    for i in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+        let vol = fso.get_root_volume_mut().ok_or(nix::errno::Errno::ENODEV)?;
+        let offset = libhammer2::volume::get_volume_data_offset(i);
+        if offset < vol.get_size() && (sopt.all_volume_data || zone == i) {
+            let buf = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+            let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+            let mut broot =
+                libhammer2::fs::Hammer2Blockref::new(libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME);
+            broot.mirror_tid = voldata.mirror_tid;
+            broot.data_off = offset | u64::try_from(libhammer2::fs::HAMMER2_PBUFRADIX)?;
+            crate::show::show_blockref_dot(fso, 0, None, None, i, &broot, &mut visited, sopt, opt)?;
+        }
+    }
+    println!("}}");
+    Ok(())
+}
+
+fn print_dot_header() {
+    println!("digraph hammer2 {{");
+    println!("    node [shape=box, style=filled, fontname=\"monospace\"];");
+}
@@ -1,10 +1,13 @@
 pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
-    let t = crate::env::init();
-    let sopt = crate::show::ShowOptions::new(t.0, t.1, t.2, t.3, t.4, 16);
+    let sopt = crate::show::ShowOptions::new(opt, 16);
 
     let mut fso = libhammer2::ondisk::init(devpath, true)?;
     let bests = fso.get_best_volume_data()?;
     let n = fso.get_nvolumes();
+    if opt.compare {
+        let bests: Vec<usize> = bests.iter().map(|x| x.0).collect();
+        return run_compare(&mut fso, &bests);
+    }
 
     for i in 0..n {
         println!("{}", fso[i].get_path());
@@ -29,3 +32,204 @@ pub(crate) fn run(devpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()>
     }
     Ok(())
 }
+
+const COMPARE_LABEL_WIDTH: usize = 18;
+const COMPARE_COLUMN_WIDTH: usize = 20;
+
+fn get_icrc_string(a: u32, b: u32) -> String {
+    if a == b {
+        format!("{b:#010x} OK")
+    } else {
+        format!("{b:#010x} FAILED")
+    }
+}
+
+pub(crate) fn get_icrc_sects(
+    voldata: &libhammer2::fs::Hammer2VolumeData,
+) -> (String, String, String) {
+    (
+        get_icrc_string(
+            voldata.get_crc(
+                libhammer2::fs::HAMMER2_VOLUME_ICRC0_OFF,
+                libhammer2::fs::HAMMER2_VOLUME_ICRC0_SIZE,
+            ),
+            voldata.icrc_sects[libhammer2::fs::HAMMER2_VOL_ICRC_SECT0],
+        ),
+        get_icrc_string(
+            voldata.get_crc(
+                libhammer2::fs::HAMMER2_VOLUME_ICRC1_OFF,
+                libhammer2::fs::HAMMER2_VOLUME_ICRC1_SIZE,
+            ),
+            voldata.icrc_sects[libhammer2::fs::HAMMER2_VOL_ICRC_SECT1],
+        ),
+        get_icrc_string(
+            voldata.get_crc(
+                libhammer2::fs::HAMMER2_VOLUME_ICRCVH_OFF,
+                libhammer2::fs::HAMMER2_VOLUME_ICRCVH_SIZE,
+            ),
+            voldata.icrc_volheader,
+        ),
+    )
+}
+
+// Return a reason why the header can't be the best, if any.
+// The whole voldata CRC is not critical.
+fn get_invalid_reason(voldata: &libhammer2::fs::Hammer2VolumeData) -> Option<&'static str> {
+    let (sect0, sect1, _) = get_icrc_sects(voldata);
+    if voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_HBO
+        && voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_ABO
+    {
+        Some("bad magic")
+    } else if sect0.ends_with("FAILED") {
+        Some("icrc_sects[0] failed")
+    } else if sect1.ends_with("FAILED") {
+        Some("icrc_sects[1] failed")
+    } else {
+        None
+    }
+}
+
+fn get_compare_rows(voldata: &libhammer2::fs::Hammer2VolumeData) -> Vec<(String, String)> {
+    let mut v = vec![
+        ("magic".to_string(), format!("{:#018x}", voldata.magic)),
+        ("volu_id".to_string(), voldata.volu_id.to_string()),
+        ("nvolumes".to_string(), voldata.nvolumes.to_string()),
+        ("version".to_string(), voldata.version.to_string()),
+        ("flags".to_string(), format!("{:#010x}", voldata.flags)),
+        (
+            "volu_size".to_string(),
+            format!("{:#018x}", voldata.volu_size),
+        ),
+        (
+            "total_size".to_string(),
+            format!("{:#018x}", voldata.total_size),
+        ),
+        (
+            "allocator_size".to_string(),
+            format!("{:#018x}", voldata.allocator_size),
+        ),
+        (
+            "allocator_free".to_string(),
+            format!("{:#018x}", voldata.allocator_free),
+        ),
+        (
+            "allocator_beg".to_string(),
+            format!("{:#018x}", voldata.allocator_beg),
+        ),
+        (
+            "mirror_tid".to_string(),
+            format!("{:#018x}", voldata.mirror_tid),
+        ),
+        (
+            "freemap_tid".to_string(),
+            format!("{:#018x}", voldata.freemap_tid),
+        ),
+        (
+            "bulkfree_tid".to_string(),
+            format!("{:#018x}", voldata.bulkfree_tid),
+        ),
+    ];
+    let (sect0, sect1, volhdr) = get_icrc_sects(voldata);
+    v.push(("icrc_sects[0]".to_string(), sect0));
+    v.push(("icrc_sects[1]".to_string(), sect1));
+    v.push(("icrc_volhdr".to_string(), volhdr));
+    for (name, blockset) in [
+        ("sroot", &voldata.sroot_blockset),
+        ("freemap", &voldata.freemap_blockset),
+    ] {
+        for (i, bref) in blockset.blockref.iter().enumerate() {
+            v.push((
+                format!("{name}[{i}]"),
+                format!(
+                    "{}:{:016x}",
+                    libhammer2::subs::get_blockref_type_string(bref.typ),
+                    bref.data_off
+                ),
+            ));
+        }
+    }
+    v
+}
+
+// Print all volume headers side by side.  Rows marked with '*' differ
+// among headers of the same volume.
+fn run_compare(fso: &mut libhammer2::ondisk::Ondisk, bests: &[usize]) -> hammer2_utils::Result<()> {
+    let mut columns = vec![]; // volume id, zone, rows
+    let mut reasons = vec![];
+    for i in 0..fso.get_nvolumes() {
+        let vol = &mut fso[i];
+        for j in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+            let offset = libhammer2::volume::get_volume_data_offset(j);
+            if offset < vol.get_size() {
+                let buf = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+                let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+                columns.push((i, j, get_compare_rows(voldata)));
+                reasons.push((i, j, voldata.mirror_tid, get_invalid_reason(voldata)));
+            }
+        }
+    }
+
+    let mut s = format!("  {:<COMPARE_LABEL_WIDTH$}", "");
+    for (i, j, _) in &columns {
+        let best = if bests[*i] == *j { " (best)" } else { "" };
+        s += &format!("{:<COMPARE_COLUMN_WIDTH$}", format!("vol{i}/zone{j}{best}"));
+    }
+    println!("{}", s.trim_end());
+    let nrows = columns.first().map_or(0, |x| x.2.len());
+    for r in 0..nrows {
+        let differ = columns.iter().any(|(i, _, x)| {
+            columns
+                .iter()
+                .any(|(i2, _, x2)| i == i2 && x[r].1 != x2[r].1)
+        });
+        let mut s = format!(
+            "{} {:<COMPARE_LABEL_WIDTH$}",
+            if differ { '*' } else { ' ' },
+            columns[0].2[r].0
+        );
+        for (_, _, x) in &columns {
+            s += &format!("{:<COMPARE_COLUMN_WIDTH$}", x[r].1);
+        }
+        println!("{}", s.trim_end());
+    }
+
+    // Explain the choice of get_best_volume_data(), which picks a header
+    // of the highest mirror_tid among valid ones.
+    println!();
+    for (i, best) in bests.iter().enumerate() {
+        let Some(best_tid) = reasons
+            .iter()
+            .find(|(vi, j, _, _)| *vi == i && j == best)
+            .map(|x| x.2)
+        else {
+            continue;
+        };
+        println!(
+            "Volume {i} best header {best}: highest mirror_tid {best_tid:#018x} of valid headers"
+        );
+        for (vi, j, mirror_tid, reason) in &reasons {
+            if *vi != i || j == best {
+                continue;
+            }
+            if let Some(reason) = reason {
+                println!("    header {j} invalid: {reason}");
+            } else if *mirror_tid == best_tid {
+                println!("    header {j} same mirror_tid");
+            } else if *mirror_tid < best_tid {
+                println!("    header {j} older mirror_tid {mirror_tid:#018x}");
+            } else {
+                println!("    header {j} newer mirror_tid {mirror_tid:#018x}");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_get_icrc_string() {
+        assert_eq!(super::get_icrc_string(0x1234, 0x1234), "0x00001234 OK");
+        assert_eq!(super::get_icrc_string(0x1234, 0x5678), "0x00005678 FAILED");
+    }
+}
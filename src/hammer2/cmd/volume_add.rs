@@ -0,0 +1,207 @@
+use std::os::unix::fs::FileExt;
+
+// Add a new volume to an unmounted filesystem.
+// The new volume is appended after the previous last volume, which is
+// re-aligned to the level1 freemap size (1GB) as newfs_hammer2 does for
+// volumes except for the last.
+#[allow(clippy::too_many_lines)]
+pub(crate) fn run(devpaths: &[&str], newpath: &str, opt: &crate::Opt) -> hammer2_utils::Result<()> {
+    let fso = libhammer2::ondisk::init(&devpaths.join(":"), true)?;
+    let bests = fso.get_best_volume_data()?;
+    let n = fso.get_nvolumes();
+    let root = usize::from(libhammer2::fs::HAMMER2_ROOT_VOLUME);
+    if n >= libhammer2::fs::HAMMER2_MAX_VOLUMES.into() {
+        log::error!(
+            "The maximum number of volumes is {}",
+            libhammer2::fs::HAMMER2_MAX_VOLUMES
+        );
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    for i in 0..n {
+        if fso[i].get_path() == newpath {
+            log::error!("{newpath} is already a volume");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+    }
+
+    // Get current sizes from the best root volume header.
+    let fp = std::fs::File::open(fso[root].get_path())?;
+    let v = crate::volume::read_volume_headers(&fp, fso[root].get_size())?;
+    drop(fp);
+    let rootdata = libhammer2::ondisk::media_as_volume_data(&v[bests[root].0].1);
+    if rootdata.version < libhammer2::fs::HAMMER2_VOL_VERSION_MULTI_VOLUMES {
+        log::error!(
+            "Version {} doesn't support multiple volumes",
+            rootdata.version
+        );
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let old_total_size = rootdata.total_size;
+
+    // Re-align the previous last volume to level1 boundary.
+    // Shrinking would drop freemap blocks and possibly allocated data
+    // in the tail, so it must be resized first if it can't be rounded up.
+    let vol = &fso[n - 1];
+    let last_path = vol.get_path().to_string();
+    let last_offset = vol.get_offset();
+    let last_size = vol.get_size();
+    if last_offset + last_size != old_total_size {
+        log::error!(
+            "{last_path}: size {last_size:016x} at {last_offset:016x} doesn't match \
+            total size {old_total_size:016x}"
+        );
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let last_new_size = last_size.next_multiple_of(libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE);
+    if last_new_size != last_size
+        && libhammer2::subs::get_volume_size_from_path(&last_path)? < last_new_size
+    {
+        log::error!(
+            "{last_path}: must be at least {last_new_size} bytes to align to {}",
+            libhammer2::subs::get_size_string(libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE)
+        );
+        return Err(Box::new(nix::errno::Errno::ENOSPC));
+    }
+
+    // Construct the new volume.
+    let new_id = n;
+    let new_offset = last_offset + last_new_size;
+    let new_size = libhammer2::subs::get_volume_size_from_path(newpath)?
+        & !libhammer2::fs::HAMMER2_VOLUME_ALIGNMASK;
+    if new_size == 0 {
+        log::error!("{newpath} has aligned size of 0");
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let new_total_size = new_offset + new_size;
+    let reserved_delta = crate::volume::get_reserved_size(new_total_size)
+        - crate::volume::get_reserved_size(old_total_size);
+    let delta = new_total_size - old_total_size;
+    if delta <= reserved_delta {
+        log::error!("{newpath}: not enough space to add");
+        return Err(Box::new(nix::errno::Errno::ENOSPC));
+    }
+    let free_delta = delta - reserved_delta;
+    let reserved = crate::volume::get_reserved_offsets(new_offset, new_total_size, new_offset);
+    assert_eq!(reserved.first(), Some(&0));
+
+    // Refuse to overwrite an existing HAMMER2 volume.
+    let fp = std::fs::File::open(newpath)?;
+    let buf = crate::volume::read_volume_headers(&fp, new_size)?;
+    if libhammer2::ondisk::media_as_volume_data(&buf[0].1).magic
+        == libhammer2::fs::HAMMER2_VOLUME_ID_HBO
+    {
+        log::error!("{newpath} contains a HAMMER2 volume header");
+        return Err(Box::new(nix::errno::Errno::EEXIST));
+    }
+    drop(fp);
+
+    if last_new_size != last_size {
+        println!(
+            "volume{:<2} {last_path} {last_size:016x} -> {last_new_size:016x}",
+            n - 1
+        );
+    }
+    println!(
+        "volume{new_id:<2} {newpath} {} at {new_offset:016x}",
+        libhammer2::subs::get_size_string(new_size)
+    );
+    println!("nvolumes         {n} -> {}", n + 1);
+    println!("total_size       {old_total_size:016x} -> {new_total_size:016x}");
+    println!(
+        "allocator_size   {:016x} -> {:016x}",
+        rootdata.allocator_size,
+        rootdata.allocator_size + free_delta
+    );
+    println!(
+        "allocator_free   {:016x} -> {:016x}",
+        rootdata.allocator_free,
+        rootdata.allocator_free + free_delta
+    );
+    if opt.dry_run {
+        println!("Dry run, nothing written");
+        return Ok(());
+    }
+
+    // Format the new volume header the way newfs_hammer2 does for
+    // volumes other than the root volume.
+    let mut volu_loff = rootdata.volu_loff[..n].to_vec();
+    volu_loff.push(new_offset);
+    let mut voldata = hammer2_utils::volume::new_volume_data(
+        rootdata.version,
+        new_id,
+        new_size,
+        &volu_loff,
+        new_total_size,
+        &rootdata.fsid,
+        &rootdata.fstype,
+    )?;
+    voldata.allocator_size = rootdata.allocator_size + free_delta;
+    voldata.mirror_tid = rootdata.mirror_tid;
+    voldata.freemap_tid = rootdata.freemap_tid;
+    hammer2_utils::volume::update_crc(&mut voldata);
+
+    // Write reserved areas and the volume header and all alternates.
+    let fp = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(newpath)?;
+    fp.write_all_at(
+        &vec![0; libhammer2::fs::HAMMER2_PBUFSIZE.try_into()?],
+        new_size - libhammer2::fs::HAMMER2_PBUFSIZE,
+    )?;
+    crate::volume::zero_reserved(&fp, &reserved, new_size)?;
+    for i in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+        let offset = libhammer2::volume::get_volume_data_offset(i);
+        if offset >= new_size {
+            break;
+        }
+        fp.write_all_at(libfs::cast::as_u8_slice(&voldata), offset)?;
+    }
+    fp.sync_all()?;
+    drop(fp);
+
+    // Release the re-aligned tail of the previous last volume in freemap
+    // leaves.  The best root volume header gets a new freemap_blockset,
+    // which other header copies sharing the old one also get below.
+    let fset = crate::volume::get_freemap_blockset_range();
+    let old_fset = v[bests[root].0].1[fset.clone()].to_vec();
+    drop(fso);
+    let mut fso = libhammer2::ondisk::init(&devpaths.join(":"), false)?;
+    let new_fset =
+        crate::volume::free_leaves(&mut fso, bests[root].0, old_total_size, new_total_size)?;
+
+    // Update all volume header copies on existing volumes.
+    for i in 0..n {
+        let vol = &fso[i];
+        let fp = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(vol.get_path())?;
+        let mut v = crate::volume::read_volume_headers(&fp, vol.get_size())?;
+        for (j, (offset, buf)) in v.iter_mut().enumerate() {
+            if vol.get_id() == root && buf[fset.clone()] == old_fset[..] {
+                buf[fset.clone()].copy_from_slice(&new_fset);
+            }
+            let voldata = crate::volume::media_as_volume_data_mut(buf);
+            if voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_HBO {
+                log::warn!("{}: skip header {j} at {offset:016x}", vol.get_path());
+                continue;
+            }
+            if i == n - 1 {
+                voldata.volu_size = last_new_size;
+            }
+            voldata.nvolumes = (n + 1).try_into()?;
+            voldata.total_size = new_total_size;
+            voldata.volu_loff[new_id] = new_offset;
+            voldata.allocator_size += free_delta;
+            if vol.get_id() == root {
+                voldata.allocator_free += free_delta;
+            }
+            hammer2_utils::volume::update_crc(voldata);
+            fp.write_all_at(buf, *offset)?;
+        }
+        fp.sync_all()?;
+    }
+    println!("{newpath} added as volume{new_id}");
+    Ok(())
+}
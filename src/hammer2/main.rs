@@ -1,6 +1,8 @@
 mod cmd;
 mod env;
 mod show;
+mod volume;
+mod walk;
 
 #[derive(Debug, Default)]
 pub(crate) struct Opt {
@@ -10,6 +12,36 @@ pub(crate) struct Opt {
     pub(crate) pfs_type: u8,
     pub(crate) uuid_str: Option<String>,
     pub(crate) mem: usize,
+    pub(crate) offline: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) scramble: bool,
+    pub(crate) json: bool,
+    pub(crate) dot: bool,
+    pub(crate) all_headers: bool,
+    pub(crate) show_tab: Option<usize>,
+    pub(crate) show_depth: Option<usize>,
+    pub(crate) min_mirror_tid: Option<u64>,
+    pub(crate) min_modify_tid: Option<u64>,
+    pub(crate) show_pfs: Option<String>,
+    pub(crate) show_types: Vec<u8>,
+    pub(crate) key_range: Option<(u64, u64)>,
+    pub(crate) inums: Vec<u64>,
+    pub(crate) histogram: bool,
+    pub(crate) map: bool,
+    pub(crate) svg: Option<String>,
+    pub(crate) compare: bool,
+    pub(crate) last: Option<usize>,
+    pub(crate) bref_from_parent: bool,
+    pub(crate) dump_format: cmd::dumpblock::Format,
+    pub(crate) dump_check: Option<libhammer2::fs::Hammer2Blockref>,
+    pub(crate) jobs: usize,
+    pub(crate) scan_dirents: bool,
+    pub(crate) save_index: Option<String>,
+    pub(crate) index: Option<String>,
+    pub(crate) list: bool,
+    pub(crate) dedup: bool,
+    pub(crate) modify_tid_range: Option<(u64, u64)>,
+    pub(crate) mtime_range: Option<(u64, u64)>,
 }
 
 impl Opt {
@@ -38,6 +70,145 @@ fn get_string_size(v: &str) -> hammer2_utils::Result<usize> {
     }
 }
 
+fn get_show_tab(v: &str) -> hammer2_utils::Result<usize> {
+    let v = v.parse()?;
+    if v > 8 {
+        return Err(Box::new(nix::errno::Errno::ERANGE));
+    }
+    Ok(v)
+}
+
+// TIDs and keys are hexadecimal with or without 0x prefix.
+fn get_hex(v: &str) -> hammer2_utils::Result<u64> {
+    let v = v.strip_prefix("0x").unwrap_or(v);
+    Ok(u64::from_str_radix(v, 16)?)
+}
+
+fn get_inums(v: &str) -> hammer2_utils::Result<Vec<u64>> {
+    let mut l = vec![];
+    for s in v.split(',') {
+        l.push(cmd::parse_u64(s)?);
+    }
+    Ok(l)
+}
+
+// BEG:END where either side can be omitted.
+fn get_key_range(v: &str) -> hammer2_utils::Result<(u64, u64)> {
+    get_hex_range(v, ':')
+}
+
+// BEG-END where either side can be omitted.
+fn get_tid_range(v: &str) -> hammer2_utils::Result<(u64, u64)> {
+    get_hex_range(v, '-')
+}
+
+fn get_hex_range(v: &str, sep: char) -> hammer2_utils::Result<(u64, u64)> {
+    let Some((beg, end)) = v.split_once(sep) else {
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    };
+    let beg = if beg.is_empty() { 0 } else { get_hex(beg)? };
+    let end = if end.is_empty() {
+        u64::MAX
+    } else {
+        get_hex(end)?
+    };
+    if beg > end {
+        return Err(Box::new(nix::errno::Errno::ERANGE));
+    }
+    Ok((beg, end))
+}
+
+// Seconds since epoch, or YYYY-MM-DD[THH:MM:SS] in UTC.
+fn get_time(v: &str) -> hammer2_utils::Result<u64> {
+    if v.bytes().all(|x| x.is_ascii_digit()) {
+        return Ok(v.parse()?);
+    }
+    let (date, t) = v.split_once('T').unwrap_or((v, "00:00:00"));
+    let date: Vec<&str> = date.split('-').collect();
+    let t: Vec<&str> = t.split(':').collect();
+    if date.len() != 3 || t.len() != 3 {
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    }
+    let date = time::Date::from_calendar_date(
+        date[0].parse()?,
+        time::Month::try_from(date[1].parse::<u8>()?)?,
+        date[2].parse()?,
+    )?;
+    let t = time::Time::from_hms(t[0].parse()?, t[1].parse()?, t[2].parse()?)?;
+    Ok(u64::try_from(
+        time::PrimitiveDateTime::new(date, t)
+            .assume_utc()
+            .unix_timestamp(),
+    )?)
+}
+
+// BEG,END in microseconds where either side can be omitted.
+// END is inclusive to the end of the second.
+fn get_mtime_range(v: &str) -> hammer2_utils::Result<(u64, u64)> {
+    let Some((beg, end)) = v.split_once(',') else {
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    };
+    let beg = if beg.is_empty() {
+        0
+    } else {
+        get_time(beg)?.saturating_mul(1_000_000)
+    };
+    let end = if end.is_empty() {
+        u64::MAX
+    } else {
+        get_time(end)?
+            .saturating_mul(1_000_000)
+            .saturating_add(999_999)
+    };
+    if beg > end {
+        return Err(Box::new(nix::errno::Errno::ERANGE));
+    }
+    Ok((beg, end))
+}
+
+fn get_blockref_types(v: &str) -> hammer2_utils::Result<Vec<u8>> {
+    let mut l = vec![];
+    for s in v.split(',') {
+        let Some(t) = walk::get_blockref_type(s) else {
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        };
+        l.push(t);
+    }
+    Ok(l)
+}
+
+fn get_jobs(v: &str) -> hammer2_utils::Result<usize> {
+    match v.parse()? {
+        0 => Err(Box::new(nix::errno::Errno::EINVAL)),
+        v => Ok(v),
+    }
+}
+
+fn get_dump_format(v: &str) -> hammer2_utils::Result<cmd::dumpblock::Format> {
+    match v {
+        "hex" => Ok(cmd::dumpblock::Format::Hex),
+        "raw" => Ok(cmd::dumpblock::Format::Raw),
+        "decode" => Ok(cmd::dumpblock::Format::Decode),
+        _ => Err(Box::new(nix::errno::Errno::EINVAL)),
+    }
+}
+
+// Invalid option values are fatal, unlike environment variables.
+fn get_opt<T>(
+    matches: &getopts::Matches,
+    name: &str,
+    f: fn(&str) -> hammer2_utils::Result<T>,
+) -> Option<T> {
+    let v = matches.opt_str(name)?;
+    match f(&v) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            log::error!("Invalid --{name} {v}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
 fn usage(prog: &str, gopt: &getopts::Options) {
     let indent = "    ";
     let ampersand = "&";
@@ -85,14 +256,32 @@ fn usage(prog: &str, gopt: &getopts::Options) {
             Return inode quota & config\n\
             {indent}growfs [<path>...]                \
             Grow a filesystem into resized partition\n\
+            {indent}growfs --offline <devpath>        \
+            Grow an unmounted filesystem into resized last volume\n\
             {indent}show <devpath>                    \
             Raw hammer2 media dump for topology\n\
+            {indent}show <devpath>@<label>[:<path>]   \
+            Raw hammer2 media dump for PFS, directory or file\n\
             {indent}freemap <devpath>                 \
             Raw hammer2 media dump for freemap\n\
             {indent}volhdr <devpath>                  \
             Raw hammer2 media dump for the volume header(s)\n\
+            {indent}df <devpath>                      \
+            Print space accounting per PFS\n\
+            {indent}compstat <devpath>@<label> [<path>] \
+            Print compression statistics per directory and extension\n\
+            {indent}history <devpath>@<label>         \
+            Print inodes of PFS grouped by modify_tid\n\
+            {indent}dumpblock <devpath> <data_off>    \
+            Dump a block as hexdump, raw bytes or decoded media\n\
+            {indent}image <devpath> <output>          \
+            Dump metadata into an image file\n\
+            {indent}image-restore <input> <output>    \
+            Restore an image file into sparse volume(s)\n\
             {indent}volume-list [<path>...]           \
             List volumes\n\
+            {indent}volume-add <devpath>... <newdev>  \
+            Add a volume to an unmounted filesystem\n\
             {indent}setcomp <comp[:level]> <path>...  \
             Set comp algo {{none, autozero, lz4, zlib}} {ampersand} level\n\
             {indent}setcheck <check> <path>...        \
@@ -112,7 +301,15 @@ fn usage(prog: &str, gopt: &getopts::Options) {
             {indent}dumpchain [<path>]                \
             Dump in-memory chain topology\n\
             {indent}cidprune [<path>]                 \
-            Free data chains"
+            Free data chains\n\
+            {indent}corrupt <image> <target> <action> \
+            Inject corruption into an image (developer)\n\
+            {indent}                                  \
+            target: volhdr=[<vol>:]<n>, data_off=<off>, inum=<inum>,\n\
+            {indent}                                  \
+            path=<label>/<path>, type=<type>[:<n>]\n\
+            {indent}                                  \
+            action: flip[:<bit>], zero, check, truncate, swap"
         ))
     );
 }
@@ -137,6 +334,118 @@ fn main() {
     gopt.optopt("t", "", "PFS type for pfs-create", "<type>");
     gopt.optopt("u", "", "uuid for pfs-create", "<uuid>");
     gopt.optopt("m", "", "buffer memory (bulkfree)", "<mem[k,m,g]>");
+    gopt.optopt("j", "jobs", "Number of media pass threads (recover)", "<n>");
+    gopt.optflag(
+        "",
+        "scan-dirents",
+        "Harvest directory entries into lost+found (recover)",
+    );
+    gopt.optopt(
+        "",
+        "save-index",
+        "Save media pass results to file (recover)",
+        "<path>",
+    );
+    gopt.optopt(
+        "",
+        "index",
+        "Load media pass results from file (recover)",
+        "<path>",
+    );
+    gopt.optflag(
+        "",
+        "list",
+        "List recoverable files without writing them (recover)",
+    );
+    gopt.optflag(
+        "",
+        "dedup",
+        "Remove recovered versions with identical content (recover)",
+    );
+    gopt.optopt(
+        "",
+        "modify-tid-range",
+        "Recover inodes within modify_tid range (recover)",
+        "<beg-end>",
+    );
+    gopt.optopt(
+        "",
+        "mtime-range",
+        "Recover inodes within mtime range (recover)",
+        "<beg,end>",
+    );
+    gopt.optflag("", "offline", "Operate on unmounted devpath (growfs)");
+    gopt.optflag(
+        "",
+        "dry-run",
+        "Don't write anything (growfs --offline, volume-add)",
+    );
+    gopt.optflag("", "scramble", "Scramble file names (image)");
+    gopt.optflag(
+        "",
+        "json",
+        "Print one JSON object per blockref (show) or file version (recover --list)",
+    );
+    gopt.optflag("", "dot", "Print blockref topology as a DOT graph (show)");
+    gopt.optflag(
+        "",
+        "all-headers",
+        "Show all volume headers (show, freemap, volhdr)",
+    );
+    gopt.optopt("", "tab", "Indentation width 0-8 (show, freemap)", "<n>");
+    gopt.optopt("", "depth", "Maximum depth of blockrefs to show", "<n>");
+    gopt.optopt(
+        "",
+        "min-mirror-tid",
+        "Omit blockrefs below mirror_tid",
+        "<tid>",
+    );
+    gopt.optopt(
+        "",
+        "min-modify-tid",
+        "Omit blockrefs below modify_tid",
+        "<tid>",
+    );
+    gopt.optopt("", "pfs", "Show PFS of label only (show)", "<label>");
+    gopt.optopt("", "type", "Show blockrefs of types only", "<type,...>");
+    gopt.optopt(
+        "",
+        "key-range",
+        "Show blockrefs within key range",
+        "<beg:end>",
+    );
+    gopt.optopt(
+        "",
+        "inum",
+        "Show inode of number only (show), recover inodes of numbers only (recover)",
+        "<inum,...>",
+    );
+    gopt.optflag(
+        "",
+        "histogram",
+        "Print free extent histogram per zone (freemap)",
+    );
+    gopt.optflag("", "map", "Print freemap usage map (freemap)");
+    gopt.optopt("", "svg", "Write freemap usage map to SVG file", "<path>");
+    gopt.optflag("", "compare", "Print volume headers side by side (volhdr)");
+    gopt.optopt("", "last", "Show last N transactions only (history)", "<n>");
+    gopt.optflag(
+        "",
+        "bref-from-parent",
+        "Find blockref of data_off from its parent (dumpblock)",
+    );
+    gopt.optopt(
+        "",
+        "format",
+        "Output format hex, raw or decode (dumpblock)",
+        "<fmt>",
+    );
+    gopt.optopt(
+        "",
+        "check",
+        "Check algorithm and code to verify block with (dumpblock)",
+        "<algo>[:<hex>]",
+    );
     gopt.optflag("", "version", "Print version and exit");
     gopt.optflag("", "help", "Print usage and exit");
 
@@ -192,6 +501,36 @@ fn main() {
         libhammer2::fs::HAMMER2_PFSTYPE_NONE
     };
     opt.uuid_str = matches.opt_str("u");
+    opt.offline = matches.opt_present("offline");
+    opt.dry_run = matches.opt_present("dry-run");
+    opt.scramble = matches.opt_present("scramble");
+    opt.json = matches.opt_present("json");
+    opt.dot = matches.opt_present("dot");
+    opt.all_headers = matches.opt_present("all-headers");
+    opt.show_tab = get_opt(&matches, "tab", get_show_tab);
+    opt.show_depth = get_opt(&matches, "depth", |v| Ok(v.parse()?));
+    opt.min_mirror_tid = get_opt(&matches, "min-mirror-tid", get_hex);
+    opt.min_modify_tid = get_opt(&matches, "min-modify-tid", get_hex);
+    opt.show_pfs = matches.opt_str("pfs");
+    opt.show_types = get_opt(&matches, "type", get_blockref_types).unwrap_or_default();
+    opt.key_range = get_opt(&matches, "key-range", get_key_range);
+    opt.inums = get_opt(&matches, "inum", get_inums).unwrap_or_default();
+    opt.histogram = matches.opt_present("histogram");
+    opt.svg = matches.opt_str("svg");
+    opt.map = matches.opt_present("map") || opt.svg.is_some();
+    opt.compare = matches.opt_present("compare");
+    opt.last = get_opt(&matches, "last", |v| Ok(v.parse()?));
+    opt.bref_from_parent = matches.opt_present("bref-from-parent");
+    opt.dump_format = get_opt(&matches, "format", get_dump_format).unwrap_or_default();
+    opt.dump_check = get_opt(&matches, "check", cmd::dumpblock::parse_check);
+    opt.jobs = get_opt(&matches, "jobs", get_jobs).unwrap_or(1);
+    opt.scan_dirents = matches.opt_present("scan-dirents");
+    opt.save_index = matches.opt_str("save-index");
+    opt.index = matches.opt_str("index");
+    opt.list = matches.opt_present("list");
+    opt.dedup = matches.opt_present("dedup");
+    opt.modify_tid_range = get_opt(&matches, "modify-tid-range", get_tid_range);
+    opt.mtime_range = get_opt(&matches, "mtime-range", get_mtime_range);
     opt.mem = match matches.opt_str("m") {
         Some(v) => match get_string_size(&v) {
             Ok(v) => v,
@@ -309,6 +648,12 @@ fn cmd_run(cmd: &str, args: &[&str], sel_path: &str, opt: &Opt) -> hammer2_utils
     } else if cmd == "stat" {
         let args = if args.is_empty() { &["."] } else { args };
         cmd::stat::run(args)
+    } else if cmd == "growfs" && opt.offline {
+        if args.len() != 1 {
+            log::error!("Requires device path");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::growfs::run_offline(args[0], opt)
     } else if cmd == "growfs" {
         let args = if args.is_empty() { &[sel_path] } else { args };
         cmd::growfs::run(args)
@@ -330,6 +675,42 @@ fn cmd_run(cmd: &str, args: &[&str], sel_path: &str, opt: &Opt) -> hammer2_utils
             return Err(Box::new(nix::errno::Errno::EINVAL));
         }
         cmd::volhdr::run(args[0], opt)
+    } else if cmd == "df" {
+        if args.len() != 1 {
+            log::error!("Requires device path");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::df::run(args[0])
+    } else if cmd == "compstat" {
+        if args.is_empty() || args.len() > 2 {
+            log::error!("Requires device path with PFS label, and optional path");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::compstat::run(args[0], args.get(1).copied())
+    } else if cmd == "history" {
+        if args.len() != 1 {
+            log::error!("Requires device path with PFS label");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::history::run(args[0], opt)
+    } else if cmd == "dumpblock" {
+        if args.len() != 2 {
+            log::error!("Requires device path and data_off");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::dumpblock::run(args[0], args[1], opt)
+    } else if cmd == "image" {
+        if args.len() != 2 {
+            log::error!("Requires device path and output path");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::image::run(args[0], args[1], opt)
+    } else if cmd == "image-restore" {
+        if args.len() != 2 {
+            log::error!("Requires image path and output path");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::image::run_restore(args[0], args[1])
     } else if cmd == "volume-list" {
         let args = if args.is_empty() { &[sel_path] } else { args };
         if cmd::volume_list::is_supported(args[0])? {
@@ -337,6 +718,12 @@ fn cmd_run(cmd: &str, args: &[&str], sel_path: &str, opt: &Opt) -> hammer2_utils
         } else {
             cmd::volume_list2::run(args, opt)
         }
+    } else if cmd == "volume-add" {
+        if args.len() < 2 {
+            log::error!("Requires device paths and a new device path");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::volume_add::run(&args[..args.len() - 1], args[args.len() - 1], opt)
     } else if cmd == "setcomp" {
         if args.len() < 2 {
             log::error!("Requires compression method and directory/file path");
@@ -372,6 +759,12 @@ fn cmd_run(cmd: &str, args: &[&str], sel_path: &str, opt: &Opt) -> hammer2_utils
     } else if cmd == "dumpchain" {
         let f = if args.is_empty() { "." } else { args[0] };
         cmd::dumpchain::run(f)
+    } else if cmd == "corrupt" {
+        if args.len() != 3 {
+            log::error!("Requires image path, target and action");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        cmd::corrupt::run(args[0], args[1], args[2])
     } else if cmd == "cidprune" {
         let f = if args.is_empty() { "." } else { args[0] };
         cmd::cidprune::run(f)
@@ -444,4 +837,118 @@ mod tests {
         // other
         assert!(super::get_string_size("xxx").is_err());
     }
+
+    #[test]
+    fn test_get_show_tab() {
+        assert_eq!(super::get_show_tab("0").unwrap(), 0);
+        assert_eq!(super::get_show_tab("8").unwrap(), 8);
+        assert!(super::get_show_tab("9").is_err());
+        assert!(super::get_show_tab("-1").is_err());
+        assert!(super::get_show_tab("x").is_err());
+    }
+
+    #[test]
+    fn test_get_hex() {
+        assert_eq!(super::get_hex("0").unwrap(), 0);
+        assert_eq!(super::get_hex("1f").unwrap(), 0x1f);
+        assert_eq!(super::get_hex("0x1F").unwrap(), 0x1f);
+        assert_eq!(super::get_hex("ffffffffffffffff").unwrap(), u64::MAX);
+        assert!(super::get_hex("").is_err());
+        assert!(super::get_hex("0x").is_err());
+        assert!(super::get_hex("g").is_err());
+        assert!(super::get_hex("10000000000000000").is_err());
+    }
+
+    #[test]
+    fn test_get_key_range() {
+        assert_eq!(super::get_key_range("10:20").unwrap(), (0x10, 0x20));
+        assert_eq!(super::get_key_range("0x10:0x10").unwrap(), (0x10, 0x10));
+        assert_eq!(super::get_key_range(":20").unwrap(), (0, 0x20));
+        assert_eq!(super::get_key_range("10:").unwrap(), (0x10, u64::MAX));
+        assert_eq!(super::get_key_range(":").unwrap(), (0, u64::MAX));
+        assert!(super::get_key_range("10").is_err());
+        assert!(super::get_key_range("20:10").is_err());
+        assert!(super::get_key_range("x:").is_err());
+    }
+
+    #[test]
+    fn test_get_inums() {
+        assert_eq!(super::get_inums("1").unwrap(), [1]);
+        assert_eq!(super::get_inums("1,0x10,16").unwrap(), [1, 16, 16]);
+        assert!(super::get_inums("").is_err());
+        assert!(super::get_inums("1,").is_err());
+        assert!(super::get_inums("x").is_err());
+    }
+
+    #[test]
+    fn test_get_tid_range() {
+        assert_eq!(super::get_tid_range("10-20").unwrap(), (0x10, 0x20));
+        assert_eq!(super::get_tid_range("0x10-").unwrap(), (0x10, u64::MAX));
+        assert_eq!(super::get_tid_range("-20").unwrap(), (0, 0x20));
+        assert!(super::get_tid_range("10:20").is_err());
+        assert!(super::get_tid_range("20-10").is_err());
+    }
+
+    #[test]
+    fn test_get_mtime_range() {
+        assert_eq!(super::get_mtime_range("0,10").unwrap(), (0, 10_999_999));
+        assert_eq!(
+            super::get_mtime_range("1970-01-02,").unwrap(),
+            (86_400_000_000, u64::MAX)
+        );
+        assert_eq!(
+            super::get_mtime_range(",1970-01-01T00:01:00").unwrap(),
+            (0, 60_999_999)
+        );
+        assert_eq!(
+            super::get_mtime_range("2024-01-01,2024-01-01").unwrap(),
+            (1_704_067_200_000_000, 1_704_067_200_999_999)
+        );
+        assert!(super::get_mtime_range("10").is_err());
+        assert!(super::get_mtime_range("10,0").is_err());
+        assert!(super::get_mtime_range("2024-13-01,").is_err());
+        assert!(super::get_mtime_range("2024-01-01T25:00:00,").is_err());
+        assert!(super::get_mtime_range("x,").is_err());
+    }
+
+    #[test]
+    fn test_get_blockref_types() {
+        assert_eq!(
+            super::get_blockref_types("inode").unwrap(),
+            [libhammer2::fs::HAMMER2_BREF_TYPE_INODE]
+        );
+        assert_eq!(
+            super::get_blockref_types("inode,DIRENT").unwrap(),
+            [
+                libhammer2::fs::HAMMER2_BREF_TYPE_INODE,
+                libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT
+            ]
+        );
+        assert!(super::get_blockref_types("").is_err());
+        assert!(super::get_blockref_types("inode,").is_err());
+        assert!(super::get_blockref_types("volume").is_err());
+    }
+
+    #[test]
+    fn test_get_jobs() {
+        assert_eq!(super::get_jobs("1").unwrap(), 1);
+        assert_eq!(super::get_jobs("16").unwrap(), 16);
+        assert!(super::get_jobs("0").is_err());
+        assert!(super::get_jobs("-1").is_err());
+        assert!(super::get_jobs("x").is_err());
+    }
+
+    #[test]
+    fn test_get_dump_format() {
+        assert_eq!(
+            super::get_dump_format("hex").unwrap(),
+            crate::cmd::dumpblock::Format::Hex
+        );
+        assert_eq!(
+            super::get_dump_format("decode").unwrap(),
+            crate::cmd::dumpblock::Format::Decode
+        );
+        assert!(super::get_dump_format("").is_err());
+        assert!(super::get_dump_format("HEX").is_err());
+    }
 }
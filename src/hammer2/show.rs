@@ -8,26 +8,31 @@ pub(crate) struct ShowOptions {
     pub(crate) min_mirror_tid: u64,
     pub(crate) min_modify_tid: u64,
     pub(crate) init_tab: usize,
+    pub(crate) pfs: Option<String>,
+    pub(crate) types: Vec<u8>,
+    pub(crate) key_range: Option<(u64, u64)>,
 }
 
 impl ShowOptions {
-    pub(crate) fn new(
-        all_volume_data: bool,
-        tab: usize,
-        depth: usize,
-        min_mirror_tid: u64,
-        min_modify_tid: u64,
-        init_tab: usize,
-    ) -> Self {
+    // Command line options take precedence over environment variables.
+    pub(crate) fn new(opt: &crate::Opt, init_tab: usize) -> Self {
+        let t = crate::env::init();
         Self {
-            all_volume_data,
-            tab,
-            depth,
-            min_mirror_tid,
-            min_modify_tid,
+            all_volume_data: opt.all_headers || t.0,
+            tab: opt.show_tab.unwrap_or(t.1),
+            depth: opt.show_depth.unwrap_or(t.2),
+            min_mirror_tid: opt.min_mirror_tid.unwrap_or(t.3),
+            min_modify_tid: opt.min_modify_tid.unwrap_or(t.4),
             init_tab,
+            pfs: opt.show_pfs.clone(),
+            types: opt.show_types.clone(),
+            key_range: opt.key_range,
         }
     }
+
+    fn is_shown(&self, typ: u8) -> bool {
+        self.types.is_empty() || self.types.contains(&typ)
+    }
 }
 
 #[derive(Debug, Default)]
@@ -192,6 +197,7 @@ pub(crate) fn show_volume_data(
             fso,
             voldata,
             sopt.init_tab,
+            0,
             i,
             &voldata.sroot_blockset.blockref[i],
             true,
@@ -207,6 +213,7 @@ pub(crate) fn show_volume_data(
             fso,
             voldata,
             sopt.init_tab,
+            0,
             i,
             &voldata.freemap_blockset.blockref[i],
             true,
@@ -236,12 +243,113 @@ pub(crate) fn show_volume_data(
     Ok(())
 }
 
+fn is_omitted(bref: &libhammer2::fs::Hammer2Blockref, sopt: &ShowOptions) -> bool {
+    // omit if smaller than mininum mirror_tid threshold
+    if bref.mirror_tid < sopt.min_mirror_tid {
+        return true;
+    }
+    // omit if key range doesn't overlap, except for the root
+    if let Some((beg, end)) = sopt.key_range {
+        if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME
+            && bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP
+            && !crate::walk::has_key_range(bref, beg, end)
+        {
+            return true;
+        }
+    }
+    // omit if smaller than mininum modify_tid threshold
+    bref.modify_tid < sopt.min_modify_tid
+        && (bref.modify_tid != 0
+            || (bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_INODE && bref.leaf_count == 0))
+}
+
+// Return true if bref is a root inode of PFS other than the one specified.
+fn is_other_pfs(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: &[u8],
+    sopt: &ShowOptions,
+) -> hammer2_utils::Result<bool> {
+    let Some(pfs) = &sopt.pfs else {
+        return Ok(false);
+    };
+    if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_INODE {
+        return Ok(false);
+    }
+    let ipdata = get_inode_data(bref, media)?;
+    Ok(ipdata.meta.is_pfs_root() && ipdata.get_filename_string()? != *pfs)
+}
+
+// Check data integrity of media, and return a string to print and
+// whether it failed or not.
+fn check_media(bref: &libhammer2::fs::Hammer2Blockref, media: &[u8]) -> (String, bool) {
+    let check_algo = libhammer2::fs::dec_check(bref.methods);
+    let check_str = libhammer2::subs::get_check_mode_string(check_algo);
+    let comp_algo = libhammer2::fs::dec_comp(bref.methods);
+    let comp_str = libhammer2::subs::get_comp_mode_string(comp_algo);
+    let meth = format!("{check_str}|{comp_str}");
+    match check_algo {
+        libhammer2::fs::HAMMER2_CHECK_NONE | libhammer2::fs::HAMMER2_CHECK_DISABLED => {
+            (format!("meth={meth} "), false)
+        }
+        libhammer2::fs::HAMMER2_CHECK_ISCSI32 => {
+            let cv = icrc32::iscsi_crc32(media);
+            let iscsi32 = bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckIscsi>();
+            if iscsi32.value == cv {
+                (format!("meth={meth} icrc={cv:08x} "), false)
+            } else {
+                (
+                    format!("(icrc {meth} {:08x}/{cv:08x} failed) ", iscsi32.value),
+                    true,
+                )
+            }
+        }
+        libhammer2::fs::HAMMER2_CHECK_XXHASH64 => {
+            let cv = libhammer2::xxhash::xxh64(media);
+            let xxhash64 = bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckXxhash64>();
+            if xxhash64.value == cv {
+                (format!("meth={meth} xxh={cv:016x} "), false)
+            } else {
+                (
+                    format!("(xxh {meth} {:016x}/{cv:016x} failed) ", xxhash64.value),
+                    true,
+                )
+            }
+        }
+        libhammer2::fs::HAMMER2_CHECK_SHA192 => {
+            let cv = libhammer2::sha::sha256(media);
+            if bref
+                .check_as::<libhammer2::fs::Hammer2BlockrefCheckSha256>()
+                .data
+                == cv.as_slice()
+            {
+                (format!("meth={meth} "), false)
+            } else {
+                (format!("(sha192 {meth} failed) "), true)
+            }
+        }
+        libhammer2::fs::HAMMER2_CHECK_FREEMAP => {
+            let cv = icrc32::iscsi_crc32(media);
+            let freemap = bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckFreemap>();
+            if freemap.icrc32 == cv {
+                (format!("meth={meth} fcrc={cv:08x} "), false)
+            } else {
+                (
+                    format!("(fcrc {meth} {:08x}/{cv:08x} failed) ", freemap.icrc32),
+                    true,
+                )
+            }
+        }
+        _ => (format!("(unknown check {meth}) "), true),
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::too_many_lines)]
 pub(crate) fn show_blockref(
     fso: &mut libhammer2::ondisk::Ondisk,
     voldata: &libhammer2::fs::Hammer2VolumeData,
     tab: usize,
+    depth: usize,
     bi: usize,
     bref: &libhammer2::fs::Hammer2Blockref,
     norecurse: bool,
@@ -249,15 +357,7 @@ pub(crate) fn show_blockref(
     sopt: &ShowOptions,
     opt: &crate::Opt,
 ) -> hammer2_utils::Result<()> {
-    // omit if smaller than mininum mirror_tid threshold
-    if bref.mirror_tid < sopt.min_mirror_tid {
-        return Ok(());
-    }
-    // omit if smaller than mininum modify_tid threshold
-    if bref.modify_tid < sopt.min_modify_tid
-        && (bref.modify_tid != 0
-            || (bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_INODE && bref.leaf_count == 0))
-    {
+    if is_omitted(bref, sopt) {
         return Ok(());
     }
 
@@ -267,6 +367,12 @@ pub(crate) fn show_blockref(
     } else {
         vec![]
     };
+    if is_other_pfs(bref, &media, sopt)? {
+        return Ok(());
+    }
+    // Blockrefs of types not specified are hidden, but their children
+    // are shown at the same indentation.
+    let shown = sopt.is_shown(bref.typ);
     let type_str = libhammer2::subs::get_blockref_type_string(bref.typ);
     let type_pad = if type_str.len() > 8 {
         0
@@ -283,7 +389,7 @@ pub(crate) fn show_blockref(
         .get_volume(bref.data_off)
         .ok_or(nix::errno::Errno::ENODEV)?
         .get_id();
-    if opt.quiet {
+    if shown && opt.quiet {
         hammer2_utils::tab::print!(
             tab,
             "{type_str}.{bi:<3} {:016x} {:016x}/{:<2} vol={id} mir={:016x} mod={:016x} leafcnt={} ",
@@ -294,7 +400,7 @@ pub(crate) fn show_blockref(
             bref.modify_tid,
             bref.leaf_count
         );
-    } else {
+    } else if shown {
         hammer2_utils::tab::println!(
             tab,
             "{type_str}.{bi:<3}{} {:016x} {:016x}/{:<2} ",
@@ -319,17 +425,19 @@ pub(crate) fn show_blockref(
             hammer2_utils::tab::print!(tab + 13, "");
         }
     }
-    if !bscan.is_empty() {
-        print!("bcnt={} ", bscan.len());
-    }
-    if bref.flags != 0 {
-        print!("flags={:02x} ", bref.flags);
-    }
-    if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE
-        || bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF
-    {
-        let freemap = bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckFreemap>();
-        print!("bigmask={:08x} avail={} ", freemap.bigmask, freemap.avail);
+    if shown {
+        if !bscan.is_empty() {
+            print!("bcnt={} ", bscan.len());
+        }
+        if bref.flags != 0 {
+            print!("flags={:02x} ", bref.flags);
+        }
+        if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE
+            || bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF
+        {
+            let freemap = bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckFreemap>();
+            print!("bigmask={:08x} avail={} ", freemap.bigmask, freemap.avail);
+        }
     }
 
     // Check data integrity in verbose mode, otherwise we are just doing
@@ -343,82 +451,36 @@ pub(crate) fn show_blockref(
     let bytes = if radix == 0 { 0 } else { 1 << radix };
     let mut failed = false;
     if bytes > 0 && (bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_DATA || opt.verbose) {
-        if !opt.quiet {
-            println!();
-            hammer2_utils::tab::print!(tab + 13, "");
-        }
-        let check_algo = libhammer2::fs::dec_check(bref.methods);
-        let check_str = libhammer2::subs::get_check_mode_string(check_algo);
-        let comp_algo = libhammer2::fs::dec_comp(bref.methods);
-        let comp_str = libhammer2::subs::get_comp_mode_string(comp_algo);
-        let meth = format!("{check_str}|{comp_str}");
-        match check_algo {
-            libhammer2::fs::HAMMER2_CHECK_NONE | libhammer2::fs::HAMMER2_CHECK_DISABLED => {
-                print!("meth={meth} ");
-            }
-            libhammer2::fs::HAMMER2_CHECK_ISCSI32 => {
-                let cv = icrc32::iscsi_crc32(&media);
-                let iscsi32 = bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckIscsi>();
-                if iscsi32.value == cv {
-                    print!("meth={meth} icrc={cv:08x} ");
-                } else {
-                    print!("(icrc {meth} {:08x}/{cv:08x} failed) ", iscsi32.value);
-                    failed = true;
-                }
-            }
-            libhammer2::fs::HAMMER2_CHECK_XXHASH64 => {
-                let cv = libhammer2::xxhash::xxh64(&media);
-                let xxhash64 = bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckXxhash64>();
-                if xxhash64.value == cv {
-                    print!("meth={meth} xxh={cv:016x} ");
-                } else {
-                    print!("(xxh {meth} {:016x}/{cv:016x} failed) ", xxhash64.value);
-                    failed = true;
-                }
-            }
-            libhammer2::fs::HAMMER2_CHECK_SHA192 => {
-                let cv = libhammer2::sha::sha256(&media);
-                if bref
-                    .check_as::<libhammer2::fs::Hammer2BlockrefCheckSha256>()
-                    .data
-                    == cv.as_slice()
-                {
-                    print!("meth={meth} ");
-                } else {
-                    print!("(sha192 {meth} failed) ");
-                    failed = true;
-                }
-            }
-            libhammer2::fs::HAMMER2_CHECK_FREEMAP => {
-                let cv = icrc32::iscsi_crc32(&media);
-                let freemap = bref.check_as::<libhammer2::fs::Hammer2BlockrefCheckFreemap>();
-                if freemap.icrc32 == cv {
-                    print!("meth={meth} fcrc={cv:08x} ");
-                } else {
-                    print!("(fcrc {meth} {:08x}/{cv:08x} failed) ", freemap.icrc32);
-                    failed = true;
-                }
+        let (s, v) = check_media(bref, &media);
+        if shown {
+            if !opt.quiet {
+                println!();
+                hammer2_utils::tab::print!(tab + 13, "");
             }
-            _ => panic!("{check_algo}"),
+            print!("{s}");
         }
+        failed = v;
     }
 
-    let tab = tab + sopt.tab;
-    let obrace = if opt.quiet {
+    let ntab = if shown { tab + sopt.tab } else { tab };
+    let obrace = if !shown {
+        false
+    } else if opt.quiet {
         println!();
         false
     } else {
-        show_blockref_data(&media, tab, bref, norecurse)?
+        show_blockref_data(&media, ntab, bref, norecurse)?
     };
 
     // Update statistics.
     if let Some(ref mut stat) = stat {
         if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF {
-            let bmdata = libhammer2::fs::media_as(&media);
+            let bmdata = get_bmap_data(bref, &media)?;
             for i in 0..libhammer2::fs::HAMMER2_FREEMAP_COUNT {
                 let bmdata = &bmdata[i];
-                let data_off =
-                    bref.key + u64::try_from(i)? * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
+                let data_off = bref
+                    .key
+                    .wrapping_add(u64::try_from(i)? * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE);
                 if data_off >= voldata.aux_end && data_off < fso.get_total_size() {
                     for j in 0..4 {
                         count_blocks(bmdata, j, stat)?;
@@ -435,17 +497,27 @@ pub(crate) fn show_blockref(
     // That is, if an indirect or inode fails we still try to list its
     // direct children to help with debugging, but go no further than
     // that because they are probably garbage.
-    if (sopt.depth == usize::MAX || ((tab - sopt.init_tab) / sopt.tab) < sopt.depth) && !norecurse {
+    if is_in_depth(depth, sopt) && !norecurse {
         for (i, bref) in bscan.iter().enumerate() {
             if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
-                show_blockref(fso, voldata, tab, i, bref, failed, stat, sopt, opt)?;
+                show_blockref(
+                    fso,
+                    voldata,
+                    ntab,
+                    depth + 1,
+                    i,
+                    bref,
+                    failed,
+                    stat,
+                    sopt,
+                    opt,
+                )?;
             }
         }
     }
-    let tab = tab - sopt.tab;
     if obrace {
         if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_INODE {
-            let ipdata = libhammer2::ondisk::media_as_inode_data(&media);
+            let ipdata = get_inode_data(bref, &media)?;
             hammer2_utils::tab::println!(
                 tab,
                 "}} ({}.{}, \"{}\")",
@@ -460,6 +532,11 @@ pub(crate) fn show_blockref(
     Ok(())
 }
 
+// Return true if children of bref at depth (0 for the root) are shown.
+fn is_in_depth(depth: usize, sopt: &ShowOptions) -> bool {
+    sopt.depth == usize::MAX || depth + 1 < sopt.depth
+}
+
 #[allow(clippy::too_many_lines)]
 fn show_blockref_data(
     media: &[u8],
@@ -476,7 +553,7 @@ fn show_blockref_data(
         }
         libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
             println!("{{");
-            let ipdata = libhammer2::ondisk::media_as_inode_data(media);
+            let ipdata = get_inode_data(bref, media)?;
             let meta = &ipdata.meta;
             hammer2_utils::tab::println!(tab, "filename \"{}\"", ipdata.get_filename_string()?);
             hammer2_utils::tab::println!(tab, "version  {}", meta.version);
@@ -597,16 +674,16 @@ fn show_blockref_data(
         libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
             println!("{{");
             let dirent = bref.embed_as::<libhammer2::fs::Hammer2DirentHead>();
-            let namelen = usize::from(dirent.namlen);
-            hammer2_utils::tab::println!(
-                tab,
-                "filename \"{}\"",
-                if namelen <= bref.check.len() {
-                    std::str::from_utf8(&bref.check[..namelen])
-                } else {
-                    std::str::from_utf8(&media[..namelen])
-                }?
-            );
+            let Some(name) = hammer2_utils::media::get_dirent_name(bref, media) else {
+                log::error!(
+                    "{:016x}: namlen {} exceeds media size {}",
+                    bref.data_off,
+                    dirent.namlen,
+                    media.len()
+                );
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            };
+            hammer2_utils::tab::println!(tab, "filename \"{}\"", std::str::from_utf8(name)?);
             hammer2_utils::tab::println!(tab, "inum {:#018x}", dirent.inum);
             hammer2_utils::tab::println!(tab, "nlen {}", dirent.namlen);
             hammer2_utils::tab::println!(
@@ -619,21 +696,18 @@ fn show_blockref_data(
         libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE
         | libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF => {
             println!("{{");
-            let mut tmp = bref.get_raw_data_off();
-            tmp &= libhammer2::fs::HAMMER2_SEGMASK;
-            tmp /= libhammer2::fs::HAMMER2_PBUFSIZE;
-            let mut tmp = usize::try_from(tmp)?;
-            assert!(tmp >= libhammer2::fs::HAMMER2_ZONE_FREEMAP_00);
-            assert!(tmp < libhammer2::fs::HAMMER2_ZONE_FREEMAP_END);
-            tmp -= libhammer2::fs::HAMMER2_ZONE_FREEMAP_00;
-            tmp /= libhammer2::fs::HAMMER2_ZONE_FREEMAP_INC;
-            hammer2_utils::tab::println!(tab, "rotation={}", tmp);
+            let Some(rotation) = hammer2_utils::media::get_freemap_rotation(bref) else {
+                log::error!("{:016x}: not within freemap zones", bref.data_off);
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            };
+            hammer2_utils::tab::println!(tab, "rotation={}", rotation);
             if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF {
-                let bmdata = libhammer2::fs::media_as::<libhammer2::fs::Hammer2BmapData>(media);
+                let bmdata = get_bmap_data(bref, media)?;
                 for i in 0..libhammer2::fs::HAMMER2_FREEMAP_COUNT {
                     let bmdata = &bmdata[i];
-                    let data_off =
-                        bref.key + u64::try_from(i)? * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
+                    let data_off = bref.key.wrapping_add(
+                        u64::try_from(i)? * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE,
+                    );
                     hammer2_utils::tab::println!(
                         tab + 4,
                         "{data_off:016x} {i:04}.{:04x} linear={:06x} avail={:06x} \
@@ -656,7 +730,8 @@ fn show_blockref_data(
             Ok(true)
         }
         libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP | libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME => {
-            let voldata = libhammer2::ondisk::media_as_volume_data(media);
+            let voldata = hammer2_utils::media::media_as_volume_data(media)
+                .ok_or(nix::errno::Errno::EINVAL)?;
             print!(
                 "mirror_tid={:016x} freemap_tid={:016x} ",
                 voldata.mirror_tid, voldata.freemap_tid
@@ -671,6 +746,339 @@ fn show_blockref_data(
     }
 }
 
+// Print bref and its children as NDJSON, one object per blockref.
+// Unlike show_blockref(), layout options don't apply, and depth is
+// relative to the volume header.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn show_blockref_json(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    zone: usize,
+    depth: usize,
+    bi: usize,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    norecurse: bool,
+    sopt: &ShowOptions,
+    opt: &crate::Opt,
+) -> hammer2_utils::Result<()> {
+    if is_omitted(bref, sopt) {
+        return Ok(());
+    }
+    let media = if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_DATA || opt.verbose {
+        fso.read_media(bref)?
+    } else {
+        vec![]
+    };
+    if is_other_pfs(bref, &media, sopt)? {
+        return Ok(());
+    }
+    let id = fso
+        .get_volume(bref.data_off)
+        .ok_or(nix::errno::Errno::ENODEV)?
+        .get_id();
+
+    let mut o = hammer2_utils::json::Object::new();
+    o.add_u64("volhdr", zone.try_into()?)
+        .add_u64("depth", depth.try_into()?)
+        .add_u64("index", bi.try_into()?)
+        .add_str("type", libhammer2::subs::get_blockref_type_string(bref.typ))
+        .add_hex("data_off", bref.data_off)
+        .add_u64("radix", crate::walk::get_radix(bref))
+        .add_hex("key", bref.key)
+        .add_u64("keybits", bref.keybits.into())
+        .add_hex("mirror_tid", bref.mirror_tid)
+        .add_hex("modify_tid", bref.modify_tid)
+        .add_u64("leaf_count", bref.leaf_count.into())
+        .add_u64("flags", bref.flags.into())
+        .add_str(
+            "comp",
+            libhammer2::subs::get_comp_mode_string(libhammer2::fs::dec_comp(bref.methods)),
+        )
+        .add_str(
+            "check_algo",
+            libhammer2::subs::get_check_mode_string(libhammer2::fs::dec_check(bref.methods)),
+        );
+    // Same as show_blockref(), data blocks are only verified in verbose mode.
+    let mut failed = false;
+    if media.is_empty() {
+        o.add_null("check");
+    } else {
+        match libhammer2::ondisk::verify_media(bref, &media) {
+            Ok(true) => o.add_str("check", "ok"),
+            Ok(false) => {
+                failed = true;
+                o.add_str("check", "failed")
+            }
+            Err(_) => {
+                failed = true;
+                o.add_str("check", "unknown")
+            }
+        };
+    }
+    o.add_u64("vol", id.try_into()?);
+    match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+            o.add_object(
+                "inode",
+                &get_inode_json(bref, get_inode_data(bref, &media)?)?,
+            );
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+            let dirent = bref.embed_as::<libhammer2::fs::Hammer2DirentHead>();
+            let Some(name) = hammer2_utils::media::get_dirent_name(bref, &media) else {
+                log::error!(
+                    "{:016x}: namlen {} exceeds media size {}",
+                    bref.data_off,
+                    dirent.namlen,
+                    media.len()
+                );
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            };
+            o.add_str("name", &String::from_utf8_lossy(name))
+                .add_hex("inum", dirent.inum)
+                .add_str(
+                    "inode_type",
+                    libhammer2::subs::get_inode_type_string(dirent.typ),
+                );
+        }
+        _ => (),
+    }
+    if sopt.is_shown(bref.typ) {
+        println!("{o}");
+    }
+
+    // If the check failed, children are probably garbage.
+    if is_in_depth(depth, sopt) && !norecurse && !failed && !media.is_empty() {
+        for (i, bref) in libhammer2::ondisk::media_as_blockref_safe(bref, &media)
+            .iter()
+            .enumerate()
+        {
+            if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
+                show_blockref_json(fso, zone, depth + 1, i, bref, false, sopt, opt)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_dot_color(typ: u8) -> &'static str {
+    match typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => "lightblue",
+        libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => "khaki",
+        libhammer2::fs::HAMMER2_BREF_TYPE_DATA => "white",
+        libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => "palegreen",
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE => "orange",
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF => "salmon",
+        _ => "lightgray",
+    }
+}
+
+// Print bref and its children as DOT nodes and edges.  A block shared by
+// several parents (e.g. by snapshots) is printed once, with an edge from
+// each parent.  Blockrefs without media (e.g. dirents with embedded
+// filename) are unique to parent.  Blockrefs of types not specified are
+// hidden, and edges go from the nearest shown ancestor (from) instead.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn show_blockref_dot(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    depth: usize,
+    parent: Option<&str>,
+    from: Option<&str>,
+    bi: usize,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    visited: &mut std::collections::HashSet<String>,
+    sopt: &ShowOptions,
+    opt: &crate::Opt,
+) -> hammer2_utils::Result<()> {
+    if is_omitted(bref, sopt) {
+        return Ok(());
+    }
+    let name = match parent {
+        Some(parent) if crate::walk::get_radix(bref) == 0 => format!("{parent}_{bi}"),
+        _ => format!("n{:016x}", bref.data_off),
+    };
+    let shown = sopt.is_shown(bref.typ);
+    // Hidden blocks aren't marked as visited, so that edges to their
+    // shown descendants are printed for each parent.
+    if shown && visited.contains(&name) {
+        if let Some(from) = from {
+            println!("    {from} -> {name};");
+        }
+        return Ok(());
+    }
+
+    let media = if crate::walk::get_radix(bref) != 0
+        && (bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_DATA || opt.verbose)
+    {
+        fso.read_media(bref)?
+    } else {
+        vec![]
+    };
+    if is_other_pfs(bref, &media, sopt)? {
+        return Ok(());
+    }
+    if shown {
+        if let Some(from) = from {
+            println!("    {from} -> {name};");
+        }
+        visited.insert(name.clone());
+        let mut label = format!(
+            "{}\n{:016x}\n{:016x}/{}\nmir={:016x}\nmod={:016x}",
+            libhammer2::subs::get_blockref_type_string(bref.typ),
+            bref.data_off,
+            bref.key,
+            bref.keybits,
+            bref.mirror_tid,
+            bref.modify_tid
+        );
+        match bref.typ {
+            libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+                let ipdata = get_inode_data(bref, &media)?;
+                label.push_str(&format!("\n\"{}\"", ipdata.get_filename_string()?));
+            }
+            libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+                if let Some(v) = hammer2_utils::media::get_dirent_name(bref, &media) {
+                    label.push_str(&format!("\n\"{}\"", String::from_utf8_lossy(v)));
+                }
+            }
+            _ => (),
+        }
+        // DOT and JSON strings share escape sequences for quotes and newlines.
+        println!(
+            "    {name} [label={}, fillcolor={}];",
+            hammer2_utils::json::quote(&label),
+            get_dot_color(bref.typ)
+        );
+    }
+
+    if is_in_depth(depth, sopt) && !media.is_empty() {
+        let from = if shown { Some(name.as_str()) } else { from };
+        for (i, bref) in libhammer2::ondisk::media_as_blockref_safe(bref, &media)
+            .iter()
+            .enumerate()
+        {
+            if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
+                show_blockref_dot(
+                    fso,
+                    depth + 1,
+                    Some(&name),
+                    from,
+                    i,
+                    bref,
+                    visited,
+                    sopt,
+                    opt,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_inode_json(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    ipdata: &libhammer2::fs::Hammer2InodeData,
+) -> hammer2_utils::Result<hammer2_utils::json::Object> {
+    let meta = &ipdata.meta;
+    let stats = bref.embed_as::<libhammer2::fs::Hammer2BlockrefEmbedStats>();
+    let mut o = hammer2_utils::json::Object::new();
+    o.add_str("filename", &ipdata.get_filename_string()?)
+        .add_u64("version", meta.version.into())
+        .add_u64("uflags", meta.uflags.into())
+        .add_u64("rmajor", meta.rmajor.into())
+        .add_u64("rminor", meta.rminor.into())
+        .add_u64("ctime", meta.ctime)
+        .add_u64("mtime", meta.mtime)
+        .add_u64("atime", meta.atime)
+        .add_u64("btime", meta.btime)
+        .add_str(
+            "uid",
+            &libhammer2::subs::get_uuid_string_from_bytes(&meta.uid),
+        )
+        .add_str(
+            "gid",
+            &libhammer2::subs::get_uuid_string_from_bytes(&meta.gid),
+        )
+        .add_str("type", libhammer2::subs::get_inode_type_string(meta.typ))
+        .add_u64("op_flags", meta.op_flags.into())
+        .add_u64("cap_flags", meta.cap_flags.into())
+        .add_u64("mode", meta.mode.into())
+        .add_hex("inum", meta.inum)
+        .add_u64("size", meta.size)
+        .add_bool(
+            "embedded",
+            meta.has_direct_data() && meta.size <= libhammer2::fs::HAMMER2_EMBEDDED_BYTES,
+        )
+        .add_u64("nlinks", meta.nlinks)
+        .add_hex("iparent", meta.iparent)
+        .add_hex("name_key", meta.name_key)
+        .add_u64("name_len", meta.name_len.into())
+        .add_u64("ncopies", meta.ncopies.into())
+        .add_str(
+            "comp_algo",
+            libhammer2::subs::get_comp_mode_string(meta.comp_algo),
+        )
+        .add_str(
+            "check_algo",
+            libhammer2::subs::get_check_mode_string(meta.check_algo),
+        );
+    if meta.is_root() {
+        o.add_str(
+            "pfs_subtype",
+            libhammer2::subs::get_pfs_subtype_string(meta.pfs_subtype),
+        )
+        .add_u64("pfs_nmasters", meta.pfs_nmasters.into())
+        .add_str(
+            "pfs_type",
+            libhammer2::subs::get_pfs_type_string(meta.pfs_type),
+        )
+        .add_hex("pfs_inum", meta.pfs_inum)
+        .add_str(
+            "pfs_clid",
+            &libhammer2::subs::get_uuid_string_from_bytes(&meta.pfs_clid),
+        )
+        .add_str(
+            "pfs_fsid",
+            &libhammer2::subs::get_uuid_string_from_bytes(&meta.pfs_fsid),
+        )
+        .add_hex("pfs_lsnap_tid", meta.pfs_lsnap_tid);
+    }
+    o.add_u64("data_quota", meta.data_quota)
+        .add_u64("data_count", stats.data_count)
+        .add_u64("inode_quota", meta.inode_quota)
+        .add_u64("inode_count", stats.inode_count);
+    Ok(o)
+}
+
+fn get_inode_data<'a>(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: &'a [u8],
+) -> hammer2_utils::Result<&'a libhammer2::fs::Hammer2InodeData> {
+    match hammer2_utils::media::media_as_inode_data(media) {
+        Some(v) => Ok(v),
+        None => {
+            log::error!("{:016x}: bad inode size {}", bref.data_off, media.len());
+            Err(Box::new(nix::errno::Errno::EINVAL))
+        }
+    }
+}
+
+fn get_bmap_data(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: &[u8],
+) -> hammer2_utils::Result<Vec<libhammer2::fs::Hammer2BmapData>> {
+    match hammer2_utils::media::media_as_bmap_data(media) {
+        Some(v) => Ok(v),
+        None => {
+            log::error!(
+                "{:016x}: bad freemap leaf size {}",
+                bref.data_off,
+                media.len()
+            );
+            Err(Box::new(nix::errno::Errno::EINVAL))
+        }
+    }
+}
+
 fn count_blocks(
     bmap: &libhammer2::fs::Hammer2BmapData,
     value: usize,
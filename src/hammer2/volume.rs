@@ -0,0 +1,186 @@
+// Helpers for tools which modify volume headers of an unmounted filesystem.
+
+// HAMMER2_ZONE_SEG (4MB) is reserved at the beginning of every 1GB of
+// storage, rounded up.  See newfs_hammer2.
+pub(crate) fn get_reserved_size(total_size: u64) -> u64 {
+    total_size.div_ceil(libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE)
+        * libhammer2::fs::HAMMER2_ZONE_SEG
+}
+
+pub(crate) fn get_total_size(voldata: &libhammer2::fs::Hammer2VolumeData) -> u64 {
+    if voldata.version >= libhammer2::fs::HAMMER2_VOL_VERSION_MULTI_VOLUMES {
+        voldata.total_size
+    } else {
+        voldata.volu_size
+    }
+}
+
+// Get volume relative offsets of reserved areas at level1 boundaries
+// newly covered by growing total size from old_total_size to new_total_size.
+pub(crate) fn get_reserved_offsets(
+    old_total_size: u64,
+    new_total_size: u64,
+    volu_loff: u64,
+) -> Vec<u64> {
+    let mut v = vec![];
+    let mut base = old_total_size.next_multiple_of(libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE);
+    while base < new_total_size {
+        assert!(base >= volu_loff);
+        v.push(base - volu_loff);
+        base += libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE;
+    }
+    v
+}
+
+// Zero reserved areas so that stale data isn't taken as freemap blocks.
+pub(crate) fn zero_reserved(
+    fp: &std::fs::File,
+    reserved: &[u64],
+    volu_size: u64,
+) -> hammer2_utils::Result<()> {
+    let zero = vec![0; libhammer2::fs::HAMMER2_ZONE_SEG.try_into()?];
+    for &x in reserved {
+        let size = std::cmp::min(libhammer2::fs::HAMMER2_ZONE_SEG, volu_size - x);
+        std::os::unix::fs::FileExt::write_all_at(fp, &zero[..size.try_into()?], x)?;
+    }
+    Ok(())
+}
+
+// Read all volume header copies within the given size.
+pub(crate) fn read_volume_headers(
+    fp: &std::fs::File,
+    size: u64,
+) -> hammer2_utils::Result<Vec<(u64, Vec<u8>)>> {
+    let mut v = vec![];
+    for i in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+        let offset = libhammer2::volume::get_volume_data_offset(i);
+        if offset >= size {
+            break;
+        }
+        let mut buf = vec![0; libhammer2::fs::HAMMER2_VOLUME_BYTES.try_into()?];
+        std::os::unix::fs::FileExt::read_exact_at(fp, &mut buf, offset)?;
+        v.push((offset, buf));
+    }
+    Ok(v)
+}
+
+const FREEMAP_BLOCK_SIZE: u64 = 16384; // 2 bits per 16KB in bitmap
+
+// Release 16KB blocks within [beg, end) of a freemap leaf media.
+// Returns the number of blocks released.
+fn free_leaf_range(media: &mut [u8], key: u64, beg: u64, end: u64) -> hammer2_utils::Result<u64> {
+    let size = std::mem::size_of::<libhammer2::fs::Hammer2BmapData>();
+    let mut n = 0;
+    for i in 0..libhammer2::fs::HAMMER2_FREEMAP_COUNT {
+        let base = key + u64::try_from(i)? * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
+        let bmdata = libfs::cast::align_head_to_mut::<libhammer2::fs::Hammer2BmapData>(
+            &mut media[i * size..],
+        );
+        for j in 0..libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE / FREEMAP_BLOCK_SIZE {
+            let x = base + j * FREEMAP_BLOCK_SIZE;
+            if x < beg || x >= end {
+                continue;
+            }
+            let bm = &mut bmdata.bitmapq[usize::try_from(j / 32)?];
+            let shift = (j % 32) * 2;
+            if (*bm >> shift) & 0x03 != 0 {
+                *bm &= !(0x03 << shift);
+                bmdata.avail += u32::try_from(FREEMAP_BLOCK_SIZE)?;
+                n += 1;
+            }
+        }
+    }
+    Ok(n)
+}
+
+// hammer2_freemap_init() in sys/vfs/hammer2/hammer2_freemap.c marks
+// blocks beyond total size as allocated when the kernel creates a leaf,
+// so a leaf of the last partial level1 zone keeps the grown range
+// allocated unless released here.  Zones without a leaf need nothing, as
+// hammer2_freemap_try_alloc() creates FREEMAP_NODE and FREEMAP_LEAF
+// chains on the first allocation within the zone.
+// Returns freemap_blockset of the given root volume header afterwards,
+// as updating a leaf also updates it.
+pub(crate) fn free_leaves(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    best: usize,
+    old_total_size: u64,
+    new_total_size: u64,
+) -> hammer2_utils::Result<Vec<u8>> {
+    let broot = crate::walk::get_root_blockref(fso, libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP)?;
+    let mut v = vec![];
+    crate::walk::walk(
+        fso,
+        &broot,
+        &mut vec![],
+        &mut std::collections::HashSet::new(),
+        &mut |_, bref, _, parents| {
+            if !crate::walk::has_key_range(bref, old_total_size, new_total_size - 1) {
+                return Ok(crate::walk::Walk::Skip);
+            }
+            if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF {
+                v.push(crate::walk::Found {
+                    bref: *bref,
+                    parents: parents.to_vec(),
+                });
+                return Ok(crate::walk::Walk::Skip);
+            }
+            Ok(crate::walk::Walk::Continue)
+        },
+    )?;
+    for found in v {
+        let mut media = crate::walk::read_raw(fso, &found.bref)?;
+        let n = free_leaf_range(&mut media, found.bref.key, old_total_size, new_total_size)?;
+        if n == 0 {
+            continue;
+        }
+        crate::walk::write_raw(fso, &found.bref, &media)?;
+        let mut bref = found.bref;
+        crate::walk::update_check(&mut bref, &media)?;
+        crate::walk::update_parents(fso, &bref, &found.parents)?;
+        println!(
+            "free             {:016x} {}",
+            found.bref.key,
+            libhammer2::subs::get_size_string(n * FREEMAP_BLOCK_SIZE)
+        );
+    }
+    let vol = &fso[usize::from(libhammer2::fs::HAMMER2_ROOT_VOLUME)];
+    let fp = std::fs::File::open(vol.get_path())?;
+    let v = read_volume_headers(&fp, vol.get_size())?;
+    Ok(v[best].1[get_freemap_blockset_range()].to_vec())
+}
+
+// Get the byte range of freemap_blockset in a volume header.
+pub(crate) fn get_freemap_blockset_range() -> std::ops::Range<usize> {
+    let offset = std::mem::offset_of!(libhammer2::fs::Hammer2VolumeData, freemap_blockset);
+    offset..offset + std::mem::size_of::<libhammer2::fs::Hammer2Blockset>()
+}
+
+pub(crate) fn media_as_volume_data_mut(buf: &mut [u8]) -> &mut libhammer2::fs::Hammer2VolumeData {
+    libfs::cast::align_head_to_mut(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_get_reserved_size() {
+        let l1 = libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE;
+        let seg = libhammer2::fs::HAMMER2_ZONE_SEG;
+        assert_eq!(super::get_reserved_size(0), 0);
+        assert_eq!(super::get_reserved_size(1), seg);
+        assert_eq!(super::get_reserved_size(l1 - 1), seg);
+        assert_eq!(super::get_reserved_size(l1), seg);
+        assert_eq!(super::get_reserved_size(l1 + 1), seg * 2);
+        assert_eq!(super::get_reserved_size(l1 * 10), seg * 10);
+    }
+
+    #[test]
+    fn test_get_reserved_offsets() {
+        let l1 = libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE;
+        assert!(super::get_reserved_offsets(l1, l1, 0).is_empty());
+        assert!(super::get_reserved_offsets(l1 / 2, l1, 0).is_empty());
+        assert_eq!(super::get_reserved_offsets(l1 / 2, l1 + 1, 0), [l1]);
+        assert_eq!(super::get_reserved_offsets(l1, l1 * 3, 0), [l1, l1 * 2]);
+        assert_eq!(super::get_reserved_offsets(l1, l1 * 3, l1), [0, l1]);
+    }
+}
@@ -0,0 +1,547 @@
+// Helpers to walk and look up on-media topology of an unmounted filesystem.
+
+// A blockref found during a walk and its location within media of the
+// parent.  The root location refers to the volume header.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Location {
+    pub(crate) bref: libhammer2::fs::Hammer2Blockref,
+    pub(crate) offset: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Walk {
+    Continue,
+    Skip,
+    Stop,
+}
+
+pub(crate) fn get_radix(bref: &libhammer2::fs::Hammer2Blockref) -> u64 {
+    bref.data_off & libhammer2::fs::HAMMER2_OFF_MASK_RADIX
+}
+
+pub(crate) fn get_io_offset(bref: &libhammer2::fs::Hammer2Blockref) -> u64 {
+    bref.data_off & !libhammer2::fs::HAMMER2_OFF_MASK_RADIX
+}
+
+// Parse a blockref type name other than the roots (VOLUME and FREEMAP).
+pub(crate) fn get_blockref_type(s: &str) -> Option<u8> {
+    match s.to_lowercase().as_str() {
+        "inode" => Some(libhammer2::fs::HAMMER2_BREF_TYPE_INODE),
+        "indirect" => Some(libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT),
+        "data" => Some(libhammer2::fs::HAMMER2_BREF_TYPE_DATA),
+        "dirent" => Some(libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT),
+        "freemap_node" => Some(libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE),
+        "freemap_leaf" => Some(libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF),
+        _ => None,
+    }
+}
+
+// Return true if key is within key range of bref.
+pub(crate) fn has_key(bref: &libhammer2::fs::Hammer2Blockref, key: u64) -> bool {
+    key >= bref.key && (bref.keybits >= 64 || ((key - bref.key) >> bref.keybits) == 0)
+}
+
+// Return true if [beg, end] overlaps with key range of bref.
+pub(crate) fn has_key_range(bref: &libhammer2::fs::Hammer2Blockref, beg: u64, end: u64) -> bool {
+    beg <= hammer2_utils::media::get_key_end(bref) && end >= bref.key
+}
+
+// Get a blockref of typ (VOLUME or FREEMAP) which refers to the best
+// root volume header.
+pub(crate) fn get_root_blockref(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    typ: u8,
+) -> hammer2_utils::Result<libhammer2::fs::Hammer2Blockref> {
+    let best = fso.get_best_volume_data()?[usize::from(libhammer2::fs::HAMMER2_ROOT_VOLUME)];
+    let offset = libhammer2::volume::get_volume_data_offset(best.0);
+    let vol = fso.get_root_volume_mut().ok_or(nix::errno::Errno::ENODEV)?;
+    let buf = vol.preadx(libhammer2::fs::HAMMER2_VOLUME_BYTES, offset)?;
+    let mut broot = libhammer2::fs::Hammer2Blockref::new(typ);
+    broot.mirror_tid = libhammer2::ondisk::media_as_volume_data(&buf).mirror_tid;
+    broot.data_off =
+        (vol.get_offset() + offset) | u64::try_from(libhammer2::fs::HAMMER2_PBUFRADIX)?;
+    Ok(broot)
+}
+
+// Read raw (possibly compressed) media of bref.
+pub(crate) fn read_raw(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    bref: &libhammer2::fs::Hammer2Blockref,
+) -> hammer2_utils::Result<Vec<u8>> {
+    let vol = fso
+        .get_volume_mut(bref.data_off)
+        .ok_or(nix::errno::Errno::ENODEV)?;
+    let poff = get_io_offset(bref) - vol.get_offset();
+    Ok(vol.preadx(1 << get_radix(bref), poff)?)
+}
+
+// Write raw media of bref.
+pub(crate) fn write_raw(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    buf: &[u8],
+) -> hammer2_utils::Result<()> {
+    let vol = fso
+        .get_volume_mut(bref.data_off)
+        .ok_or(nix::errno::Errno::ENODEV)?;
+    let poff = get_io_offset(bref) - vol.get_offset();
+    vol.pwrite(buf, poff)?;
+    Ok(())
+}
+
+pub(crate) fn is_compressed(bref: &libhammer2::fs::Hammer2Blockref) -> bool {
+    let comp_algo = libhammer2::fs::dec_comp(bref.methods);
+    comp_algo != libhammer2::fs::HAMMER2_COMP_NONE
+        && comp_algo != libhammer2::fs::HAMMER2_COMP_AUTOZERO
+}
+
+// Recompute check code of bref for media.
+pub(crate) fn update_check(
+    bref: &mut libhammer2::fs::Hammer2Blockref,
+    media: &[u8],
+) -> hammer2_utils::Result<()> {
+    match libhammer2::fs::dec_check(bref.methods) {
+        libhammer2::fs::HAMMER2_CHECK_NONE | libhammer2::fs::HAMMER2_CHECK_DISABLED => (),
+        libhammer2::fs::HAMMER2_CHECK_ISCSI32 => {
+            bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckIscsi>()
+                .value = icrc32::iscsi_crc32(media);
+        }
+        libhammer2::fs::HAMMER2_CHECK_XXHASH64 => {
+            bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckXxhash64>()
+                .value = libhammer2::xxhash::xxh64(media);
+        }
+        libhammer2::fs::HAMMER2_CHECK_SHA192 => {
+            bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckSha256>()
+                .data
+                .copy_from_slice(&libhammer2::sha::sha256(media));
+        }
+        libhammer2::fs::HAMMER2_CHECK_FREEMAP => {
+            bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckFreemap>()
+                .icrc32 = icrc32::iscsi_crc32(media);
+        }
+        v => {
+            log::error!("Unknown check algo {v}");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+    }
+    Ok(())
+}
+
+// Store bref into media of the parents, and update check codes of the
+// parents up to the volume header.  Returns the parents written, the
+// last one being the volume header.
+pub(crate) fn update_parents(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    parents: &[Location],
+) -> hammer2_utils::Result<Vec<libhammer2::fs::Hammer2Blockref>> {
+    let mut v = vec![];
+    let mut child = *bref;
+    for loc in parents.iter().rev() {
+        if is_compressed(&loc.bref) {
+            log::error!("Parent {:016x} is compressed", loc.bref.data_off);
+            return Err(Box::new(nix::errno::Errno::EOPNOTSUPP));
+        }
+        let mut media = read_raw(fso, &loc.bref)?;
+        *libfs::cast::align_head_to_mut(&mut media[loc.offset..]) = child;
+        v.push(loc.bref);
+        if loc.bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME
+            || loc.bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP
+        {
+            hammer2_utils::volume::update_crc(crate::volume::media_as_volume_data_mut(&mut media));
+            write_raw(fso, &loc.bref, &media)?;
+            break;
+        }
+        write_raw(fso, &loc.bref, &media)?;
+        child = loc.bref;
+        update_check(&mut child, &media)?;
+    }
+    Ok(v)
+}
+
+// Get offsets of blockrefs within uncompressed media of bref.
+pub(crate) fn get_blockref_offsets(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: &[u8],
+) -> Vec<usize> {
+    let size = std::mem::size_of::<libhammer2::fs::Hammer2Blockref>();
+    match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+            if hammer2_utils::media::media_as_inode_data(media)
+                .is_none_or(|ipdata| ipdata.meta.has_direct_data())
+            {
+                vec![]
+            } else {
+                let base = std::mem::offset_of!(libhammer2::fs::Hammer2InodeData, u);
+                (0..libhammer2::fs::HAMMER2_SET_COUNT)
+                    .map(|i| base + i * size)
+                    .collect()
+            }
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT
+        | libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE => {
+            (0..media.len() / size).map(|i| i * size).collect()
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME => {
+            if hammer2_utils::media::media_as_volume_data(media).is_none() {
+                return vec![];
+            }
+            let base = std::mem::offset_of!(libhammer2::fs::Hammer2VolumeData, sroot_blockset);
+            (0..libhammer2::fs::HAMMER2_SET_COUNT)
+                .map(|i| base + i * size)
+                .collect()
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP => {
+            if hammer2_utils::media::media_as_volume_data(media).is_none() {
+                return vec![];
+            }
+            let base = std::mem::offset_of!(libhammer2::fs::Hammer2VolumeData, freemap_blockset);
+            (0..libhammer2::fs::HAMMER2_SET_COUNT)
+                .map(|i| base + i * size)
+                .collect()
+        }
+        _ => vec![],
+    }
+}
+
+pub(crate) fn get_blockref_at(media: &[u8], offset: usize) -> libhammer2::fs::Hammer2Blockref {
+    *libfs::cast::align_head_to::<libhammer2::fs::Hammer2Blockref>(&media[offset..])
+}
+
+// Uncompressed media of a blockref visited by walk(), which is read on
+// first access.  Media is empty for DATA blocks, blockrefs without media,
+// and blocks which fail to read.
+pub(crate) struct Media {
+    bref: libhammer2::fs::Hammer2Blockref,
+    buf: Option<Vec<u8>>,
+}
+
+impl Media {
+    pub(crate) fn get(&mut self, fso: &mut libhammer2::ondisk::Ondisk) -> &[u8] {
+        let bref = &self.bref;
+        self.buf.get_or_insert_with(|| {
+            if bref.typ == libhammer2::fs::HAMMER2_BREF_TYPE_DATA || get_radix(bref) == 0 {
+                return vec![];
+            }
+            match fso.read_media(bref) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::warn!("{:016x}: {e}", bref.data_off);
+                    vec![]
+                }
+            }
+        })
+    }
+}
+
+// Walk the topology under bref in depth-first order.  Each media block is
+// visited once.  f gets media to be read on demand and locations of all
+// parents, and returns whether to descend into bref.  Media is read only
+// if f needs it or descends into bref.  Returns true if f stopped the walk.
+pub(crate) fn walk<F>(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    parents: &mut Vec<Location>,
+    visited: &mut std::collections::HashSet<u64>,
+    f: &mut F,
+) -> hammer2_utils::Result<bool>
+where
+    F: FnMut(
+        &mut libhammer2::ondisk::Ondisk,
+        &libhammer2::fs::Hammer2Blockref,
+        &mut Media,
+        &[Location],
+    ) -> hammer2_utils::Result<Walk>,
+{
+    let mut media = Media {
+        bref: *bref,
+        buf: None,
+    };
+    match f(fso, bref, &mut media, parents)? {
+        Walk::Continue => (),
+        Walk::Skip => return Ok(false),
+        Walk::Stop => return Ok(true),
+    }
+    let media = media.get(fso);
+    for offset in get_blockref_offsets(bref, &media) {
+        let child = get_blockref_at(&media, offset);
+        if child.typ == libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY {
+            continue;
+        }
+        if get_radix(&child) != 0 && !visited.insert(child.data_off) {
+            continue;
+        }
+        parents.push(Location {
+            bref: *bref,
+            offset,
+        });
+        let stop = walk(fso, &child, parents, visited, f)?;
+        parents.pop();
+        if stop {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// A blockref and locations of its parents.
+#[derive(Clone, Debug)]
+pub(crate) struct Found {
+    pub(crate) bref: libhammer2::fs::Hammer2Blockref,
+    pub(crate) parents: Vec<Location>,
+}
+
+// Get PFS labels and root inodes under the super root.
+pub(crate) fn get_pfs_roots(
+    fso: &mut libhammer2::ondisk::Ondisk,
+) -> hammer2_utils::Result<Vec<(String, Found)>> {
+    let broot = get_root_blockref(fso, libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME)?;
+    let mut v = vec![];
+    walk(
+        fso,
+        &broot,
+        &mut vec![],
+        &mut std::collections::HashSet::new(),
+        &mut |fso, bref, media, parents| match bref.typ {
+            libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME
+            | libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => Ok(Walk::Continue),
+            libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+                let Some(ipdata) = hammer2_utils::media::media_as_inode_data(media.get(fso)) else {
+                    return Ok(Walk::Skip);
+                };
+                if ipdata.meta.is_sup_root() {
+                    Ok(Walk::Continue)
+                } else {
+                    if ipdata.meta.is_pfs_root() {
+                        v.push((
+                            ipdata.get_filename_string()?,
+                            Found {
+                                bref: *bref,
+                                parents: parents.to_vec(),
+                            },
+                        ));
+                    }
+                    Ok(Walk::Skip)
+                }
+            }
+            _ => Ok(Walk::Skip),
+        },
+    )?;
+    Ok(v)
+}
+
+pub(crate) fn get_pfs_root(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    label: &str,
+) -> hammer2_utils::Result<Found> {
+    for (s, x) in get_pfs_roots(fso)? {
+        if s == label {
+            return Ok(x);
+        }
+    }
+    log::error!("No such PFS {label}");
+    Err(Box::new(nix::errno::Errno::ENOENT))
+}
+
+// Look up a blockref by data_off (with or without radix) under the volume
+// and freemap roots.  The volume header itself isn't a match.
+pub(crate) fn lookup_data_off(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    data_off: u64,
+) -> hammer2_utils::Result<Option<Found>> {
+    let mut found = None;
+    for typ in [
+        libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME,
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP,
+    ] {
+        let broot = get_root_blockref(fso, typ)?;
+        walk(
+            fso,
+            &broot,
+            &mut vec![],
+            &mut std::collections::HashSet::new(),
+            &mut |_, bref, _, parents| {
+                if !parents.is_empty()
+                    && (bref.data_off == data_off || get_io_offset(bref) == data_off)
+                {
+                    found = Some(Found {
+                        bref: *bref,
+                        parents: parents.to_vec(),
+                    });
+                    Ok(Walk::Stop)
+                } else {
+                    Ok(Walk::Continue)
+                }
+            },
+        )?;
+        if found.is_some() {
+            break;
+        }
+    }
+    Ok(found)
+}
+
+// Look up an inode by inode number.  Inodes are indexed by inode number
+// under the PFS root inode.
+pub(crate) fn lookup_inum(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    pfs: &Found,
+    inum: u64,
+) -> hammer2_utils::Result<Option<Found>> {
+    let mut found = None;
+    let mut parents = pfs.parents.clone();
+    walk(
+        fso,
+        &pfs.bref,
+        &mut parents,
+        &mut std::collections::HashSet::new(),
+        &mut |_, bref, _, parents| {
+            if parents.len() == pfs.parents.len() {
+                return Ok(Walk::Continue); // PFS root
+            }
+            match bref.typ {
+                libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT if has_key(bref, inum) => {
+                    Ok(Walk::Continue)
+                }
+                libhammer2::fs::HAMMER2_BREF_TYPE_INODE if bref.key == inum => {
+                    found = Some(Found {
+                        bref: *bref,
+                        parents: parents.to_vec(),
+                    });
+                    Ok(Walk::Stop)
+                }
+                _ => Ok(Walk::Skip),
+            }
+        },
+    )?;
+    if found.is_none() && inum == 1 {
+        // The PFS root inode is the root directory.
+        return Ok(Some(pfs.clone()));
+    }
+    Ok(found)
+}
+
+// Look up a directory entry by name, and return its inode number.
+// Directory entries are keyed by the directory hash of the filename,
+// whose low 15 bits are used to resolve collisions.
+pub(crate) fn lookup_name(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    dir: &Found,
+    name: &[u8],
+) -> hammer2_utils::Result<Option<u64>> {
+    let lhc = libhammer2::subs::dirhash(name);
+    let beg = lhc & !0x7FFF;
+    let end = lhc | 0x7FFF;
+    let mut found = None;
+    let mut parents = dir.parents.clone();
+    walk(
+        fso,
+        &dir.bref,
+        &mut parents,
+        &mut std::collections::HashSet::new(),
+        &mut |fso, bref, media, parents| {
+            if parents.len() == dir.parents.len() {
+                return Ok(Walk::Continue); // directory
+            }
+            match bref.typ {
+                libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT if has_key_range(bref, beg, end) => {
+                    Ok(Walk::Continue)
+                }
+                libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT
+                    if (beg..=end).contains(&bref.key)
+                        && hammer2_utils::media::get_dirent_name(bref, media.get(fso))
+                            == Some(name) =>
+                {
+                    found = Some(bref.embed_as::<libhammer2::fs::Hammer2DirentHead>().inum);
+                    Ok(Walk::Stop)
+                }
+                _ => Ok(Walk::Skip),
+            }
+        },
+    )?;
+    Ok(found)
+}
+
+// Get directory entries of a directory as name, inode number and type.
+pub(crate) fn get_dirents(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    dir: &Found,
+) -> hammer2_utils::Result<Vec<(Vec<u8>, u64, u8)>> {
+    let mut v = vec![];
+    let mut parents = dir.parents.clone();
+    walk(
+        fso,
+        &dir.bref,
+        &mut parents,
+        &mut std::collections::HashSet::new(),
+        &mut |fso, bref, media, parents| {
+            if parents.len() == dir.parents.len() {
+                return Ok(Walk::Continue); // directory
+            }
+            match bref.typ {
+                libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => Ok(Walk::Continue),
+                libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+                    if let Some(name) = hammer2_utils::media::get_dirent_name(bref, media.get(fso))
+                    {
+                        let dirent = bref.embed_as::<libhammer2::fs::Hammer2DirentHead>();
+                        v.push((name.to_vec(), dirent.inum, dirent.typ));
+                    }
+                    Ok(Walk::Skip)
+                }
+                _ => Ok(Walk::Skip),
+            }
+        },
+    )?;
+    Ok(v)
+}
+
+// Look up an inode by path relative to the PFS root.
+pub(crate) fn lookup_path(
+    fso: &mut libhammer2::ondisk::Ondisk,
+    pfs: &Found,
+    path: &str,
+) -> hammer2_utils::Result<Option<Found>> {
+    let mut cur = pfs.clone();
+    for s in path.split('/').filter(|s| !s.is_empty()) {
+        let Some(inum) = lookup_name(fso, &cur, s.as_bytes())? else {
+            return Ok(None);
+        };
+        let Some(v) = lookup_inum(fso, pfs, inum)? else {
+            return Ok(None);
+        };
+        cur = v;
+    }
+    Ok(Some(cur))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_has_key() {
+        let mut bref = libhammer2::fs::Hammer2Blockref::new_empty();
+        bref.key = 0x1000;
+        bref.keybits = 8;
+        assert!(!super::has_key(&bref, 0xFFF));
+        assert!(super::has_key(&bref, 0x1000));
+        assert!(super::has_key(&bref, 0x10FF));
+        assert!(!super::has_key(&bref, 0x1100));
+        bref.key = 0;
+        bref.keybits = 64;
+        assert!(super::has_key(&bref, 0));
+        assert!(super::has_key(&bref, u64::MAX));
+    }
+
+    #[test]
+    fn test_has_key_range() {
+        let mut bref = libhammer2::fs::Hammer2Blockref::new_empty();
+        bref.key = 0x1000;
+        bref.keybits = 8;
+        assert!(!super::has_key_range(&bref, 0, 0xFFF));
+        assert!(super::has_key_range(&bref, 0, 0x1000));
+        assert!(super::has_key_range(&bref, 0x10FF, 0x2000));
+        assert!(!super::has_key_range(&bref, 0x1100, 0x2000));
+        bref.key = 0x8000_0000_0000_0000;
+        bref.keybits = 63;
+        assert!(super::has_key_range(&bref, u64::MAX - 1, u64::MAX));
+    }
+}
@@ -0,0 +1,128 @@
+// Minimal JSON writer for line oriented (NDJSON) output.
+// An object is built in insertion order and printed as a single line,
+// so that large outputs can be streamed without holding them in memory.
+
+#[derive(Clone, Debug, Default)]
+pub struct Object {
+    buf: String,
+}
+
+impl Object {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn add_key(&mut self, k: &str) {
+        self.buf.push(if self.buf.is_empty() { '{' } else { ',' });
+        self.buf.push_str(&quote(k));
+        self.buf.push(':');
+    }
+
+    pub fn add_str(&mut self, k: &str, v: &str) -> &mut Self {
+        self.add_key(k);
+        self.buf.push_str(&quote(v));
+        self
+    }
+
+    pub fn add_u64(&mut self, k: &str, v: u64) -> &mut Self {
+        self.add_key(k);
+        self.buf.push_str(&v.to_string());
+        self
+    }
+
+    // 64 bit offsets, keys and TIDs don't fit in a double,
+    // so they are written as hexadecimal strings.
+    pub fn add_hex(&mut self, k: &str, v: u64) -> &mut Self {
+        self.add_key(k);
+        self.buf.push_str(&format!("\"{v:#018x}\""));
+        self
+    }
+
+    pub fn add_bool(&mut self, k: &str, v: bool) -> &mut Self {
+        self.add_key(k);
+        self.buf.push_str(if v { "true" } else { "false" });
+        self
+    }
+
+    pub fn add_null(&mut self, k: &str) -> &mut Self {
+        self.add_key(k);
+        self.buf.push_str("null");
+        self
+    }
+
+    pub fn add_object(&mut self, k: &str, v: &Object) -> &mut Self {
+        self.add_key(k);
+        self.buf.push_str(&v.to_string());
+        self
+    }
+}
+
+impl std::fmt::Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.buf.is_empty() {
+            write!(f, "{{}}")
+        } else {
+            write!(f, "{}}}", self.buf)
+        }
+    }
+}
+
+#[must_use]
+pub fn quote(s: &str) -> String {
+    let mut v = String::with_capacity(s.len() + 2);
+    v.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => v.push_str("\\\""),
+            '\\' => v.push_str("\\\\"),
+            '\n' => v.push_str("\\n"),
+            '\r' => v.push_str("\\r"),
+            '\t' => v.push_str("\\t"),
+            c if u32::from(c) < 0x20 => v.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => v.push(c),
+        }
+    }
+    v.push('"');
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_quote() {
+        assert_eq!(super::quote(""), "\"\"");
+        assert_eq!(super::quote("abc"), "\"abc\"");
+        assert_eq!(super::quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(super::quote("a\nb\tc"), "\"a\\nb\\tc\"");
+        assert_eq!(super::quote("\u{1}"), "\"\\u0001\"");
+        assert_eq!(super::quote("\u{e9}"), "\"\u{e9}\"");
+    }
+
+    #[test]
+    fn test_object() {
+        let o = super::Object::new();
+        assert!(o.is_empty());
+        assert_eq!(o.to_string(), "{}");
+
+        let mut x = super::Object::new();
+        x.add_u64("a", 1).add_str("b", "x");
+        let mut o = super::Object::new();
+        o.add_hex("off", 0x10)
+            .add_bool("ok", true)
+            .add_null("n")
+            .add_object("x", &x);
+        assert!(!o.is_empty());
+        assert_eq!(
+            o.to_string(),
+            "{\"off\":\"0x0000000000000010\",\"ok\":true,\"n\":null,\"x\":{\"a\":1,\"b\":\"x\"}}"
+        );
+    }
+}
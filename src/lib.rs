@@ -1,4 +1,7 @@
+pub mod json;
+pub mod media;
 pub mod tab;
 pub mod util;
+pub mod volume;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
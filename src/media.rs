@@ -0,0 +1,413 @@
+// Bounds checked accessors for on-media structures.
+// Lengths and offsets taken from media (e.g. namlen, keybits, radix of
+// data_off) can't be trusted on a corrupted image, so these return None
+// where libhammer2 accessors would index out of range.
+
+const TAB_INDENT: usize = 8;
+
+#[must_use]
+pub fn media_as_volume_data(media: &[u8]) -> Option<&libhammer2::fs::Hammer2VolumeData> {
+    if media.len() < std::mem::size_of::<libhammer2::fs::Hammer2VolumeData>() {
+        None
+    } else {
+        Some(libhammer2::ondisk::media_as_volume_data(media))
+    }
+}
+
+#[must_use]
+pub fn media_as_inode_data(media: &[u8]) -> Option<&libhammer2::fs::Hammer2InodeData> {
+    if media.len() < std::mem::size_of::<libhammer2::fs::Hammer2InodeData>() {
+        None
+    } else {
+        Some(libhammer2::ondisk::media_as_inode_data(media))
+    }
+}
+
+// Freemap leaf must contain HAMMER2_FREEMAP_COUNT entries.
+#[must_use]
+pub fn media_as_bmap_data(media: &[u8]) -> Option<Vec<libhammer2::fs::Hammer2BmapData>> {
+    if media.len()
+        < libhammer2::fs::HAMMER2_FREEMAP_COUNT
+            * std::mem::size_of::<libhammer2::fs::Hammer2BmapData>()
+    {
+        None
+    } else {
+        Some(libhammer2::fs::media_as(media))
+    }
+}
+
+// Get filename of a directory entry.  Filenames <= 64 bytes are
+// embedded in bref, otherwise media contains the filename.
+#[must_use]
+pub fn get_dirent_name<'a>(
+    bref: &'a libhammer2::fs::Hammer2Blockref,
+    media: &'a [u8],
+) -> Option<&'a [u8]> {
+    let namelen = usize::from(bref.embed_as::<libhammer2::fs::Hammer2DirentHead>().namlen);
+    if namelen <= bref.check.len() {
+        Some(&bref.check[..namelen])
+    } else if namelen <= media.len() {
+        Some(&media[..namelen])
+    } else {
+        None
+    }
+}
+
+// Get rotation of a freemap node or leaf from its offset within
+// the reserved area of a zone.
+#[must_use]
+pub fn get_freemap_rotation(bref: &libhammer2::fs::Hammer2Blockref) -> Option<usize> {
+    let tmp = (bref.get_raw_data_off() & libhammer2::fs::HAMMER2_SEGMASK)
+        / libhammer2::fs::HAMMER2_PBUFSIZE;
+    let tmp = usize::try_from(tmp).ok()?;
+    if (libhammer2::fs::HAMMER2_ZONE_FREEMAP_00..libhammer2::fs::HAMMER2_ZONE_FREEMAP_END)
+        .contains(&tmp)
+    {
+        Some(
+            (tmp - libhammer2::fs::HAMMER2_ZONE_FREEMAP_00)
+                / libhammer2::fs::HAMMER2_ZONE_FREEMAP_INC,
+        )
+    } else {
+        None
+    }
+}
+
+// Get the last key within key range of bref.
+#[must_use]
+pub fn get_key_end(bref: &libhammer2::fs::Hammer2Blockref) -> u64 {
+    if bref.keybits >= 64 {
+        u64::MAX
+    } else {
+        bref.key.saturating_add((1 << bref.keybits) - 1)
+    }
+}
+
+// Format media of bref by type, one string per line.
+// Types without a decoder (e.g. DATA) result in an empty vector.
+/// # Errors
+#[allow(clippy::too_many_lines)]
+pub fn format_media(
+    tab: usize,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    media: &[u8],
+) -> crate::Result<Vec<String>> {
+    let mut v = vec![];
+    match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+            let ipdata = media_as_inode_data(media).ok_or(nix::errno::Errno::EINVAL)?;
+            let meta = &ipdata.meta;
+            v.push(crate::tab::format!(
+                tab,
+                "filename \"{}\"\n",
+                ipdata.get_filename_string()?
+            ));
+            v.push(crate::tab::format!(tab, "version {}\n", meta.version));
+            if meta.is_root() {
+                v.push(crate::tab::format!(
+                    tab,
+                    "pfs_subtype {} ({})\n",
+                    meta.pfs_subtype,
+                    libhammer2::subs::get_pfs_subtype_string(meta.pfs_subtype)
+                ));
+            }
+            v.push(crate::tab::format!(tab, "uflags {:#010x}\n", meta.uflags));
+            if meta.rmajor != 0 || meta.rminor != 0 {
+                v.push(crate::tab::format!(tab, "rmajor {}\n", meta.rmajor));
+                v.push(crate::tab::format!(tab, "rminor {}\n", meta.rminor));
+            }
+            v.push(crate::tab::format!(
+                tab,
+                "ctime {}\n",
+                libhammer2::subs::get_local_time_string(meta.ctime)
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "mtime {}\n",
+                libhammer2::subs::get_local_time_string(meta.mtime)
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "atime {}\n",
+                libhammer2::subs::get_local_time_string(meta.atime)
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "btime {}\n",
+                libhammer2::subs::get_local_time_string(meta.btime)
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "uid {}\n",
+                libhammer2::subs::get_uuid_string_from_bytes(&meta.uid)
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "gid {}\n",
+                libhammer2::subs::get_uuid_string_from_bytes(&meta.gid)
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "type {}\n",
+                libhammer2::subs::get_inode_type_string(meta.typ)
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "op_flags {:#04x}\n",
+                meta.op_flags
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "cap_flags {:#06x}\n",
+                meta.cap_flags
+            ));
+            v.push(crate::tab::format!(tab, "mode {:<7o}\n", meta.mode));
+            v.push(crate::tab::format!(tab, "inum {:#018x}\n", meta.inum));
+            v.push(crate::tab::format!(tab, "size {} ", meta.size));
+            if meta.has_direct_data() && meta.size <= libhammer2::fs::HAMMER2_EMBEDDED_BYTES {
+                v.push("(embedded data)\n".to_string());
+            } else {
+                v.push("\n".to_string());
+            }
+            v.push(crate::tab::format!(tab, "nlinks {}\n", meta.nlinks));
+            v.push(crate::tab::format!(tab, "iparent {:#018x}\n", meta.iparent));
+            v.push(crate::tab::format!(
+                tab,
+                "name_key {:#018x}\n",
+                meta.name_key
+            ));
+            v.push(crate::tab::format!(tab, "name_len {}\n", meta.name_len));
+            v.push(crate::tab::format!(tab, "ncopies {}\n", meta.ncopies));
+            v.push(crate::tab::format!(
+                tab,
+                "comp_algo {}\n",
+                libhammer2::subs::get_comp_mode_string(meta.comp_algo)
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "check_algo {}\n",
+                libhammer2::subs::get_check_mode_string(meta.check_algo)
+            ));
+            if meta.is_root() {
+                v.push(crate::tab::format!(
+                    tab,
+                    "pfs_nmasters {}\n",
+                    meta.pfs_nmasters
+                ));
+                v.push(crate::tab::format!(
+                    tab,
+                    "pfs_type {} ({})\n",
+                    meta.pfs_type,
+                    libhammer2::subs::get_pfs_type_string(meta.pfs_type)
+                ));
+                v.push(crate::tab::format!(
+                    tab,
+                    "pfs_inum {:#018x}\n",
+                    meta.pfs_inum
+                ));
+                v.push(crate::tab::format!(
+                    tab,
+                    "pfs_clid {}\n",
+                    libhammer2::subs::get_uuid_string_from_bytes(&meta.pfs_clid)
+                ));
+                v.push(crate::tab::format!(
+                    tab,
+                    "pfs_fsid {}\n",
+                    libhammer2::subs::get_uuid_string_from_bytes(&meta.pfs_fsid)
+                ));
+                v.push(crate::tab::format!(
+                    tab,
+                    "pfs_lsnap_tid {:#018x}\n",
+                    meta.pfs_lsnap_tid
+                ));
+            }
+            v.push(crate::tab::format!(tab, "data_quota {}\n", meta.data_quota));
+            v.push(crate::tab::format!(
+                tab,
+                "data_count {}\n",
+                bref.embed_as::<libhammer2::fs::Hammer2BlockrefEmbedStats>()
+                    .data_count
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "inode_quota {}\n",
+                meta.inode_quota
+            ));
+            v.push(crate::tab::format!(
+                tab,
+                "inode_count {}\n",
+                bref.embed_as::<libhammer2::fs::Hammer2BlockrefEmbedStats>()
+                    .inode_count
+            ));
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT
+        | libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_NODE => {
+            for (i, bref) in libhammer2::fs::media_as::<libhammer2::fs::Hammer2Blockref>(media)
+                .iter()
+                .enumerate()
+            {
+                v.push(crate::tab::format!(
+                    tab,
+                    "{i:<3} {:016x} {:<12} {:016x}/{:<2}\n",
+                    bref.data_off,
+                    libhammer2::subs::get_blockref_type_string(bref.typ),
+                    bref.key,
+                    bref.keybits
+                ));
+            }
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+            let dirent = bref.embed_as::<libhammer2::fs::Hammer2DirentHead>();
+            let name = get_dirent_name(bref, media).ok_or(nix::errno::Errno::EINVAL)?;
+            v.push(crate::tab::format!(
+                tab,
+                "filename \"{}\"\n",
+                std::str::from_utf8(name)?
+            ));
+            v.push(crate::tab::format!(tab, "inum {:#018x}\n", dirent.inum));
+            v.push(crate::tab::format!(tab, "namelen {}\n", dirent.namlen));
+            v.push(crate::tab::format!(
+                tab,
+                "type {}\n",
+                libhammer2::subs::get_inode_type_string(dirent.typ)
+            ));
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_VOLUME => {
+            let voldata = media_as_volume_data(media).ok_or(nix::errno::Errno::EINVAL)?;
+            for (name, x) in [
+                ("magic", voldata.magic),
+                ("volu_size", voldata.volu_size),
+                ("total_size", voldata.total_size),
+                ("allocator_size", voldata.allocator_size),
+                ("allocator_free", voldata.allocator_free),
+                ("allocator_beg", voldata.allocator_beg),
+                ("mirror_tid", voldata.mirror_tid),
+                ("freemap_tid", voldata.freemap_tid),
+                ("bulkfree_tid", voldata.bulkfree_tid),
+            ] {
+                v.push(crate::tab::format!(tab, "{name} {x:#018x}\n"));
+            }
+            v.push(crate::tab::format!(tab, "volu_id {}\n", voldata.volu_id));
+            v.push(crate::tab::format!(tab, "nvolumes {}\n", voldata.nvolumes));
+            v.push(crate::tab::format!(tab, "version {}\n", voldata.version));
+            for (name, blockset) in [
+                ("sroot", &voldata.sroot_blockset),
+                ("freemap", &voldata.freemap_blockset),
+            ] {
+                for (i, bref) in blockset.blockref.iter().enumerate() {
+                    v.push(crate::tab::format!(
+                        tab,
+                        "{name}[{i}] {:016x} {:<12} {:016x}/{:<2}\n",
+                        bref.data_off,
+                        libhammer2::subs::get_blockref_type_string(bref.typ),
+                        bref.key,
+                        bref.keybits
+                    ));
+                }
+            }
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF => {
+            let bmdata = media_as_bmap_data(media).ok_or(nix::errno::Errno::EINVAL)?;
+            for i in 0..libhammer2::fs::HAMMER2_FREEMAP_COUNT {
+                let bmdata = &bmdata[i];
+                let data_off = bref
+                    .key
+                    .wrapping_add(u64::try_from(i)? * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE);
+                v.push(crate::tab::format!(
+                    tab,
+                    "{data_off:016x} {i:04}.{:04x} (avail={:07}) \
+                    {:016x} {:016x} {:016x} {:016x} {:016x} {:016x} {:016x} {:016x}\n",
+                    bmdata.class,
+                    bmdata.avail,
+                    bmdata.bitmapq[0],
+                    bmdata.bitmapq[1],
+                    bmdata.bitmapq[2],
+                    bmdata.bitmapq[3],
+                    bmdata.bitmapq[4],
+                    bmdata.bitmapq[5],
+                    bmdata.bitmapq[6],
+                    bmdata.bitmapq[7]
+                ));
+            }
+        }
+        _ => (),
+    }
+    Ok(v)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_media_as_inode_data() {
+        let media = vec![0; libhammer2::fs::HAMMER2_INODE_BYTES.try_into().unwrap()];
+        assert!(super::media_as_inode_data(&media).is_some());
+        assert!(super::media_as_inode_data(&media[1..]).is_none());
+        assert!(super::media_as_inode_data(&[]).is_none());
+    }
+
+    #[test]
+    fn test_media_as_bmap_data() {
+        let media = vec![
+            0;
+            libhammer2::fs::HAMMER2_FREEMAP_COUNT
+                * std::mem::size_of::<libhammer2::fs::Hammer2BmapData>()
+        ];
+        assert_eq!(
+            super::media_as_bmap_data(&media).unwrap().len(),
+            libhammer2::fs::HAMMER2_FREEMAP_COUNT
+        );
+        assert!(super::media_as_bmap_data(&media[1..]).is_none());
+    }
+
+    #[test]
+    fn test_get_dirent_name() {
+        let mut bref =
+            libhammer2::fs::Hammer2Blockref::new(libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT);
+        bref.check[..3].copy_from_slice(b"xyz");
+        bref.embed_as_mut::<libhammer2::fs::Hammer2DirentHead>()
+            .namlen = 3;
+        assert_eq!(super::get_dirent_name(&bref, &[]), Some(&b"xyz"[..]));
+        let media = vec![b'x'; 256];
+        bref.embed_as_mut::<libhammer2::fs::Hammer2DirentHead>()
+            .namlen = 256;
+        assert_eq!(super::get_dirent_name(&bref, &media), Some(&media[..]));
+        // namlen exceeds media size
+        bref.embed_as_mut::<libhammer2::fs::Hammer2DirentHead>()
+            .namlen = 257;
+        assert_eq!(super::get_dirent_name(&bref, &media), None);
+        assert_eq!(super::get_dirent_name(&bref, &[]), None);
+    }
+
+    #[test]
+    fn test_get_freemap_rotation() {
+        let mut bref =
+            libhammer2::fs::Hammer2Blockref::new(libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF);
+        let pbufsize = libhammer2::fs::HAMMER2_PBUFSIZE;
+        let zone00 = u64::try_from(libhammer2::fs::HAMMER2_ZONE_FREEMAP_00).unwrap();
+        let inc = u64::try_from(libhammer2::fs::HAMMER2_ZONE_FREEMAP_INC).unwrap();
+        bref.data_off = (zone00 * pbufsize) | 15;
+        assert_eq!(super::get_freemap_rotation(&bref), Some(0));
+        bref.data_off = ((zone00 + inc) * pbufsize) | 15;
+        assert_eq!(super::get_freemap_rotation(&bref), Some(1));
+        // outside of freemap zones
+        bref.data_off = 15;
+        assert_eq!(super::get_freemap_rotation(&bref), None);
+        let end = u64::try_from(libhammer2::fs::HAMMER2_ZONE_FREEMAP_END).unwrap();
+        bref.data_off = (end * pbufsize) | 15;
+        assert_eq!(super::get_freemap_rotation(&bref), None);
+    }
+
+    #[test]
+    fn test_get_key_end() {
+        let mut bref = libhammer2::fs::Hammer2Blockref::new_empty();
+        bref.key = 0x1000;
+        bref.keybits = 8;
+        assert_eq!(super::get_key_end(&bref), 0x10FF);
+        bref.key = u64::MAX - 1;
+        assert_eq!(super::get_key_end(&bref), u64::MAX);
+        for keybits in [64, 65, 255] {
+            bref.key = 0;
+            bref.keybits = keybits;
+            assert_eq!(super::get_key_end(&bref), u64::MAX);
+        }
+    }
+}
@@ -1,10 +1,12 @@
 mod mkfs;
+mod os;
+mod probe;
 
 fn usage(prog: &str, gopt: &getopts::Options) {
     print!(
         "{}",
         gopt.usage(&format!(
-            "usage: {prog} [-b bootsize] [-r auxsize] \
+            "usage: {prog} [-f] [-E discard|nodiscard] [-b bootsize] [-r auxsize] \
             [-V version] [-L label ...] [-s size] special ..."
         ))
     );
@@ -75,6 +77,23 @@ fn main() {
         size must be 1GiB or larger.",
         "<size>",
     );
+    gopt.optflag(
+        "f",
+        "",
+        "Force formatting. By default newfs_hammer2 refuses to format a \
+        device which contains an existing HAMMER2 volume header or a known \
+        foreign signature, or which has a mounted partition.",
+    );
+    gopt.optopt(
+        "E",
+        "",
+        "Specify extended options. \"discard\" discards (TRIM) the entire \
+        device before formatting, and \"nodiscard\" disables it. Block \
+        devices are discarded and regular files have holes punched. \
+        Discard is enabled by default.",
+        "<extended-options>",
+    );
+    gopt.optflag("", "nodiscard", "Equivalent to -E nodiscard");
     gopt.optflag("d", "", "Enable debug flag");
     gopt.optflag("", "version", "Print version and exit");
     gopt.optflag("", "help", "Print usage and exit");
@@ -173,6 +192,22 @@ fn main() {
             std::process::exit(1);
         }
     }
+    if let Some(v) = matches.opt_str("E") {
+        for s in &v.split(',').collect::<Vec<&str>>() {
+            match *s {
+                "discard" => opt.discard = true,
+                "nodiscard" => opt.discard = false,
+                _ => {
+                    log::error!("Unknown extended option '{s}'");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    if matches.opt_present("nodiscard") {
+        opt.discard = false;
+    }
+    opt.force = matches.opt_present("f");
     opt.debug = matches.opt_present("d");
 
     let args: Vec<&str> = matches.free.iter().map(String::as_str).collect();
@@ -1,3 +1,6 @@
+use std::io::Write;
+use std::os::unix::fs::FileTypeExt;
+
 #[derive(Debug)]
 pub(crate) enum Label {
     #[allow(dead_code)]
@@ -54,6 +57,8 @@ pub(crate) struct Opt {
     pub(crate) comp_type: u8,
     pub(crate) check_type: u8,
     pub(crate) default_label_type: Option<Label>,
+    pub(crate) force: bool,
+    pub(crate) discard: bool,
     pub(crate) debug: bool,
 }
 
@@ -65,6 +70,7 @@ impl Opt {
             comp_type: libhammer2::fs::HAMMER2_COMP_DEFAULT, // default LZ4
             check_type: libhammer2::fs::HAMMER2_CHECK_DEFAULT, // default xxhash64
             default_label_type: None,
+            discard: true,
             volfsid: uuid::Uuid::new_v4(),
             supclid: uuid::Uuid::new_v4(),
             supfsid: uuid::Uuid::new_v4(),
@@ -450,7 +456,6 @@ fn format(
     index: usize,
     free_size: u64,
 ) -> hammer2_utils::Result<()> {
-    const DMSG_PEER_HAMMER2: u8 = 3; // server: h2 mounted volume
     let boot_base = libhammer2::fs::HAMMER2_ZONE_SEG;
     let aux_base = boot_base + opt.boot_area_size;
     let mut alloc_base;
@@ -471,9 +476,6 @@ fn format(
         sroot_blockset.blockref[0] = t.1;
     } else {
         alloc_base = 0;
-        for i in 0..libhammer2::fs::HAMMER2_SET_COUNT {
-            sroot_blockset.blockref[i].typ = libhammer2::fs::HAMMER2_BREF_TYPE_INVALID;
-        }
     }
 
     // Format the volume header.
@@ -481,67 +483,36 @@ fn format(
     // The volume header points to sroot_blockset.  Also be absolutely
     // sure that allocator_beg is set for the root volume.
     let vol = &fso[index];
-    let mut voldata = libhammer2::fs::Hammer2VolumeData::new();
-
-    voldata.magic = libhammer2::fs::HAMMER2_VOLUME_ID_HBO;
+    let volu_loff = (0..fso.get_nvolumes())
+        .map(|i| fso[i].get_offset())
+        .collect::<Vec<_>>();
+    let mut voldata = hammer2_utils::volume::new_volume_data(
+        opt.hammer2_version,
+        vol.get_id(),
+        vol.get_size(),
+        &volu_loff,
+        fso.get_total_size(),
+        libfs::cast::as_u8_slice(&opt.volfsid),
+        libfs::cast::as_u8_slice(&opt.fstype),
+    )?;
     if vol.get_id() == libhammer2::fs::HAMMER2_ROOT_VOLUME.into() {
         voldata.boot_beg = boot_base;
         voldata.boot_end = boot_base + opt.boot_area_size;
         voldata.aux_beg = aux_base;
         voldata.aux_end = aux_base + opt.aux_area_size;
     }
-    voldata.volu_size = vol.get_size();
-    voldata.version = opt.hammer2_version;
-    voldata.flags = 0;
-
-    if voldata.version >= libhammer2::fs::HAMMER2_VOL_VERSION_MULTI_VOLUMES {
-        voldata.volu_id = vol.get_id().try_into()?;
-        voldata.nvolumes = fso.get_nvolumes().try_into()?;
-        voldata.total_size = fso.get_total_size();
-        for i in 0..libhammer2::fs::HAMMER2_MAX_VOLUMES.into() {
-            voldata.volu_loff[i] = if i < fso.get_nvolumes() {
-                fso[i].get_offset()
-            } else {
-                u64::MAX
-            };
-        }
-    }
-
-    voldata
-        .fsid
-        .copy_from_slice(libfs::cast::as_u8_slice(&opt.volfsid));
-    voldata
-        .fstype
-        .copy_from_slice(libfs::cast::as_u8_slice(&opt.fstype));
-
-    voldata.peer_type = DMSG_PEER_HAMMER2; // LNK_CONN identification
 
     assert!(vol.get_id() == libhammer2::fs::HAMMER2_ROOT_VOLUME.into() || alloc_base == 0);
     voldata.allocator_size = free_size;
     if vol.get_id() == libhammer2::fs::HAMMER2_ROOT_VOLUME.into() {
         voldata.allocator_free = free_size;
         voldata.allocator_beg = alloc_base;
+        voldata.sroot_blockset = sroot_blockset;
     }
 
-    voldata.sroot_blockset = sroot_blockset;
     voldata.mirror_tid = 16; // all blockref mirror TIDs set to 16
     voldata.freemap_tid = 16; // all blockref mirror TIDs set to 16
-    voldata.icrc_sects[libhammer2::fs::HAMMER2_VOL_ICRC_SECT1] = voldata.get_crc(
-        libhammer2::fs::HAMMER2_VOLUME_ICRC1_OFF,
-        libhammer2::fs::HAMMER2_VOLUME_ICRC1_SIZE,
-    );
-
-    // Set ICRC_SECT0 after all remaining elements of sect0 have been
-    // populated in the volume header.  Note hat ICRC_SECT* (except for
-    // SECT0) are part of sect0.
-    voldata.icrc_sects[libhammer2::fs::HAMMER2_VOL_ICRC_SECT0] = voldata.get_crc(
-        libhammer2::fs::HAMMER2_VOLUME_ICRC0_OFF,
-        libhammer2::fs::HAMMER2_VOLUME_ICRC0_SIZE,
-    );
-    voldata.icrc_volheader = voldata.get_crc(
-        libhammer2::fs::HAMMER2_VOLUME_ICRCVH_OFF,
-        libhammer2::fs::HAMMER2_VOLUME_ICRCVH_SIZE,
-    );
+    hammer2_utils::volume::update_crc(&mut voldata);
 
     // Write the volume header and all alternates.
     let vol = &mut fso[index];
@@ -579,12 +550,52 @@ fn alloc_direct(
     Ok((base + (1 << radix), bref))
 }
 
+fn discard_volume(f: &str, size: u64) -> hammer2_utils::Result<()> {
+    let fp = std::fs::OpenOptions::new().write(true).open(f)?;
+    let t = fp.metadata()?.file_type();
+    let discard = if t.is_block_device() || t.is_char_device() {
+        crate::os::discard_device
+    } else if t.is_file() {
+        crate::os::punch_hole
+    } else {
+        return Ok(());
+    };
+
+    // Discard in level1 sized chunks so progress can be printed.
+    let chunk_size = libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE;
+    let verbose = size > chunk_size * 16;
+    let mut offset = 0;
+    while offset < size {
+        let len = std::cmp::min(chunk_size, size - offset);
+        if let Err(e) = discard(&fp, offset, len) {
+            // Not an error, some devices or filesystems don't support it.
+            if verbose {
+                println!();
+            }
+            log::warn!("{f}: discard failed at {offset:#x}: {e}");
+            return Ok(());
+        }
+        offset += len;
+        if verbose {
+            print!("Discarding {f}: {:3}%\r", offset * 100 / size);
+            std::io::stdout().flush()?;
+        }
+    }
+    if verbose {
+        println!();
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_lines)]
 pub(crate) fn mkfs(args: &[&str], opt: &mut Opt) -> hammer2_utils::Result<()> {
     let nvolumes = args.len();
     assert!(nvolumes >= 1);
     assert!(nvolumes <= libhammer2::fs::HAMMER2_MAX_VOLUMES.into());
 
+    // Check for existing filesystems and mounted partitions.
+    crate::probe::check_devices(args, opt.force)?;
+
     // Construct volumes information.
     // 1GB alignment (level1 freemap size) for volumes except for the last.
     // For the last volume, typically 8MB alignment to avoid edge cases for
@@ -647,6 +658,15 @@ pub(crate) fn mkfs(args: &[&str], opt: &mut Opt) -> hammer2_utils::Result<()> {
     }
     fso.verify_volumes(false)?;
 
+    // Discard volumes before formatting.  The entire device is discarded
+    // including the unaligned tail beyond the volume size.
+    if opt.discard {
+        for i in 0..nvolumes {
+            let f = fso[i].get_path();
+            discard_volume(f, libhammer2::subs::get_volume_size_from_path(f)?)?;
+        }
+    }
+
     // Adjust options.
     opt.adjust(fso.get_total_size());
 
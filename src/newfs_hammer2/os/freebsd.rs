@@ -0,0 +1,32 @@
+use std::os::fd::AsRawFd;
+
+nix::ioctl_write_ptr!(diocgdelete, b'd', 136, [libc::off_t; 2]);
+
+pub(crate) fn discard_device(fp: &std::fs::File, offset: u64, len: u64) -> nix::Result<()> {
+    let offset = offset
+        .try_into()
+        .map_err(|_| nix::errno::Errno::EOVERFLOW)?;
+    let len = len.try_into().map_err(|_| nix::errno::Errno::EOVERFLOW)?;
+    let range = [offset, len];
+    unsafe { diocgdelete(fp.as_raw_fd(), &range) }?;
+    Ok(())
+}
+
+// fspacectl(2) is only available on FreeBSD 14 or newer.  It may
+// deallocate less than requested, in which case the remaining range
+// is returned.
+pub(crate) fn punch_hole(fp: &std::fs::File, offset: u64, len: u64) -> nix::Result<()> {
+    let mut range = libc::spacectl_range {
+        r_offset: offset
+            .try_into()
+            .map_err(|_| nix::errno::Errno::EOVERFLOW)?,
+        r_len: len.try_into().map_err(|_| nix::errno::Errno::EOVERFLOW)?,
+    };
+    while range.r_len > 0 {
+        let rqsr = range;
+        nix::errno::Errno::result(unsafe {
+            libc::fspacectl(fp.as_raw_fd(), libc::SPACECTL_DEALLOC, &rqsr, 0, &mut range)
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use std::os::fd::AsRawFd;
+
+nix::ioctl_write_ptr_bad!(blkdiscard, nix::request_code_none!(0x12, 119), [u64; 2]);
+
+pub(crate) fn discard_device(fp: &std::fs::File, offset: u64, len: u64) -> nix::Result<()> {
+    let range = [offset, len];
+    unsafe { blkdiscard(fp.as_raw_fd(), &range) }?;
+    Ok(())
+}
+
+pub(crate) fn punch_hole(fp: &std::fs::File, offset: u64, len: u64) -> nix::Result<()> {
+    let offset = offset
+        .try_into()
+        .map_err(|_| nix::errno::Errno::EOVERFLOW)?;
+    let len = len.try_into().map_err(|_| nix::errno::Errno::EOVERFLOW)?;
+    nix::errno::Errno::result(unsafe {
+        libc::fallocate(
+            fp.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset,
+            len,
+        )
+    })?;
+    Ok(())
+}
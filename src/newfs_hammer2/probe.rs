@@ -0,0 +1,394 @@
+use std::os::unix::fs::FileExt;
+
+// Bytes read from the head of each device for foreign signatures.
+// btrfs has its primary superblock at 64KB, so read a bit beyond that.
+const PROBE_BYTES: usize = 128 * 1024;
+
+const HAMMER1_VOLUME_SIGNATURE: u64 = 0xC841_4D4D_C552_3031; // HAMMER_FSBUF_VOLUME
+
+#[derive(Debug, Default)]
+pub(crate) struct ProbeResult {
+    pub(crate) path: String,
+    pub(crate) hammer2: Option<Hammer2Info>,
+    pub(crate) foreign: Vec<&'static str>,
+    pub(crate) mounted: Vec<(String, String)>,
+}
+
+impl ProbeResult {
+    fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.hammer2.is_none() && self.foreign.is_empty() && self.mounted.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Hammer2Info {
+    pub(crate) zone: usize,
+    pub(crate) fsid: String,
+    pub(crate) version: u32,
+    pub(crate) volu_id: u8,
+    pub(crate) nvolumes: u8,
+    pub(crate) labels: Vec<String>,
+}
+
+fn read_at(fp: &std::fs::File, size: usize, offset: u64) -> Option<Vec<u8>> {
+    let mut buf = vec![0; size];
+    fp.read_exact_at(&mut buf, offset).ok()?;
+    Some(buf)
+}
+
+// Read the head of the device, short read is fine for a small device.
+fn read_head(fp: &std::fs::File, size: usize) -> std::io::Result<Vec<u8>> {
+    let mut buf = vec![0; size];
+    let mut n = 0;
+    while n < size {
+        match fp.read_at(&mut buf[n..], n.try_into().unwrap_or(u64::MAX)) {
+            Ok(0) => break,
+            Ok(v) => n += v,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    buf.truncate(n);
+    Ok(buf)
+}
+
+fn has_bytes_at(buf: &[u8], offset: usize, s: &[u8]) -> bool {
+    match buf.get(offset..offset + s.len()) {
+        Some(v) => v == s,
+        None => false,
+    }
+}
+
+// Detect well known non HAMMER2 signatures in the head of the device.
+pub(crate) fn get_foreign_signatures(buf: &[u8]) -> Vec<&'static str> {
+    let mut v = vec![];
+    if has_bytes_at(buf, 0, &HAMMER1_VOLUME_SIGNATURE.to_le_bytes()) {
+        v.push("HAMMER1");
+    }
+    // ext2/3/4 superblock at 1024, s_magic at 56
+    if has_bytes_at(buf, 1024 + 56, &[0x53, 0xef]) {
+        v.push("ext2/ext3/ext4");
+    }
+    if has_bytes_at(buf, 0, b"XFSB") {
+        v.push("XFS");
+    }
+    // btrfs primary superblock at 64KB, magic at 64
+    if has_bytes_at(buf, 65536 + 64, b"_BHRfS_M") {
+        v.push("btrfs");
+    }
+    // swap signature at the end of the first page, try common page sizes
+    for pgsz in [4096, 8192, 16384, 65536] {
+        if has_bytes_at(buf, pgsz - 10, b"SWAPSPACE2")
+            || has_bytes_at(buf, pgsz - 10, b"SWAP-SPACE")
+        {
+            v.push("swap");
+            break;
+        }
+    }
+    // GPT header at LBA 1, try 512 and 4096 byte sectors
+    if has_bytes_at(buf, 512, b"EFI PART") || has_bytes_at(buf, 4096, b"EFI PART") {
+        v.push("GPT");
+    } else if has_bytes_at(buf, 510, &[0x55, 0xaa]) {
+        v.push("MBR/boot sector");
+    }
+    v
+}
+
+fn get_block(fp: &std::fs::File, data_off: u64, size: u64) -> Option<Vec<u8>> {
+    let radix = data_off & libhammer2::fs::HAMMER2_OFF_MASK_RADIX;
+    if radix == 0 || radix > libhammer2::fs::HAMMER2_PBUFRADIX.try_into().ok()? {
+        return None;
+    }
+    let bytes = 1 << radix;
+    let offset = data_off & !libhammer2::fs::HAMMER2_OFF_MASK_RADIX;
+    if offset + bytes > size {
+        return None;
+    }
+    read_at(fp, bytes.try_into().ok()?, offset)
+}
+
+// Collect PFS labels hanging off the super-root.  newfs_hammer2 puts them
+// directly in the super-root blockset, but a mounted filesystem may have
+// pushed them down into indirect blocks.
+fn scan_pfs_labels(
+    fp: &std::fs::File,
+    bref: &libhammer2::fs::Hammer2Blockref,
+    size: u64,
+    depth: usize,
+    labels: &mut Vec<String>,
+) {
+    if depth > 8 {
+        return;
+    }
+    match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+            let Some(media) = get_block(fp, bref.data_off, size) else {
+                return;
+            };
+            if media.len() != std::mem::size_of::<libhammer2::fs::Hammer2InodeData>() {
+                return;
+            }
+            let ipdata = libhammer2::ondisk::media_as_inode_data(&media);
+            if ipdata.meta.is_sup_root() {
+                if ipdata.meta.has_direct_data() {
+                    return;
+                }
+                for bref in &ipdata
+                    .u_as::<libhammer2::fs::Hammer2Blockset>()
+                    .as_blockref()
+                {
+                    scan_pfs_labels(fp, bref, size, depth + 1, labels);
+                }
+            } else if ipdata.meta.is_pfs_root() {
+                if let Ok(v) = ipdata.get_filename_string() {
+                    labels.push(v);
+                }
+            }
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => {
+            let Some(media) = get_block(fp, bref.data_off, size) else {
+                return;
+            };
+            for bref in &libhammer2::fs::media_as::<libhammer2::fs::Hammer2Blockref>(&media) {
+                scan_pfs_labels(fp, bref, size, depth + 1, labels);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn probe_hammer2(fp: &std::fs::File, size: u64) -> Option<Hammer2Info> {
+    let mut best: Option<(usize, Vec<u8>)> = None;
+    for i in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+        let offset = libhammer2::volume::get_volume_data_offset(i);
+        if offset + libhammer2::fs::HAMMER2_VOLUME_BYTES > size {
+            break;
+        }
+        let Some(buf) = read_at(
+            fp,
+            libhammer2::fs::HAMMER2_VOLUME_BYTES.try_into().ok()?,
+            offset,
+        ) else {
+            continue;
+        };
+        let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+        if voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_HBO
+            && voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_ABO
+        {
+            continue;
+        }
+        if let Some((_, ref v)) = best {
+            if libhammer2::ondisk::media_as_volume_data(v).mirror_tid >= voldata.mirror_tid {
+                continue;
+            }
+        }
+        best = Some((i, buf));
+    }
+    let (zone, buf) = best?;
+    let voldata = libhammer2::ondisk::media_as_volume_data(&buf);
+    let mut info = Hammer2Info {
+        zone,
+        fsid: libhammer2::subs::get_uuid_string_from_bytes(&voldata.fsid),
+        version: voldata.version,
+        volu_id: voldata.volu_id,
+        nvolumes: voldata.nvolumes,
+        labels: vec![],
+    };
+    // PFS inodes only live on the root volume.
+    if voldata.volu_id == libhammer2::fs::HAMMER2_ROOT_VOLUME {
+        for bref in &voldata.sroot_blockset.as_blockref() {
+            scan_pfs_labels(fp, bref, size, 0, &mut info.labels);
+        }
+        info.labels.sort();
+        info.labels.dedup();
+    }
+    Some(info)
+}
+
+fn get_mounted_sources() -> hammer2_utils::Result<Vec<(String, String)>> {
+    let mut v = vec![];
+    for t in &libfs::os::get_mnt_info()? {
+        // HAMMER2 sources may look like "/dev/da0:/dev/da1@DATA".
+        let source = match t.mntfromname.find('@') {
+            Some(i) => &t.mntfromname[..i],
+            None => t.mntfromname.as_str(),
+        };
+        for s in source.split(':') {
+            if s.starts_with('/') {
+                v.push((s.to_string(), t.mntonname.clone()));
+            }
+        }
+    }
+    Ok(v)
+}
+
+// Return true if source is the device itself or one of its partitions,
+// e.g. /dev/sda1 or /dev/nvme0n1p1 on Linux, /dev/da0s1a or /dev/da0p1
+// on FreeBSD.
+pub(crate) fn is_same_or_partition(device: &str, source: &str) -> bool {
+    let Some(rest) = source.strip_prefix(device) else {
+        return false;
+    };
+    if rest.is_empty() {
+        return true;
+    }
+    // A device name ending with a digit (e.g. nvme0n1, da1) needs an
+    // explicit separator, otherwise nvme0n10 or da10 is a different disk.
+    let rest = if device.ends_with(|c: char| c.is_ascii_digit()) {
+        match rest.strip_prefix(['p', 's']) {
+            Some(v) => v,
+            None => return false,
+        }
+    } else {
+        rest
+    };
+    rest.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn canonicalize(f: &str) -> String {
+    match std::fs::canonicalize(f) {
+        Ok(v) => v.to_str().unwrap_or(f).to_string(),
+        Err(_) => f.to_string(),
+    }
+}
+
+pub(crate) fn probe(f: &str) -> hammer2_utils::Result<ProbeResult> {
+    let mut res = ProbeResult::new(f);
+    let fp = std::fs::File::open(f)?;
+    let size = libhammer2::subs::get_volume_size_from_path(f)?;
+
+    res.hammer2 = probe_hammer2(&fp, size);
+    res.foreign = get_foreign_signatures(&read_head(&fp, PROBE_BYTES)?);
+
+    let device = canonicalize(f);
+    for (source, target) in get_mounted_sources()? {
+        if is_same_or_partition(&device, &canonicalize(&source)) {
+            res.mounted.push((source, target));
+        }
+    }
+    Ok(res)
+}
+
+pub(crate) fn print_probe_result(res: &ProbeResult) {
+    if let Some(info) = &res.hammer2 {
+        println!(
+            "{}: existing HAMMER2 filesystem (volume {} of {}, header {})",
+            res.path, info.volu_id, info.nvolumes, info.zone
+        );
+        println!("    fsid    {}", info.fsid);
+        println!("    version {}", info.version);
+        for s in &info.labels {
+            println!("    PFS     \"{s}\"");
+        }
+    }
+    for s in &res.foreign {
+        println!("{}: {s} signature found", res.path);
+    }
+    for (source, target) in &res.mounted {
+        println!("{}: {source} is mounted on {target}", res.path);
+    }
+}
+
+// Check each device of the volume set before writing anything to it.
+pub(crate) fn check_devices(args: &[&str], force: bool) -> hammer2_utils::Result<()> {
+    let mut found = false;
+    let mut mounted = false;
+    for f in args {
+        let res = probe(f)?;
+        if !res.is_empty() {
+            print_probe_result(&res);
+            found = true;
+            if !res.mounted.is_empty() {
+                mounted = true;
+            }
+        }
+    }
+    if !found {
+        return Ok(());
+    }
+    if force {
+        log::warn!("Formatting anyway (-f)");
+        return Ok(());
+    }
+    log::error!("Refusing to format, use -f to override");
+    Err(Box::new(if mounted {
+        nix::errno::Errno::EBUSY
+    } else {
+        nix::errno::Errno::EEXIST
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_get_foreign_signatures() {
+        assert!(super::get_foreign_signatures(&[]).is_empty());
+        assert!(super::get_foreign_signatures(&[0; super::PROBE_BYTES]).is_empty());
+
+        let mut buf = vec![0; super::PROBE_BYTES];
+        buf[1024 + 56] = 0x53;
+        buf[1024 + 57] = 0xef;
+        assert_eq!(super::get_foreign_signatures(&buf), ["ext2/ext3/ext4"]);
+
+        let mut buf = vec![0; super::PROBE_BYTES];
+        buf[..4].copy_from_slice(b"XFSB");
+        assert_eq!(super::get_foreign_signatures(&buf), ["XFS"]);
+
+        let mut buf = vec![0; super::PROBE_BYTES];
+        buf[65536 + 64..65536 + 72].copy_from_slice(b"_BHRfS_M");
+        assert_eq!(super::get_foreign_signatures(&buf), ["btrfs"]);
+
+        let mut buf = vec![0; super::PROBE_BYTES];
+        buf[4096 - 10..4096].copy_from_slice(b"SWAPSPACE2");
+        assert_eq!(super::get_foreign_signatures(&buf), ["swap"]);
+
+        let mut buf = vec![0; super::PROBE_BYTES];
+        buf[..8].copy_from_slice(&super::HAMMER1_VOLUME_SIGNATURE.to_le_bytes());
+        assert_eq!(super::get_foreign_signatures(&buf), ["HAMMER1"]);
+
+        let mut buf = vec![0; super::PROBE_BYTES];
+        buf[510] = 0x55;
+        buf[511] = 0xaa;
+        assert_eq!(super::get_foreign_signatures(&buf), ["MBR/boot sector"]);
+        buf[512..520].copy_from_slice(b"EFI PART");
+        assert_eq!(super::get_foreign_signatures(&buf), ["GPT"]);
+
+        // truncated superblock
+        assert!(super::get_foreign_signatures(&[0; 1024 + 57]).is_empty());
+    }
+
+    #[test]
+    fn test_is_same_or_partition() {
+        assert!(super::is_same_or_partition("/dev/sda", "/dev/sda"));
+        assert!(super::is_same_or_partition("/dev/sda", "/dev/sda1"));
+        assert!(super::is_same_or_partition(
+            "/dev/nvme0n1",
+            "/dev/nvme0n1p2"
+        ));
+        assert!(super::is_same_or_partition("/dev/da0", "/dev/da0s1a"));
+        assert!(super::is_same_or_partition("/dev/da0", "/dev/da0p1"));
+
+        assert!(!super::is_same_or_partition("/dev/sda", "/dev/sdb"));
+        assert!(!super::is_same_or_partition("/dev/sda", "/dev/sdaa"));
+        assert!(!super::is_same_or_partition("/dev/sda1", "/dev/sda"));
+        assert!(!super::is_same_or_partition("/dev/da0", "/dev/da0xyz"));
+        assert!(!super::is_same_or_partition(
+            "/dev/nvme0n1",
+            "/dev/nvme0n10"
+        ));
+        assert!(!super::is_same_or_partition(
+            "/dev/nvme0n1",
+            "/dev/nvme0n10p1"
+        ));
+        assert!(!super::is_same_or_partition("/dev/da1", "/dev/da10"));
+        assert!(!super::is_same_or_partition("/dev/da1", "/dev/da10s1"));
+        assert!(!super::is_same_or_partition("/dev/sda", "/dev/sdap1"));
+    }
+}
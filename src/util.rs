@@ -12,6 +12,19 @@ pub fn print_version() {
     println!("{}", get_version_string());
 }
 
+#[must_use]
+pub fn get_chars_per_line() -> usize {
+    if let Some((terminal_size::Width(w), terminal_size::Height(_))) =
+        terminal_size::terminal_size()
+    {
+        w.into()
+    } else if let Ok(v) = std::env::var("COLUMNS") {
+        v.parse().unwrap_or(80)
+    } else {
+        80 // last resort
+    }
+}
+
 /// # Errors
 pub fn init_std_logger() -> Result<(), log::SetLoggerError> {
     let env = env_logger::Env::default().filter_or(
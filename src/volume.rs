@@ -0,0 +1,87 @@
+// Volume header construction shared by newfs_hammer2 and hammer2 directives
+// which format a new volume.
+
+pub const DMSG_PEER_HAMMER2: u8 = 3; // server: h2 mounted volume
+
+// Create a volume header with fields common to all volumes.
+// volu_loff contains offsets of all volumes including this one, and is
+// ignored for versions without multiple volumes support.  Volumes other
+// than the root volume have no sroot_blockset, and the caller sets boot
+// and aux areas, allocator fields, sroot_blockset and TIDs for the root
+// volume.
+/// # Errors
+pub fn new_volume_data(
+    version: u32,
+    volu_id: usize,
+    volu_size: u64,
+    volu_loff: &[u64],
+    total_size: u64,
+    fsid: &[u8],
+    fstype: &[u8],
+) -> crate::Result<libhammer2::fs::Hammer2VolumeData> {
+    let mut voldata = libhammer2::fs::Hammer2VolumeData::new();
+    voldata.magic = libhammer2::fs::HAMMER2_VOLUME_ID_HBO;
+    voldata.volu_size = volu_size;
+    voldata.version = version;
+    voldata.flags = 0;
+
+    if voldata.version >= libhammer2::fs::HAMMER2_VOL_VERSION_MULTI_VOLUMES {
+        voldata.volu_id = volu_id.try_into()?;
+        voldata.nvolumes = volu_loff.len().try_into()?;
+        voldata.total_size = total_size;
+        for i in 0..libhammer2::fs::HAMMER2_MAX_VOLUMES.into() {
+            voldata.volu_loff[i] = volu_loff.get(i).copied().unwrap_or(u64::MAX);
+        }
+    }
+
+    voldata.fsid.copy_from_slice(fsid);
+    voldata.fstype.copy_from_slice(fstype);
+    voldata.peer_type = DMSG_PEER_HAMMER2; // LNK_CONN identification
+
+    if volu_id != libhammer2::fs::HAMMER2_ROOT_VOLUME.into() {
+        for i in 0..libhammer2::fs::HAMMER2_SET_COUNT {
+            voldata.sroot_blockset.blockref[i].typ = libhammer2::fs::HAMMER2_BREF_TYPE_INVALID;
+        }
+    }
+    Ok(voldata)
+}
+
+// Update check codes of the volume header.
+// Set ICRC_SECT0 after all remaining elements of sect0 have been
+// populated in the volume header.  Note that ICRC_SECT* (except for
+// SECT0) are part of sect0.
+pub fn update_crc(voldata: &mut libhammer2::fs::Hammer2VolumeData) {
+    voldata.icrc_sects[libhammer2::fs::HAMMER2_VOL_ICRC_SECT1] = voldata.get_crc(
+        libhammer2::fs::HAMMER2_VOLUME_ICRC1_OFF,
+        libhammer2::fs::HAMMER2_VOLUME_ICRC1_SIZE,
+    );
+    voldata.icrc_sects[libhammer2::fs::HAMMER2_VOL_ICRC_SECT0] = voldata.get_crc(
+        libhammer2::fs::HAMMER2_VOLUME_ICRC0_OFF,
+        libhammer2::fs::HAMMER2_VOLUME_ICRC0_SIZE,
+    );
+    voldata.icrc_volheader = voldata.get_crc(
+        libhammer2::fs::HAMMER2_VOLUME_ICRCVH_OFF,
+        libhammer2::fs::HAMMER2_VOLUME_ICRCVH_SIZE,
+    );
+}
+
+// Check magic and check codes of the volume header.
+#[must_use]
+pub fn is_valid(voldata: &libhammer2::fs::Hammer2VolumeData) -> bool {
+    voldata.magic == libhammer2::fs::HAMMER2_VOLUME_ID_HBO
+        && voldata.icrc_sects[libhammer2::fs::HAMMER2_VOL_ICRC_SECT0]
+            == voldata.get_crc(
+                libhammer2::fs::HAMMER2_VOLUME_ICRC0_OFF,
+                libhammer2::fs::HAMMER2_VOLUME_ICRC0_SIZE,
+            )
+        && voldata.icrc_sects[libhammer2::fs::HAMMER2_VOL_ICRC_SECT1]
+            == voldata.get_crc(
+                libhammer2::fs::HAMMER2_VOLUME_ICRC1_OFF,
+                libhammer2::fs::HAMMER2_VOLUME_ICRC1_SIZE,
+            )
+        && voldata.icrc_volheader
+            == voldata.get_crc(
+                libhammer2::fs::HAMMER2_VOLUME_ICRCVH_OFF,
+                libhammer2::fs::HAMMER2_VOLUME_ICRCVH_SIZE,
+            )
+}
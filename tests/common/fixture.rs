@@ -0,0 +1,554 @@
+// Builder of test images with on-media structures which newfs_hammer2
+// doesn't create.  The root volume of a freshly formatted image is modified
+// in place, and volume header copies are rewritten on finish().  Blocks are
+// allocated after allocator_beg, so everything is on the root volume.
+
+const FREEMAP_BLOCK_SIZE: u64 = 16384; // 2 bits per 16KB in bitmap
+const TIME: u64 = 1_700_000_000_000_000; // fixed timestamps in microseconds
+pub(crate) const ROOT_INUM: u64 = 1;
+
+pub(crate) struct Image {
+    fp: std::fs::File,
+    volhdr: Vec<u8>,
+    alloc: u64,
+    allocated: Vec<(u64, u64)>,
+    tid: u64,
+}
+
+impl Image {
+    // Open the root volume, which is the first one of devpath.
+    pub(crate) fn open(devpath: &str) -> Self {
+        let fp = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(devpath.split(':').next().unwrap())
+            .unwrap();
+        let mut volhdr = vec![0; libhammer2::fs::HAMMER2_VOLUME_BYTES.try_into().unwrap()];
+        std::os::unix::fs::FileExt::read_exact_at(&fp, &mut volhdr, 0).unwrap();
+        let mut image = Self {
+            fp,
+            volhdr,
+            alloc: 0,
+            allocated: vec![],
+            tid: 0,
+        };
+        let voldata = image.voldata();
+        assert_eq!(voldata.magic, libhammer2::fs::HAMMER2_VOLUME_ID_HBO);
+        image.alloc = voldata
+            .allocator_beg
+            .next_multiple_of(libhammer2::fs::HAMMER2_PBUFSIZE);
+        image.tid = voldata.mirror_tid;
+        image
+    }
+
+    pub(crate) fn voldata(&self) -> &libhammer2::fs::Hammer2VolumeData {
+        libhammer2::ondisk::media_as_volume_data(&self.volhdr)
+    }
+
+    pub(crate) fn voldata_mut(&mut self) -> &mut libhammer2::fs::Hammer2VolumeData {
+        libfs::cast::align_head_to_mut(&mut self.volhdr)
+    }
+
+    fn get_total_size(&self) -> u64 {
+        let voldata = self.voldata();
+        if voldata.version >= libhammer2::fs::HAMMER2_VOL_VERSION_MULTI_VOLUMES {
+            voldata.total_size
+        } else {
+            voldata.volu_size
+        }
+    }
+
+    // Start a new transaction, and return its TID.
+    pub(crate) fn next_tid(&mut self) -> u64 {
+        self.tid += 1;
+        self.tid
+    }
+
+    // Allocate a block of power of 2 size, naturally aligned.
+    pub(crate) fn alloc(&mut self, size: u64) -> u64 {
+        assert!(size.is_power_of_two() && size <= libhammer2::fs::HAMMER2_PBUFSIZE);
+        let offset = self.alloc.next_multiple_of(size);
+        self.alloc = offset + size;
+        self.allocated.push((offset, offset + size));
+        offset
+    }
+
+    pub(crate) fn read_at(&self, size: usize, offset: u64) -> Vec<u8> {
+        let mut buf = vec![0; size];
+        std::os::unix::fs::FileExt::read_exact_at(&self.fp, &mut buf, offset).unwrap();
+        buf
+    }
+
+    pub(crate) fn write_at(&self, buf: &[u8], offset: u64) {
+        std::os::unix::fs::FileExt::write_all_at(&self.fp, buf, offset).unwrap();
+    }
+
+    pub(crate) fn read_block(&self, bref: &libhammer2::fs::Hammer2Blockref) -> Vec<u8> {
+        let radix = bref.data_off & libhammer2::fs::HAMMER2_OFF_MASK_RADIX;
+        self.read_at(
+            1 << radix,
+            bref.data_off & !libhammer2::fs::HAMMER2_OFF_MASK_RADIX,
+        )
+    }
+
+    // Allocate and write media of bref, and update its data offset, TIDs
+    // and xxhash64 check code.  Media size must be a power of 2 from 1KB
+    // to 64KB.
+    pub(crate) fn write_block(
+        &mut self,
+        bref: &mut libhammer2::fs::Hammer2Blockref,
+        media: &[u8],
+        tid: u64,
+    ) {
+        let size = u64::try_from(media.len()).unwrap();
+        assert!(size >= 1024);
+        let offset = self.alloc(size);
+        self.write_at(media, offset);
+        bref.data_off = offset | u64::from(size.trailing_zeros());
+        bref.mirror_tid = tid;
+        bref.modify_tid = tid;
+        assert_eq!(
+            libhammer2::fs::dec_check(bref.methods),
+            libhammer2::fs::HAMMER2_CHECK_XXHASH64
+        );
+        bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckXxhash64>()
+            .value = libhammer2::xxhash::xxh64(media);
+    }
+
+    // Get up to HAMMER2_SET_COUNT blockrefs to be stored in a blockset.
+    // Blockrefs which don't fit are stored in indirect blocks, split on
+    // the highest bit in which their keys differ, e.g. inode numbers and
+    // directory hashes in a PFS root are split on bit 63.
+    fn write_blockset(
+        &mut self,
+        brefs: &[libhammer2::fs::Hammer2Blockref],
+        tid: u64,
+    ) -> Vec<libhammer2::fs::Hammer2Blockref> {
+        let mut v = brefs.to_vec();
+        v.sort_by_key(|x| x.key);
+        if v.len() <= libhammer2::fs::HAMMER2_SET_COUNT {
+            return v;
+        }
+        let bit = 63 - (v[0].key ^ v[v.len() - 1].key).leading_zeros();
+        let (lo, hi) = v.split_at(v.partition_point(|x| (x.key >> bit) & 1 == 0));
+        vec![
+            self.write_indirect(lo, bit, tid),
+            self.write_indirect(hi, bit, tid),
+        ]
+    }
+
+    fn write_indirect(
+        &mut self,
+        brefs: &[libhammer2::fs::Hammer2Blockref],
+        keybits: u32,
+        tid: u64,
+    ) -> libhammer2::fs::Hammer2Blockref {
+        let size = std::mem::size_of::<libhammer2::fs::Hammer2Blockref>();
+        let mut media = vec![0; (brefs.len() * size).next_power_of_two().max(1024)];
+        assert!(media.len() <= libhammer2::fs::HAMMER2_PBUFSIZE.try_into().unwrap());
+        for (i, x) in brefs.iter().enumerate() {
+            media[i * size..(i + 1) * size].copy_from_slice(libfs::cast::as_u8_slice(x));
+        }
+        let mut bref = new_blockref(libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT);
+        bref.key = brefs[0].key & !((1 << keybits) - 1);
+        bref.keybits = keybits.try_into().unwrap();
+        self.write_block(&mut bref, &media, tid);
+        bref
+    }
+
+    fn read_sroot(&self) -> Vec<u8> {
+        self.read_block(&self.voldata().sroot_blockset.blockref[0])
+    }
+
+    // Get blockrefs of PFS root inodes under the super root.
+    fn get_pfs_roots(&self) -> Vec<libhammer2::fs::Hammer2Blockref> {
+        let media = self.read_sroot();
+        libhammer2::ondisk::media_as_inode_data(&media)
+            .u_as::<libhammer2::fs::Hammer2Blockset>()
+            .blockref
+            .iter()
+            .filter(|x| x.typ == libhammer2::fs::HAMMER2_BREF_TYPE_INODE)
+            .copied()
+            .collect()
+    }
+
+    // Get a PFS created by newfs_hammer2 to populate.
+    pub(crate) fn pfs(&self, label: &str) -> Pfs {
+        for bref in self.get_pfs_roots() {
+            let media = self.read_block(&bref);
+            let ipdata = libhammer2::ondisk::media_as_inode_data(&media);
+            if ipdata.get_filename_string().unwrap() != label {
+                continue;
+            }
+            assert!(ipdata
+                .u_as::<libhammer2::fs::Hammer2Blockset>()
+                .blockref
+                .iter()
+                .all(|x| x.typ == libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY));
+            let next_inum = ipdata.meta.pfs_inum;
+            let mut inodes = std::collections::BTreeMap::new();
+            inodes.insert(
+                ROOT_INUM,
+                Inode {
+                    media,
+                    data: None,
+                    brefs: vec![],
+                    bref,
+                    dirty: false,
+                },
+            );
+            return Pfs { inodes, next_inum };
+        }
+        panic!("No such PFS {label}");
+    }
+
+    // Replace a PFS root inode with the same key, or add a new one, and
+    // rewrite the super root.
+    fn set_pfs_root(&mut self, bref: libhammer2::fs::Hammer2Blockref, tid: u64) {
+        let mut v = self.get_pfs_roots();
+        v.retain(|x| x.key != bref.key);
+        v.push(bref);
+        v.sort_by_key(|x| x.key);
+        let mut media = self.read_sroot();
+        let blockset =
+            libfs::cast::align_head_to_mut::<libhammer2::fs::Hammer2InodeData>(&mut media)
+                .u_as_mut::<libhammer2::fs::Hammer2Blockset>();
+        assert!(v.len() <= blockset.blockref.len());
+        for (i, x) in blockset.blockref.iter_mut().enumerate() {
+            *x = v
+                .get(i)
+                .copied()
+                .unwrap_or_else(libhammer2::fs::Hammer2Blockref::new_empty);
+        }
+        let mut sroot = self.voldata().sroot_blockset.blockref[0];
+        self.write_block(&mut sroot, &media, tid);
+        self.voldata_mut().sroot_blockset.blockref[0] = sroot;
+    }
+
+    // Create a snapshot of the last committed PFS root.  The snapshot
+    // shares the cluster id with the PFS.
+    pub(crate) fn snapshot(&mut self, pfs: &Pfs, label: &str) -> u64 {
+        let tid = self.next_tid();
+        let root = &pfs.inodes[&ROOT_INUM];
+        assert!(!root.dirty);
+        let mut media = root.media.clone();
+        let ipdata = libfs::cast::align_head_to_mut::<libhammer2::fs::Hammer2InodeData>(&mut media);
+        set_filename(ipdata, label);
+        ipdata.meta.pfs_subtype = libhammer2::fs::HAMMER2_PFSSUBTYPE_SNAPSHOT;
+        ipdata.meta.pfs_lsnap_tid = tid;
+        ipdata.meta.pfs_fsid[0] ^= 0xFF;
+        let mut bref = root.bref;
+        bref.key = ipdata.meta.name_key;
+        self.write_block(&mut bref, &media, tid);
+        self.set_pfs_root(bref, tid);
+        tid
+    }
+
+    // Write freemap leaves the way hammer2_freemap_init() initializes them,
+    // i.e. blocks below SEG-aligned allocator_beg and beyond total size are
+    // allocated, and also mark the reserved area of each zone and blocks
+    // allocated by alloc().  At most HAMMER2_SET_COUNT zones are supported,
+    // as leaves are directly stored in the volume header.
+    pub(crate) fn write_freemap(&mut self) {
+        let total_size = self.get_total_size();
+        let nzones = total_size.div_ceil(libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE);
+        assert!(nzones <= u64::try_from(libhammer2::fs::HAMMER2_SET_COUNT).unwrap());
+        let lokey = self
+            .voldata()
+            .allocator_beg
+            .next_multiple_of(libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE);
+        let hikey = total_size & !(libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE - 1);
+        let tid = self.next_tid();
+        let size = std::mem::size_of::<libhammer2::fs::Hammer2BmapData>();
+        for z in 0..nzones {
+            let base = z * libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE;
+            let mut media = vec![0; libhammer2::fs::HAMMER2_FREEMAP_COUNT * size];
+            for i in 0..libhammer2::fs::HAMMER2_FREEMAP_COUNT {
+                let key =
+                    base + u64::try_from(i).unwrap() * libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE;
+                let bmdata = libfs::cast::align_head_to_mut::<libhammer2::fs::Hammer2BmapData>(
+                    &mut media[i * size..],
+                );
+                if key < lokey || key >= hikey || key == base {
+                    bmdata.bitmapq.fill(u64::MAX);
+                    bmdata.linear = libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE
+                        .try_into()
+                        .unwrap();
+                    continue;
+                }
+                let mut free = 0;
+                for j in 0..libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE / FREEMAP_BLOCK_SIZE {
+                    let x = key + j * FREEMAP_BLOCK_SIZE;
+                    if self
+                        .allocated
+                        .iter()
+                        .any(|(beg, end)| *beg < x + FREEMAP_BLOCK_SIZE && *end > x)
+                    {
+                        bmdata.bitmapq[usize::try_from(j / 32).unwrap()] |= 0x03 << ((j % 32) * 2);
+                        bmdata.linear = (x + FREEMAP_BLOCK_SIZE - key).try_into().unwrap();
+                    } else {
+                        free += FREEMAP_BLOCK_SIZE;
+                    }
+                }
+                bmdata.avail = free.try_into().unwrap();
+            }
+            let offset = base
+                + u64::try_from(libhammer2::fs::HAMMER2_ZONE_FREEMAP_00).unwrap()
+                    * libhammer2::fs::HAMMER2_PBUFSIZE;
+            self.write_at(&media, offset);
+
+            let mut bref = libhammer2::fs::Hammer2Blockref::new(
+                libhammer2::fs::HAMMER2_BREF_TYPE_FREEMAP_LEAF,
+            );
+            bref.key = base;
+            bref.keybits = libhammer2::fs::HAMMER2_FREEMAP_LEVEL1_SIZE
+                .trailing_zeros()
+                .try_into()
+                .unwrap();
+            bref.data_off = offset | u64::from(media.len().trailing_zeros());
+            bref.methods = libhammer2::fs::enc_check(libhammer2::fs::HAMMER2_CHECK_FREEMAP)
+                | libhammer2::fs::enc_comp(libhammer2::fs::HAMMER2_COMP_NONE);
+            bref.mirror_tid = tid;
+            bref.modify_tid = tid;
+            bref.check_as_mut::<libhammer2::fs::Hammer2BlockrefCheckFreemap>()
+                .icrc32 = icrc32::iscsi_crc32(&media);
+            self.voldata_mut().freemap_blockset.blockref[usize::try_from(z).unwrap()] = bref;
+        }
+        self.voldata_mut().freemap_tid = tid;
+    }
+
+    // Write all volume header copies with updated check codes.
+    pub(crate) fn finish(mut self) {
+        let tid = self.tid;
+        let voldata = self.voldata_mut();
+        voldata.mirror_tid = tid;
+        hammer2_utils::volume::update_crc(voldata);
+        let volu_size = self.voldata().volu_size;
+        for i in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+            let offset = libhammer2::volume::get_volume_data_offset(i);
+            if offset >= volu_size {
+                break;
+            }
+            self.write_at(&self.volhdr, offset);
+        }
+        self.fp.sync_all().unwrap();
+    }
+}
+
+pub(crate) fn new_blockref(typ: u8) -> libhammer2::fs::Hammer2Blockref {
+    let mut bref = libhammer2::fs::Hammer2Blockref::new(typ);
+    bref.methods = libhammer2::fs::enc_check(libhammer2::fs::HAMMER2_CHECK_XXHASH64)
+        | libhammer2::fs::enc_comp(libhammer2::fs::HAMMER2_COMP_NONE);
+    bref
+}
+
+fn set_filename(ipdata: &mut libhammer2::fs::Hammer2InodeData, name: &str) {
+    ipdata.filename.fill(0);
+    ipdata.filename[..name.len()].copy_from_slice(name.as_bytes());
+    ipdata.meta.name_len = name.len().try_into().unwrap();
+    ipdata.meta.name_key = libhammer2::subs::dirhash(name.as_bytes());
+}
+
+// An inode and its children to be written on commit.
+struct Inode {
+    media: Vec<u8>,
+    data: Option<Vec<u8>>,                       // file content to write
+    brefs: Vec<libhammer2::fs::Hammer2Blockref>, // DATA or DIRENT blockrefs
+    bref: libhammer2::fs::Hammer2Blockref,       // last written
+    dirty: bool,
+}
+
+impl Inode {
+    fn ipdata(&self) -> &libhammer2::fs::Hammer2InodeData {
+        libhammer2::ondisk::media_as_inode_data(&self.media)
+    }
+
+    fn ipdata_mut(&mut self) -> &mut libhammer2::fs::Hammer2InodeData {
+        self.dirty = true;
+        libfs::cast::align_head_to_mut(&mut self.media)
+    }
+
+    // Write file content and the inode, with additional blockrefs of
+    // inodes if this is the PFS root.
+    fn write(&mut self, image: &mut Image, extra: &[libhammer2::fs::Hammer2Blockref], tid: u64) {
+        let base = std::mem::offset_of!(libhammer2::fs::Hammer2InodeData, u);
+        let embedded = usize::try_from(libhammer2::fs::HAMMER2_EMBEDDED_BYTES).unwrap();
+        if let Some(data) = self.data.take() {
+            let ipdata = self.ipdata_mut();
+            ipdata.meta.size = data.len().try_into().unwrap();
+            ipdata.meta.mtime = TIME + tid * 1_000_000;
+            self.brefs.clear();
+            if data.len() <= embedded {
+                self.ipdata_mut().meta.op_flags |= libhammer2::fs::HAMMER2_OPFLAG_DIRECTDATA;
+                self.media[base..base + embedded].fill(0);
+                self.media[base..base + data.len()].copy_from_slice(&data);
+            } else {
+                self.ipdata_mut().meta.op_flags &= !libhammer2::fs::HAMMER2_OPFLAG_DIRECTDATA;
+                let size = libhammer2::fs::HAMMER2_PBUFSIZE.try_into().unwrap();
+                for (i, chunk) in data.chunks(size).enumerate() {
+                    let mut media = chunk.to_vec();
+                    media.resize(chunk.len().next_power_of_two().max(1024), 0);
+                    let mut bref = new_blockref(libhammer2::fs::HAMMER2_BREF_TYPE_DATA);
+                    bref.key = u64::try_from(i * size).unwrap();
+                    bref.keybits = media.len().trailing_zeros().try_into().unwrap();
+                    image.write_block(&mut bref, &media, tid);
+                    self.brefs.push(bref);
+                }
+            }
+        }
+        if !self.ipdata().meta.has_direct_data() {
+            for x in &mut self.brefs {
+                if x.mirror_tid == 0 {
+                    x.mirror_tid = tid;
+                    x.modify_tid = tid;
+                }
+            }
+            let mut v = self.brefs.clone();
+            v.extend_from_slice(extra);
+            let v = image.write_blockset(&v, tid);
+            let blockset = self
+                .ipdata_mut()
+                .u_as_mut::<libhammer2::fs::Hammer2Blockset>();
+            for (i, x) in blockset.blockref.iter_mut().enumerate() {
+                *x = v
+                    .get(i)
+                    .copied()
+                    .unwrap_or_else(libhammer2::fs::Hammer2Blockref::new_empty);
+            }
+        }
+        let media = self.media.clone();
+        image.write_block(&mut self.bref, &media, tid);
+        self.dirty = false;
+    }
+}
+
+// A PFS to populate with directories and files.  Modified inodes are
+// written to newly allocated blocks on commit, so that blocks of older
+// versions remain on media.
+pub(crate) struct Pfs {
+    inodes: std::collections::BTreeMap<u64, Inode>,
+    next_inum: u64,
+}
+
+impl Pfs {
+    fn add_inode(&mut self, parent: u64, name: &str, typ: u8) -> u64 {
+        let inum = self.next_inum;
+        self.next_inum += 1;
+        let root = self.inodes[&ROOT_INUM].ipdata();
+        let mut ipdata = libhammer2::fs::Hammer2InodeData::new();
+        ipdata.meta.version = libhammer2::fs::HAMMER2_INODE_VERSION_ONE;
+        ipdata.meta.ctime = TIME;
+        ipdata.meta.mtime = TIME;
+        ipdata.meta.btime = TIME;
+        ipdata.meta.typ = typ;
+        ipdata.meta.mode = if typ == libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY {
+            0o755
+        } else {
+            0o644
+        };
+        ipdata.meta.inum = inum;
+        ipdata.meta.iparent = parent;
+        ipdata.meta.nlinks = 1;
+        ipdata.meta.comp_algo = root.meta.comp_algo;
+        ipdata.meta.check_algo = root.meta.check_algo;
+        set_filename(&mut ipdata, name);
+        let mut bref = new_blockref(libhammer2::fs::HAMMER2_BREF_TYPE_INODE);
+        bref.key = inum;
+        self.inodes.insert(
+            inum,
+            Inode {
+                media: libfs::cast::as_u8_slice(&ipdata).to_vec(),
+                data: None,
+                brefs: vec![],
+                bref,
+                dirty: true,
+            },
+        );
+
+        // Directory entry names are embedded in blockrefs.
+        let mut dirent =
+            libhammer2::fs::Hammer2Blockref::new(libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT);
+        assert!(name.len() <= dirent.check.len());
+        dirent.key = libhammer2::subs::dirhash(name.as_bytes());
+        let head = dirent.embed_as_mut::<libhammer2::fs::Hammer2DirentHead>();
+        head.inum = inum;
+        head.namlen = name.len().try_into().unwrap();
+        head.typ = typ;
+        dirent.check[..name.len()].copy_from_slice(name.as_bytes());
+        let dir = self.inodes.get_mut(&parent).unwrap();
+        dir.brefs.push(dirent);
+        dir.ipdata_mut().meta.mtime = TIME;
+        inum
+    }
+
+    pub(crate) fn mkdir(&mut self, parent: u64, name: &str) -> u64 {
+        self.add_inode(parent, name, libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY)
+    }
+
+    // Create a regular file.  Content up to HAMMER2_EMBEDDED_BYTES is
+    // directly stored in the inode.
+    pub(crate) fn create(&mut self, parent: u64, name: &str, data: &[u8]) -> u64 {
+        let inum = self.add_inode(parent, name, libhammer2::fs::HAMMER2_OBJTYPE_REGFILE);
+        self.write(inum, data);
+        inum
+    }
+
+    // Replace content of a regular file.
+    pub(crate) fn write(&mut self, inum: u64, data: &[u8]) {
+        let inode = self.inodes.get_mut(&inum).unwrap();
+        inode.data = Some(data.to_vec());
+        inode.dirty = true;
+    }
+
+    // Remove a directory entry and its inode.
+    pub(crate) fn remove(&mut self, parent: u64, name: &str) {
+        let dir = self.inodes.get_mut(&parent).unwrap();
+        let i = dir
+            .brefs
+            .iter()
+            .position(|x| {
+                let head = x.embed_as::<libhammer2::fs::Hammer2DirentHead>();
+                x.check.get(..usize::from(head.namlen)) == Some(name.as_bytes())
+            })
+            .unwrap();
+        let inum = dir
+            .brefs
+            .remove(i)
+            .embed_as::<libhammer2::fs::Hammer2DirentHead>()
+            .inum;
+        dir.ipdata_mut().meta.mtime = TIME;
+        self.inodes.remove(&inum);
+    }
+
+    // Set compression mode of the inode, which applies to blocks written
+    // by the kernel, not by this fixture.
+    pub(crate) fn set_comp_algo(&mut self, inum: u64, comp: u8) {
+        let inode = self.inodes.get_mut(&inum).unwrap();
+        inode.ipdata_mut().meta.comp_algo = libhammer2::fs::enc_algo(comp);
+    }
+
+    pub(crate) fn get_blockref(&self, inum: u64) -> libhammer2::fs::Hammer2Blockref {
+        self.inodes[&inum].bref
+    }
+
+    // Write modified inodes and the PFS root in a new transaction, and
+    // return its TID.
+    pub(crate) fn commit(&mut self, image: &mut Image) -> u64 {
+        let tid = image.next_tid();
+        let mut v = vec![];
+        for (inum, inode) in &mut self.inodes {
+            if *inum != ROOT_INUM {
+                if inode.dirty {
+                    inode.write(image, &[], tid);
+                }
+                v.push(inode.bref);
+            }
+        }
+        let next_inum = self.next_inum;
+        let root = self.inodes.get_mut(&ROOT_INUM).unwrap();
+        root.ipdata_mut().meta.pfs_inum = next_inum;
+        root.write(image, &v, tid);
+        image.set_pfs_root(root.bref, tid);
+        tid
+    }
+}
@@ -0,0 +1,91 @@
+// Helpers shared by integration tests.
+// Not every test uses all of them.
+#![allow(dead_code)]
+
+pub(crate) mod fixture;
+
+pub(crate) const VOLUME_SIZE: u64 = 1 << 30;
+
+pub(crate) struct TempDir(pub(crate) std::path::PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let d = std::env::temp_dir().join(format!("hammer2-utils-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&d);
+        std::fs::create_dir_all(&d).unwrap();
+        Self(d)
+    }
+
+    pub(crate) fn path(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub(crate) fn command(bin: &str, args: &[&str], envs: &[(&str, &str)]) -> std::process::Output {
+    let mut cmd = std::process::Command::new(bin);
+    cmd.args(args).env("RUST_LOG", "off");
+    for s in [
+        "HAMMER2_SHOW_ALL_VOLUME_HEADERS",
+        "HAMMER2_SHOW_TAB",
+        "HAMMER2_SHOW_DEPTH",
+        "HAMMER2_SHOW_MIN_MIRROR_TID",
+        "HAMMER2_SHOW_MIN_MODIFY_TID",
+    ] {
+        cmd.env_remove(s);
+    }
+    for (k, v) in envs {
+        cmd.env(k, v);
+    }
+    cmd.output().unwrap()
+}
+
+pub(crate) fn run(bin: &str, args: &[&str], envs: &[(&str, &str)]) -> String {
+    let out = command(bin, args, envs);
+    assert!(
+        out.status.success(),
+        "{bin} {args:?} failed\n{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    String::from_utf8(out.stdout).unwrap()
+}
+
+// Create sparse volumes and format them.
+pub(crate) fn newfs(dir: &TempDir, nvolumes: usize, version: Option<&str>) -> String {
+    newfs_sizes(dir, &vec![VOLUME_SIZE; nvolumes], version)
+}
+
+// Same as newfs(), but with the given volume sizes.
+pub(crate) fn newfs_sizes(dir: &TempDir, sizes: &[u64], version: Option<&str>) -> String {
+    let mut paths = vec![];
+    for (i, size) in sizes.iter().enumerate() {
+        let f = dir.path(&format!("vol{i}"));
+        let fp = std::fs::File::create(&f).unwrap();
+        fp.set_len(*size).unwrap();
+        paths.push(f);
+    }
+    let mut args = vec!["--nodiscard"];
+    if let Some(v) = version {
+        args.push("-V");
+        args.push(v);
+    }
+    for f in &paths {
+        args.push(f);
+    }
+    run(env!("CARGO_BIN_EXE_newfs_hammer2"), &args, &[]);
+    paths.join(":")
+}
+
+// Count cells of c in rows of freemap --map output.
+pub(crate) fn count_map_cells(s: &str, c: char) -> usize {
+    s.lines()
+        .filter_map(|x| x.split_once(' '))
+        .filter(|(offset, _)| offset.len() == 16 && offset.chars().all(|x| x.is_ascii_hexdigit()))
+        .map(|(_, cells)| cells.chars().filter(|x| *x == c).count())
+        .sum()
+}
@@ -0,0 +1,48 @@
+// Tests for compstat suggestions and totals.  Blocks written by the
+// fixture are never compressed regardless of compression mode.
+
+mod common;
+
+use common::fixture::{Image, ROOT_INUM};
+use common::{newfs, run, TempDir};
+
+// Get logical and physical columns of the row for the path.
+fn get_row(s: &str, path: &str) -> (String, String) {
+    let v: Vec<_> = s
+        .lines()
+        .find(|x| x.split_whitespace().next() == Some(path))
+        .unwrap()
+        .split_whitespace()
+        .collect();
+    (v[1].to_string(), v[2].to_string())
+}
+
+#[test]
+fn test_compstat() {
+    let dir = TempDir::new("compstat");
+    let devpath = newfs(&dir, 1, None);
+    let mut image = Image::open(&devpath);
+    let mut pfs = image.pfs("DATA");
+    let data = vec![1; 1 << 18];
+    let inum = pfs.mkdir(ROOT_INUM, "lz4");
+    pfs.set_comp_algo(inum, libhammer2::fs::HAMMER2_COMP_LZ4);
+    pfs.create(inum, "file", &data);
+    let inum = pfs.mkdir(ROOT_INUM, "autozero");
+    pfs.set_comp_algo(inum, libhammer2::fs::HAMMER2_COMP_AUTOZERO);
+    pfs.create(inum, "file", &data);
+    let inum = pfs.mkdir(ROOT_INUM, "embedded");
+    pfs.create(inum, "file", b"embedded");
+    pfs.mkdir(ROOT_INUM, "empty");
+    pfs.commit(&mut image);
+    image.finish();
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let root = format!("{devpath}@DATA");
+    let s = run(hammer2, &["compstat", &root], &[]);
+    assert_eq!(s, run(hammer2, &["compstat", &root, "/"], &[]));
+    assert!(s.contains("/lz4: "), "{s}");
+    assert!(!s.contains("/autozero: "), "{s}");
+
+    // Data embedded in inodes counts.
+    assert_ne!(get_row(&s, "/embedded"), get_row(&s, "/empty"), "{s}");
+}
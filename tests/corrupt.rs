@@ -0,0 +1,63 @@
+// Regression tests for corrupted images found by fuzz targets.
+// Read-only directives may fail on these images, but must not panic.
+
+mod common;
+
+use common::fixture::Image;
+use common::{command, newfs, run, TempDir};
+
+const PANIC_EXIT_CODE: i32 = 101;
+
+fn assert_no_panic(bin: &str, args: &[&str]) {
+    let out = command(bin, args, &[]);
+    assert_ne!(
+        out.status.code(),
+        Some(PANIC_EXIT_CODE),
+        "{bin} {args:?} panicked\n{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+fn corrupt(devpath: &str, target: &str, action: &str) {
+    run(
+        env!("CARGO_BIN_EXE_hammer2"),
+        &["corrupt", devpath, target, action],
+        &[],
+    );
+}
+
+// Super root inode which is neither super root nor PFS root.
+#[test]
+fn test_sup_root_pfs_type() {
+    let dir = TempDir::new("corrupt-pfs-type");
+    let devpath = newfs(&dir, 1, None);
+    let bit = (std::mem::offset_of!(libhammer2::fs::Hammer2InodeData, meta)
+        + std::mem::offset_of!(libhammer2::fs::Hammer2InodeMeta, pfs_type))
+        * 8
+        + usize::try_from(libhammer2::fs::HAMMER2_PFSTYPE_SUPROOT.trailing_zeros()).unwrap();
+    corrupt(&devpath, "type=inode:0", &format!("flip:{bit}"));
+
+    let fsck = env!("CARGO_BIN_EXE_fsck_hammer2");
+    assert_no_panic(fsck, &["-p", &devpath]);
+    assert_no_panic(fsck, &["-p", "-P", &devpath]);
+    assert_no_panic(fsck, &[&devpath]);
+    assert_no_panic(env!("CARGO_BIN_EXE_hammer2"), &["show", &devpath]);
+}
+
+// Freemap leaf whose media is all zero.  newfs_hammer2 doesn't create
+// freemap leaves, so they are written first.
+#[test]
+fn test_freemap_leaf_zero() {
+    let dir = TempDir::new("corrupt-freemap-leaf");
+    let devpath = newfs(&dir, 1, None);
+    let mut image = Image::open(&devpath);
+    image.write_freemap();
+    image.finish();
+    corrupt(&devpath, "type=freemap_leaf", "zero");
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    for cmd in ["show", "freemap"] {
+        assert_no_panic(hammer2, &[cmd, &devpath]);
+    }
+    assert_no_panic(env!("CARGO_BIN_EXE_fsck_hammer2"), &["-v", &devpath]);
+}
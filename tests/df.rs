@@ -0,0 +1,60 @@
+// Tests for df space accounting of PFSes and snapshots.
+
+mod common;
+
+use common::fixture::{Image, ROOT_INUM};
+use common::{newfs, run, TempDir};
+
+// Parse a size string, e.g. 1.50KB, into bytes.
+fn parse_size(s: &str) -> f64 {
+    for (unit, n) in [
+        ("TB", 1_099_511_627_776.0),
+        ("GB", 1_073_741_824.0),
+        ("MB", 1_048_576.0),
+        ("KB", 1024.0),
+    ] {
+        if let Some(v) = s.strip_suffix(unit) {
+            return v.parse::<f64>().unwrap() * n;
+        }
+    }
+    s.strip_suffix('B').unwrap().parse().unwrap()
+}
+
+// Get unique and shared columns of the PFS.
+fn get_unique_shared(s: &str, label: &str) -> (String, String) {
+    let v: Vec<_> = s
+        .lines()
+        .find(|x| x.split_whitespace().next() == Some(label))
+        .unwrap()
+        .split_whitespace()
+        .collect();
+    (v[6].to_string(), v[7].to_string())
+}
+
+// A file written after the snapshot is unique to the PFS, and a file
+// written before it is shared by the PFS and the snapshot.
+#[test]
+fn test_df_snapshot() {
+    let dir = TempDir::new("df-snapshot");
+    let devpath = newfs(&dir, 1, None);
+    let mut image = Image::open(&devpath);
+    let mut pfs = image.pfs("DATA");
+    pfs.create(ROOT_INUM, "old", &vec![1; 100_000]);
+    pfs.commit(&mut image);
+    image.snapshot(&pfs, "DATA.snap");
+    pfs.create(ROOT_INUM, "new", &vec![2; 200_000]);
+    pfs.commit(&mut image);
+    image.finish();
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let s = run(hammer2, &["df", &devpath], &[]);
+    assert!(s.contains("Total physical storage: "), "{s}");
+    let (unique, shared) = get_unique_shared(&s, "DATA");
+    let (snap_unique, snap_shared) = get_unique_shared(&s, "DATA.snap");
+    assert!(parse_size(&unique) >= 200_000.0, "{s}");
+    assert!(parse_size(&shared) >= 100_000.0, "{s}");
+    assert!(parse_size(&snap_unique) < 100_000.0, "{s}");
+    assert_eq!(shared, snap_shared, "{s}");
+    let (_, shared) = get_unique_shared(&s, "LOCAL");
+    assert!(parse_size(&shared) < 1.0, "{s}");
+}
@@ -0,0 +1,118 @@
+// Tests for dumpblock against volume headers and a blockref found in them.
+
+mod common;
+
+use common::{command, newfs, newfs_sizes, run, TempDir, VOLUME_SIZE};
+
+// Get the data offset of the first super root blockref from dumpblock
+// output of the volume header.
+fn get_sroot(s: &str) -> String {
+    let sroot = s
+        .lines()
+        .find_map(|x| x.strip_prefix("sroot[0] "))
+        .and_then(|x| x.split_whitespace().next())
+        .unwrap();
+    format!("0x{sroot}")
+}
+
+#[test]
+fn test_dumpblock() {
+    let dir = TempDir::new("dumpblock");
+    let devpath = newfs(&dir, 1, None);
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let s = run(
+        hammer2,
+        &["--format", "decode", "dumpblock", &devpath, "0"],
+        &[],
+    );
+    assert!(s.contains(" check=ok"), "{s}");
+    let sroot = get_sroot(&s);
+
+    // The super root inode is verified against the blockref of its parent.
+    let s = run(
+        hammer2,
+        &[
+            "--bref-from-parent",
+            "--format",
+            "decode",
+            "dumpblock",
+            &devpath,
+            &sroot,
+        ],
+        &[],
+    );
+    assert!(s.contains(" check=ok"), "{s}");
+    let out = command(
+        hammer2,
+        &["--format", "raw", "dumpblock", &devpath, &sroot],
+        &[],
+    );
+    assert!(out.status.success());
+    assert_eq!(out.stdout.len(), 1024);
+}
+
+#[test]
+fn test_dumpblock_check() {
+    let dir = TempDir::new("dumpblock-check");
+    let devpath = newfs(&dir, 1, None);
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let s = run(hammer2, &["dumpblock", &devpath, "0"], &[]);
+    let sroot = get_sroot(&run(
+        hammer2,
+        &["--format", "decode", "dumpblock", &devpath, "0"],
+        &[],
+    ));
+    assert!(s.contains(" check=ok"), "{s}");
+
+    // Without the parent, the block is verified against the given check code.
+    let out = command(
+        hammer2,
+        &["--format", "raw", "dumpblock", &devpath, &sroot],
+        &[],
+    );
+    assert!(out.status.success());
+    let xxh = libhammer2::xxhash::xxh64(&out.stdout);
+    let s = run(hammer2, &["dumpblock", &devpath, &sroot], &[]);
+    assert!(s.contains(" meth=none|none check=none"), "{s}");
+    let check = format!("xxhash64:{xxh:016x}");
+    let s = run(
+        hammer2,
+        &["--check", &check, "dumpblock", &devpath, &sroot],
+        &[],
+    );
+    assert!(s.contains(" meth=xxhash64|none check=ok"), "{s}");
+    let check = format!("xxhash64:{:016x}", xxh ^ 1);
+    let s = run(
+        hammer2,
+        &["--check", &check, "dumpblock", &devpath, &sroot],
+        &[],
+    );
+    assert!(s.contains(" check=failed"), "{s}");
+    for check in ["xxhash64", "none:0", "xxhash64:xyz"] {
+        let out = command(
+            hammer2,
+            &["--check", check, "dumpblock", &devpath, &sroot],
+            &[],
+        );
+        assert!(!out.status.success(), "{check}");
+    }
+}
+
+#[test]
+fn test_dumpblock_volume_check() {
+    let dir = TempDir::new("dumpblock-volume-check");
+    let devpath = newfs_sizes(&dir, &[5 * VOLUME_SIZE], None);
+
+    // A volume header is verified against its icrc sections.
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let offset = libhammer2::volume::get_volume_data_offset(1);
+    let data_off = format!("{offset:#x}");
+    let s = run(hammer2, &["dumpblock", &devpath, &data_off], &[]);
+    assert!(s.contains(" check=ok"), "{s}");
+    let fp = std::fs::File::options().write(true).open(&devpath).unwrap();
+    std::os::unix::fs::FileExt::write_all_at(&fp, &[0xff], offset + 8).unwrap();
+    let s = run(hammer2, &["dumpblock", &devpath, &data_off], &[]);
+    assert!(s.contains(" check=failed"), "{s}");
+}
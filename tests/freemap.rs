@@ -0,0 +1,88 @@
+// Tests for freemap --histogram and --map against images with and without
+// freemap leaves.  newfs_hammer2 doesn't create freemap leaves, which the
+// kernel creates on mount.
+
+mod common;
+
+use common::fixture::Image;
+use common::{count_map_cells, newfs, run, TempDir, VOLUME_SIZE};
+
+// Get free space of all zones in GB from freemap --histogram output.
+fn get_total_free(s: &str) -> f64 {
+    s.lines()
+        .skip_while(|x| *x != "total")
+        .find_map(|x| x.trim_start().strip_prefix("free "))
+        .and_then(|x| x.strip_suffix("GB"))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+// Zones without a freemap leaf are entirely free, except for reserved
+// areas, so writing leaves only marks blocks near allocator_beg allocated.
+#[test]
+fn test_freemap_histogram_leafless() {
+    let dir = TempDir::new("freemap-histogram-leafless");
+    let devpath = newfs(&dir, 1, None);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let s = run(hammer2, &["--histogram", "freemap", &devpath], &[]);
+    let free = get_total_free(&s);
+    assert!(free > 0.9, "{s}");
+    assert!(s.contains("zone 0 "), "{s}");
+
+    let mut image = Image::open(&devpath);
+    image.write_freemap();
+    image.finish();
+    let s = run(hammer2, &["--histogram", "freemap", &devpath], &[]);
+    let free_leaf = get_total_free(&s);
+    assert!(free_leaf <= free && free - free_leaf < 0.01, "{free} {s}");
+}
+
+// A cell is a 4MB LEVEL0 chunk for a 1GB volume.  The reserved segment
+// is reserved with or without freemap leaves, and blocks near
+// allocator_beg become allocated once leaves are written.
+#[test]
+fn test_freemap_map() {
+    let dir = TempDir::new("freemap-map");
+    let devpath = newfs(&dir, 1, None);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let envs = [("COLUMNS", "80")];
+    let ncells =
+        usize::try_from(VOLUME_SIZE / libhammer2::fs::HAMMER2_FREEMAP_LEVEL0_SIZE).unwrap();
+    let count = |s: &str| ['.', ':', '#', 'R'].map(|c| count_map_cells(s, c));
+
+    let s = run(hammer2, &["--map", "freemap", &devpath], &envs);
+    let [free, _, allocated, reserved] = count(&s);
+    assert_eq!(count(&s).iter().sum::<usize>(), ncells, "{s}");
+    assert!(reserved > 0, "{s}");
+    assert_eq!(allocated, 0, "{s}");
+    assert!(free > reserved, "{s}");
+
+    let mut image = Image::open(&devpath);
+    image.write_freemap();
+    image.finish();
+    let s = run(hammer2, &["--map", "freemap", &devpath], &envs);
+    let v = count(&s);
+    assert_eq!(v.iter().sum::<usize>(), ncells, "{s}");
+    assert_eq!(v[3], reserved, "{s}");
+    assert!(v[2] > 0, "{s}");
+
+    // Same cells in SVG, plus one of each state in the legend.
+    let svg = dir.path("map.svg");
+    run(
+        hammer2,
+        &["--map", "--svg", &svg, "freemap", &devpath],
+        &envs,
+    );
+    let s = std::fs::read_to_string(&svg).unwrap();
+    assert!(s.starts_with("<svg "), "{s}");
+    assert!(s.contains(">vol=0 "), "{s}");
+    assert!(s.trim_end().ends_with("</svg>"), "{s}");
+    for (color, n) in [("#4a78c2", v[2]), ("#707070", v[3])] {
+        let rects = s
+            .lines()
+            .filter(|x| x.starts_with("<rect ") && x.contains(&format!("fill=\"{color}\"")))
+            .count();
+        assert_eq!(rects, n + 1, "{color}\n{s}");
+    }
+}
@@ -0,0 +1,301 @@
+// Golden output tests for read-only directives.  Images are created by
+// newfs_hammer2 in a temporary directory and populated with directories,
+// files and a snapshot, and output of each directive is normalized and
+// compared against tests/golden/<name>.out.  Assertions on the output
+// other than golden files belong to tests of each directive.
+//
+// Directives run as the built binaries rather than in-process, as they are
+// separate crates without a library API, and print via println!, which
+// libtest captures per thread and doesn't make available to tests.
+//
+// Run with HAMMER2_GOLDEN_BLESS=1 to (re)generate golden files.
+// A missing golden file fails the test.
+
+mod common;
+
+use common::fixture::{Image, ROOT_INUM};
+use common::{command, newfs, run, TempDir};
+
+const BLESS: &str = "HAMMER2_GOLDEN_BLESS";
+
+fn is_uuid(s: &str) -> bool {
+    let v: Vec<&str> = s.split('-').collect();
+    v.len() == 5
+        && v.iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(x, n)| x.len() == n && x.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// Mask values which differ across runs, i.e. paths, uuids, timestamps,
+// and check codes which depend on them.  Spacing is preserved.
+fn normalize(s: &str, dir: &TempDir) -> String {
+    let prefix = dir.0.to_str().unwrap();
+    let mut v = vec![];
+    for line in s.lines() {
+        let line = line.replace(prefix, "<dir>");
+        let trimmed = line.trim_start();
+        // skip a marker of differing rows (volhdr --compare)
+        let key = trimmed.trim_start_matches("* ");
+        let key = key.split(' ').next().unwrap_or_default();
+        if matches!(key, "ctime" | "mtime" | "atime" | "btime") {
+            let n = line.len() - trimmed.len() + key.len();
+            let value = line[n..].trim_start();
+            v.push(format!("{}<time>", &line[..line.len() - value.len()]));
+            continue;
+        }
+        let is_icrc = key.starts_with("icrc_");
+        let mut tokens = vec![];
+        for t in line.split(' ') {
+            tokens.push(if is_uuid(t) {
+                "<uuid>".to_string()
+            } else if is_icrc && t.starts_with("0x") {
+                "<crc>".to_string()
+            } else if let Some((k, _)) = t.split_once('=') {
+                if matches!(k, "xxh" | "icrc" | "fcrc") {
+                    format!("{k}=<check>")
+                } else {
+                    t.to_string()
+                }
+            } else {
+                t.to_string()
+            });
+        }
+        v.push(tokens.join(" "));
+    }
+    v.join("\n") + "\n"
+}
+
+// Same as normalize(), but for NDJSON output.
+fn normalize_json(s: &str) -> String {
+    let mut v = vec![];
+    for line in s.lines() {
+        let mut tokens: Vec<String> = line.split('"').map(str::to_string).collect();
+        for i in 0..tokens.len() {
+            if is_uuid(&tokens[i]) {
+                "<uuid>".clone_into(&mut tokens[i]);
+            } else if i > 0
+                && matches!(
+                    tokens[i - 1].as_str(),
+                    "ctime" | "mtime" | "atime" | "btime"
+                )
+            {
+                let t = tokens[i].trim_start_matches(':');
+                let t = t.trim_start_matches(|c: char| c.is_ascii_digit());
+                tokens[i] = format!(":<time>{t}");
+            }
+        }
+        v.push(tokens.join("\""));
+    }
+    v.join("\n") + "\n"
+}
+
+fn check_golden(name: &str, output: &str) {
+    let f = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.out"));
+    if std::env::var(BLESS).is_ok() {
+        std::fs::create_dir_all(f.parent().unwrap()).unwrap();
+        std::fs::write(&f, output).unwrap();
+        return;
+    }
+    let Ok(expected) = std::fs::read_to_string(&f) else {
+        panic!("{name}: missing {f:?}\nrerun with {BLESS}=1 to generate it");
+    };
+    if expected != output {
+        let (i, (a, b)) = expected
+            .lines()
+            .zip(output.lines())
+            .enumerate()
+            .find(|(_, (a, b))| a != b)
+            .unwrap_or((0, ("", "")));
+        panic!(
+            "{name}: output differs from {f:?} at line {}\nexpected: {a}\nactual:   {b}\n\
+            rerun with {BLESS}=1 if the change is intended",
+            i + 1
+        );
+    }
+}
+
+const SHOW_ENVS: &[(&str, &[(&str, &str)])] = &[
+    ("default", &[]),
+    ("all", &[("HAMMER2_SHOW_ALL_VOLUME_HEADERS", "1")]),
+    ("tab4", &[("HAMMER2_SHOW_TAB", "4")]),
+    ("depth1", &[("HAMMER2_SHOW_DEPTH", "1")]),
+    ("mirror", &[("HAMMER2_SHOW_MIN_MIRROR_TID", "11")]),
+    ("modify", &[("HAMMER2_SHOW_MIN_MODIFY_TID", "11")]),
+];
+
+// Command line options equivalent to SHOW_ENVS, which share golden files.
+const SHOW_OPTS: &[(&str, &[&str])] = &[
+    ("all", &["--all-headers"]),
+    ("tab4", &["--tab", "4"]),
+    ("depth1", &["--depth", "1"]),
+    ("mirror", &["--min-mirror-tid", "0x11"]),
+    ("modify", &["--min-modify-tid", "11"]),
+];
+
+// Populate the DATA PFS.  The PFS root has more than HAMMER2_SET_COUNT
+// blockrefs, so inodes and directory entries are in indirect blocks.
+fn populate(devpath: &str) {
+    let mut image = Image::open(devpath);
+    let mut pfs = image.pfs("DATA");
+    let dir = pfs.mkdir(ROOT_INUM, "dir");
+    pfs.create(dir, "embedded", b"embedded data\n");
+    pfs.create(dir, "large", &vec![0x5a; 100_000]);
+    let sub = pfs.mkdir(dir, "sub");
+    pfs.create(sub, "file", &vec![0xa5; 4096]);
+    pfs.create(ROOT_INUM, "file", b"");
+    pfs.commit(&mut image);
+    image.snapshot(&pfs, "DATA.snap");
+    pfs.create(ROOT_INUM, "new", b"created after snapshot\n");
+    pfs.commit(&mut image);
+    image.write_freemap();
+    image.finish();
+}
+
+fn test_image(name: &str, nvolumes: usize, version: Option<&str>) {
+    let dir = TempDir::new(name);
+    let devpath = newfs(&dir, nvolumes, version);
+    populate(&devpath);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+
+    for cmd in ["show", "freemap", "volhdr"] {
+        for (env_name, envs) in SHOW_ENVS {
+            let s = run(hammer2, &[cmd, &devpath], envs);
+            check_golden(&format!("{name}-{cmd}-{env_name}"), &normalize(&s, &dir));
+        }
+        for (opt_name, opts) in SHOW_OPTS {
+            let mut args = opts.to_vec();
+            args.extend([cmd, devpath.as_str()]);
+            let s = run(hammer2, &args, &[]);
+            check_golden(&format!("{name}-{cmd}-{opt_name}"), &normalize(&s, &dir));
+        }
+    }
+    let s = run(hammer2, &["--type", "inode,dirent", "show", &devpath], &[]);
+    check_golden(&format!("{name}-show-type"), &normalize(&s, &dir));
+    let s = run(
+        hammer2,
+        &["--pfs", "DATA", "--tab", "0", "show", &devpath],
+        &[],
+    );
+    check_golden(&format!("{name}-show-pfs"), &normalize(&s, &dir));
+    let s = run(hammer2, &["--histogram", "freemap", &devpath], &[]);
+    check_golden(&format!("{name}-freemap-histogram"), &normalize(&s, &dir));
+    let svg = dir.path("map.svg");
+    let s = run(
+        hammer2,
+        &["--map", "--svg", &svg, "freemap", &devpath],
+        &[("COLUMNS", "80")],
+    );
+    check_golden(&format!("{name}-freemap-map"), &normalize(&s, &dir));
+    let s = run(hammer2, &["--compare", "volhdr", &devpath], &[]);
+    check_golden(&format!("{name}-volhdr-compare"), &normalize(&s, &dir));
+    let s = run(hammer2, &["df", &devpath], &[]);
+    check_golden(&format!("{name}-df"), &normalize(&s, &dir));
+    let root = format!("{devpath}@DATA");
+    let s = run(hammer2, &["show", &root], &[]);
+    check_golden(&format!("{name}-show-root"), &normalize(&s, &dir));
+    let s = run(hammer2, &["compstat", &root], &[]);
+    check_golden(&format!("{name}-compstat"), &normalize(&s, &dir));
+    let s = run(hammer2, &["history", &root], &[]);
+    check_golden(&format!("{name}-history"), &normalize(&s, &dir));
+    let s = run(
+        hammer2,
+        &["--format", "decode", "dumpblock", &devpath, "0"],
+        &[],
+    );
+    check_golden(&format!("{name}-dumpblock-volhdr"), &normalize(&s, &dir));
+    // super root inode via the first blockref of the volume header
+    let sroot = s
+        .lines()
+        .find_map(|x| x.strip_prefix("sroot[0] "))
+        .and_then(|x| x.split_whitespace().next())
+        .unwrap();
+    let sroot = format!("0x{sroot}");
+    let s = run(
+        hammer2,
+        &[
+            "--bref-from-parent",
+            "--format",
+            "decode",
+            "dumpblock",
+            &devpath,
+            &sroot,
+        ],
+        &[],
+    );
+    check_golden(&format!("{name}-dumpblock-sroot"), &normalize(&s, &dir));
+    for (opt_name, opts) in [
+        ("default", &[][..]),
+        ("best", &["-b"]),
+        ("pfs", &["-p", "-P"]),
+    ] {
+        let mut args = opts.to_vec();
+        args.push(&devpath);
+        let s = run(env!("CARGO_BIN_EXE_fsck_hammer2"), &args, &[]);
+        check_golden(&format!("{name}-fsck-{opt_name}"), &normalize(&s, &dir));
+    }
+    let s = run(hammer2, &["--json", "show", &devpath], &[]);
+    check_golden(&format!("{name}-show-json"), &normalize_json(&s));
+    let s = run(hammer2, &["--dot", "show", &devpath], &[]);
+    check_golden(&format!("{name}-show-dot"), &s);
+}
+
+#[test]
+fn test_single_volume() {
+    test_image("single", 1, None);
+}
+
+#[test]
+fn test_single_volume_v1() {
+    test_image("single-v1", 1, Some("1"));
+}
+
+#[test]
+fn test_multi_volumes() {
+    test_image("multi", 2, None);
+}
+
+#[test]
+fn test_invalid_show_options() {
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    for opts in [
+        &["--tab", "9"][..],
+        &["--depth", "-1"],
+        &["--min-mirror-tid", "xyz"],
+        &["--type", "inode,volume"],
+        &["--key-range", "20:10"],
+        &["--inum", "x"],
+        &["--inum", "1,2"],
+    ] {
+        let mut args = opts.to_vec();
+        args.extend(["show", "/nonexistent"]);
+        let out = command(hammer2, &args, &[]);
+        assert!(!out.status.success(), "{args:?} succeeded");
+        assert!(out.stdout.is_empty(), "{args:?} printed output");
+    }
+}
+
+#[test]
+fn test_normalize_json() {
+    let s = "{\"ctime\":1700000000000000,\"uid\":\"01234567-89ab-cdef-0123-456789abcdef\"}\n";
+    assert_eq!(normalize_json(s), "{\"ctime\":<time>,\"uid\":\"<uuid>\"}\n");
+}
+
+#[test]
+fn test_normalize() {
+    let dir = TempDir::new("normalize");
+    let s = format!(
+        "{}/vol0\n    fsid           01234567-89ab-cdef-0123-456789abcdef\n\
+        ctime    Mon Jan  1 00:00:00 2024\n\
+        meth=xxhash64|none xxh=0123456789abcdef \n\
+        icrc_sects[7]  0x01234567/0x01234567 (OK)\n",
+        dir.0.to_str().unwrap()
+    );
+    assert_eq!(
+        normalize(&s, &dir),
+        "<dir>/vol0\n    fsid           <uuid>\nctime    <time>\n\
+        meth=xxhash64|none xxh=<check> \nicrc_sects[7]  <crc> (OK)\n"
+    );
+}
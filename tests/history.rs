@@ -0,0 +1,62 @@
+// Tests for history markers of snapshots and volume headers.
+
+mod common;
+
+use common::fixture::{Image, ROOT_INUM};
+use common::{newfs_sizes, run, TempDir};
+
+// Snapshots of other PFSes and broken volume headers aren't markers.
+#[test]
+fn test_history_markers() {
+    let dir = TempDir::new("history-markers");
+    let devpath = newfs_sizes(&dir, &[3 << 30], None);
+    let mut image = Image::open(&devpath);
+    let mut pfs = image.pfs("DATA");
+    pfs.create(ROOT_INUM, "file", b"file");
+    pfs.commit(&mut image);
+    image.snapshot(&pfs, "DATA.snap");
+    let mut pfs = image.pfs("LOCAL");
+    pfs.create(ROOT_INUM, "file", b"file");
+    pfs.commit(&mut image);
+    image.snapshot(&pfs, "LOCAL.snap");
+    image.finish();
+
+    let offset = libhammer2::volume::get_volume_data_offset(1);
+    let fp = std::fs::File::options().write(true).open(&devpath).unwrap();
+    std::os::unix::fs::FileExt::write_all_at(&fp, &[0; 8], offset).unwrap();
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let s = run(hammer2, &["history", &format!("{devpath}@DATA")], &[]);
+    assert!(s.contains("/file"), "{s}");
+    assert!(s.contains(" snapshot DATA.snap"), "{s}");
+    assert!(!s.contains(" snapshot LOCAL.snap"), "{s}");
+    assert!(s.contains(" volume header 0"), "{s}");
+    assert!(!s.contains(" volume header 1"), "{s}");
+    let s = run(hammer2, &["history", &format!("{devpath}@LOCAL")], &[]);
+    assert!(s.contains(" snapshot LOCAL.snap"), "{s}");
+    assert!(!s.contains(" snapshot DATA.snap"), "{s}");
+}
+
+// Versions of a file in a subdirectory are found by path, and --last 0
+// prints no versions.
+#[test]
+fn test_history_path() {
+    let dir = TempDir::new("history-path");
+    let devpath = newfs_sizes(&dir, &[1 << 30], None);
+    let mut image = Image::open(&devpath);
+    let mut pfs = image.pfs("DATA");
+    let inum = pfs.mkdir(ROOT_INUM, "dir");
+    let inum = pfs.mkdir(inum, "sub");
+    pfs.create(inum, "file", &vec![0xa5; 4096]);
+    pfs.commit(&mut image);
+    image.snapshot(&pfs, "DATA.snap");
+    image.finish();
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let root = format!("{devpath}@DATA");
+    let s = run(hammer2, &["history", &root], &[]);
+    assert!(s.contains(" snapshot DATA.snap"), "{s}");
+    assert!(s.contains("/dir/sub/file"), "{s}");
+    let s = run(hammer2, &["--last", "0", "history", &root], &[]);
+    assert!(!s.contains("modify_tid"), "{s}");
+}
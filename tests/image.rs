@@ -0,0 +1,98 @@
+// Tests for image and image-restore against images with files and
+// directories.
+
+mod common;
+
+use common::fixture::{Image, ROOT_INUM};
+use common::{command, newfs, run, TempDir};
+
+// Create an image with a directory, a file with embedded data and a file
+// with data blocks, and return inode numbers of the directory and the
+// file in the directory.
+fn populate(devpath: &str) -> (u64, u64) {
+    let mut image = Image::open(devpath);
+    let mut pfs = image.pfs("DATA");
+    let dir = pfs.mkdir(ROOT_INUM, "dir");
+    let file = pfs.create(dir, "file", b"hello");
+    pfs.create(ROOT_INUM, "large", &vec![0x5a; 100_000]);
+    pfs.commit(&mut image);
+    image.write_freemap();
+    image.finish();
+    (dir, file)
+}
+
+// Get the first filename in show output.
+fn get_filename(s: &str) -> &str {
+    let (_, s) = s.split_once("filename \"").unwrap();
+    s.split_once('"').unwrap().0
+}
+
+#[test]
+fn test_image_restore() {
+    let dir = TempDir::new("image-restore");
+    let devpath = newfs(&dir, 1, None);
+    populate(&devpath);
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let fsck = env!("CARGO_BIN_EXE_fsck_hammer2");
+    run(fsck, &[&devpath], &[]);
+    let output = dir.path("image");
+    let restored = dir.path("restored");
+    run(hammer2, &["image", &devpath, &output], &[]);
+    run(hammer2, &["image-restore", &output, &restored], &[]);
+    let s = run(
+        hammer2,
+        &["show", &format!("{devpath}@DATA:/dir/file")],
+        &[],
+    );
+    assert_eq!(
+        s,
+        run(
+            hammer2,
+            &["show", &format!("{restored}@DATA:/dir/file")],
+            &[]
+        )
+    );
+}
+
+// Names are scrambled along with directory hashes, so the restored image
+// passes fsck_hammer2 and path lookups work with scrambled names.
+#[test]
+fn test_image_scramble() {
+    let dir = TempDir::new("image-scramble");
+    let devpath = newfs(&dir, 1, None);
+    let (dinum, finum) = populate(&devpath);
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let output = dir.path("image");
+    let restored = dir.path("restored");
+    let s = run(hammer2, &["--scramble", "image", &devpath, &output], &[]);
+    assert!(s.contains(", scrambled"), "{s}");
+    let s = run(hammer2, &["image-restore", &output, &restored], &[]);
+    assert!(s.contains("(scrambled)"), "{s}");
+
+    run(env!("CARGO_BIN_EXE_fsck_hammer2"), &[&restored], &[]);
+    let s = run(hammer2, &["show", &devpath], &[]);
+    let names = ["\"dir\"", "\"file\"", "\"large\""];
+    assert!(names.iter().all(|x| s.contains(x)), "{s}");
+    let s = run(hammer2, &["show", &restored], &[]);
+    assert!(!names.iter().any(|x| s.contains(x)), "{s}");
+    for path in ["/dir", "/dir/file", "/large"] {
+        let out = command(hammer2, &["show", &format!("{restored}@DATA:{path}")], &[]);
+        assert!(!out.status.success(), "{path} found");
+    }
+
+    let root = format!("{restored}@DATA");
+    let s = run(hammer2, &["--inum", &dinum.to_string(), "show", &root], &[]);
+    let dname = get_filename(&s).to_string();
+    assert_eq!(dname.len(), "dir".len(), "{s}");
+    let s = run(hammer2, &["--inum", &finum.to_string(), "show", &root], &[]);
+    let fname = get_filename(&s).to_string();
+    assert_eq!(fname.len(), "file".len(), "{s}");
+    let s = run(
+        hammer2,
+        &["show", &format!("{restored}@DATA:/{dname}/{fname}")],
+        &[],
+    );
+    assert_eq!(get_filename(&s), fname, "{s}");
+}
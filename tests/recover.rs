@@ -0,0 +1,432 @@
+// Tests for recover directives against populated images.
+
+mod common;
+
+use common::fixture::{Image, Pfs, ROOT_INUM};
+use common::{newfs, run, TempDir};
+
+const FILE_A: &[u8] = &[b'a'; 100_000];
+const FILE_B: &[u8] = &[b'b'; 100_000];
+
+// Create a directory with five files named <prefix>0..4, whose content
+// is the filename.  Return inode numbers of the directory and the files.
+fn create_files(pfs: &mut Pfs, name: &str, prefix: &str) -> (u64, Vec<u64>) {
+    let dir = pfs.mkdir(ROOT_INUM, name);
+    let inums = (0..5)
+        .map(|i| {
+            let name = format!("{prefix}{i}");
+            pfs.create(dir, &name, name.as_bytes())
+        })
+        .collect();
+    (dir, inums)
+}
+
+// Destroy the last written inode, so that it fails to validate.
+fn destroy(image: &Image, pfs: &Pfs, inum: u64) {
+    let bref = pfs.get_blockref(inum);
+    image.write_at(
+        &[0; 1024],
+        bref.data_off & !libhammer2::fs::HAMMER2_OFF_MASK_RADIX,
+    );
+}
+
+// Populate DATA with three versions of /dir/file, the second of which has
+// different content, and /lost with files f0..4.  LOCAL has /lost with
+// files l0..4.  Both /lost are written once and their inodes destroyed,
+// so that files under them are only reachable by directory entries in
+// indirect blocks.  Both PFSes allocate inode numbers from the same
+// value, so files under /lost have the same inode numbers in both.
+// Return inode numbers of files under /lost.
+fn populate(devpath: &str) -> Vec<u64> {
+    let mut image = Image::open(devpath);
+    let mut pfs = image.pfs("DATA");
+    let (lost, inums) = create_files(&mut pfs, "lost", "f");
+    let dir = pfs.mkdir(ROOT_INUM, "dir");
+    let file = pfs.create(dir, "file", FILE_A);
+    pfs.commit(&mut image);
+    for data in [FILE_B, FILE_A] {
+        pfs.write(file, data);
+        pfs.commit(&mut image);
+    }
+    destroy(&image, &pfs, lost);
+
+    let mut pfs = image.pfs("LOCAL");
+    let (lost, local) = create_files(&mut pfs, "lost", "l");
+    assert_eq!(local, inums);
+    pfs.commit(&mut image);
+    destroy(&image, &pfs, lost);
+    image.finish();
+    inums
+}
+
+// Get relative paths under a directory, sorted.
+fn get_tree(dir: &std::path::Path, prefix: &str, v: &mut Vec<String>) {
+    for e in std::fs::read_dir(dir).unwrap() {
+        let e = e.unwrap();
+        let name = format!("{prefix}/{}", e.file_name().to_str().unwrap());
+        if e.file_type().unwrap().is_dir() {
+            get_tree(&e.path(), &name, v);
+        }
+        v.push(name);
+    }
+    v.sort();
+}
+
+// Media pass with threads must recover the same as a single thread,
+// including ranges across volumes.
+#[test]
+fn test_recover_jobs() {
+    let dir = TempDir::new("recover-jobs");
+    let devpath = newfs(&dir, 2, None);
+    populate(&devpath);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let mut trees = vec![];
+    for jobs in ["1", "3"] {
+        let dest = dir.path(&format!("dest{jobs}"));
+        std::fs::create_dir(&dest).unwrap();
+        run(
+            hammer2,
+            &["-q", "-j", jobs, "recover", &devpath, "/", &dest],
+            &[],
+        );
+        let mut v = vec![];
+        get_tree(std::path::Path::new(&dest), "", &mut v);
+        trees.push(v);
+    }
+    assert_eq!(trees[0], trees[1]);
+    assert_eq!(
+        trees[0],
+        [
+            "/dir",
+            "/dir/file.00001",
+            "/dir/file.00002",
+            "/dir/file.00003"
+        ]
+    );
+
+    // Versions in the order found on media.
+    let dest = std::path::Path::new(&dir.path("dest1")).join("dir");
+    for (i, data) in [FILE_A, FILE_B, FILE_A].iter().enumerate() {
+        let f = dest.join(format!("file.{:05}", i + 1));
+        assert_eq!(std::fs::read(f).unwrap(), *data);
+    }
+}
+
+#[test]
+fn test_recover_invalid_jobs() {
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let out = common::command(
+        hammer2,
+        &["-j", "0", "recover", "/nonexistent", "/", "/nonexistent"],
+        &[],
+    );
+    assert!(!out.status.success());
+}
+
+// Nothing is lost on a fresh image, so lost+found must not be created.
+#[test]
+fn test_recover_scan_dirents() {
+    let dir = TempDir::new("recover-scan-dirents");
+    let devpath = newfs(&dir, 1, None);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let dest = dir.path("dest");
+    std::fs::create_dir(&dest).unwrap();
+    let s = run(
+        hammer2,
+        &["-q", "--scan-dirents", "recover", &devpath, "/", &dest],
+        &[],
+    );
+    assert!(s.contains("LOST+FOUND PASS"), "{s}");
+    assert!(!std::path::Path::new(&dest).join("lost+found").exists());
+}
+
+// Files under the destroyed directories are only recovered from their
+// directory entries.  Files of the other PFS with the same inode number
+// aren't dumped as versions of them.
+#[test]
+fn test_recover_lost_found() {
+    let dir = TempDir::new("recover-lost-found");
+    let devpath = newfs(&dir, 1, None);
+    populate(&devpath);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let dest = dir.path("dest");
+    std::fs::create_dir(&dest).unwrap();
+    let s = run(
+        hammer2,
+        &["-q", "--scan-dirents", "recover", &devpath, "/", &dest],
+        &[],
+    );
+    assert!(s.contains("Lost+found=10"), "{s}");
+
+    let lost = std::path::Path::new(&dest).join("lost+found");
+    let mut v = vec![];
+    get_tree(&lost, "", &mut v);
+    assert_eq!(v.len(), 10, "{v:?}");
+    for i in 0..5 {
+        let f = v
+            .iter()
+            .find(|x| x.ends_with(&format!("-f{i}.00001")))
+            .unwrap();
+        let l = v
+            .iter()
+            .find(|x| x.ends_with(&format!("-l{i}.00001")))
+            .unwrap();
+        assert_eq!(f.split('-').next(), l.split('-').next(), "{v:?}");
+        let buf = std::fs::read(lost.join(&f[1..])).unwrap();
+        assert_eq!(buf, format!("f{i}").as_bytes());
+        let buf = std::fs::read(lost.join(&l[1..])).unwrap();
+        assert_eq!(buf, format!("l{i}").as_bytes());
+    }
+}
+
+// The third version of /dir/file is identical to the first one, so only
+// two versions remain and the duplicate is recorded in the manifest.
+#[test]
+fn test_recover_dedup() {
+    let dir = TempDir::new("recover-dedup");
+    let devpath = newfs(&dir, 1, None);
+    populate(&devpath);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let dest = dir.path("dest");
+    std::fs::create_dir(&dest).unwrap();
+    run(
+        hammer2,
+        &["-q", "--dedup", "recover", &devpath, "/", &dest],
+        &[],
+    );
+    let mut v = vec![];
+    get_tree(std::path::Path::new(&dest), "", &mut v);
+    assert_eq!(
+        v,
+        [
+            "/dir",
+            "/dir/file.00001",
+            "/dir/file.00002",
+            "/dir/file.dedup"
+        ]
+    );
+    let dest = std::path::Path::new(&dest).join("dir");
+    assert_eq!(std::fs::read(dest.join("file.00001")).unwrap(), FILE_A);
+    assert_eq!(std::fs::read(dest.join("file.00002")).unwrap(), FILE_B);
+    let s = std::fs::read_to_string(dest.join("file.dedup")).unwrap();
+    assert_eq!(s.lines().count(), 1, "{s}");
+    assert!(s.starts_with("file.00001 inum=0x"), "{s}");
+}
+
+// Recovery from a saved index must be the same as from the media pass,
+// and the index must not be loaded against another device, nor with
+// --scan-dirents unless it was saved with it.
+#[test]
+fn test_recover_index() {
+    let dir = TempDir::new("recover-index");
+    let devpath = newfs(&dir, 1, None);
+    populate(&devpath);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let index = dir.path("index");
+    let mut trees = vec![];
+    for (name, opts) in [
+        ("dest1", ["--save-index", index.as_str()]),
+        ("dest2", ["--index", index.as_str()]),
+    ] {
+        let dest = dir.path(name);
+        std::fs::create_dir(&dest).unwrap();
+        let mut args = vec!["-q", "--scan-dirents"];
+        args.extend(opts);
+        args.extend(["recover", devpath.as_str(), "/", dest.as_str()]);
+        run(hammer2, &args, &[]);
+        let mut v = vec![];
+        get_tree(std::path::Path::new(&dest), "", &mut v);
+        trees.push(v);
+    }
+    assert_eq!(trees[0], trees[1]);
+    assert!(trees[0].contains(&"/dir/file.00003".to_string()));
+    assert_eq!(
+        trees[0]
+            .iter()
+            .filter(|x| x.starts_with("/lost+found/"))
+            .count(),
+        10
+    );
+    let s = std::fs::read_to_string(&index).unwrap();
+    assert!(
+        s.starts_with("hammer2-recover-index 3\nscan_dirents 1\n"),
+        "{s}"
+    );
+
+    // An index of another version is rejected.
+    let dest = dir.path("dest3");
+    std::fs::create_dir(&dest).unwrap();
+    std::fs::write(&index, s.replacen(" 3\n", " 2\n", 1)).unwrap();
+    let out = common::command(
+        hammer2,
+        &["-q", "--index", &index, "recover", &devpath, "/", &dest],
+        &[],
+    );
+    assert!(!out.status.success());
+
+    // Dirents saved are ignored without --scan-dirents, and an index saved
+    // without them is rejected with --scan-dirents.
+    std::fs::write(&index, &s).unwrap();
+    run(
+        hammer2,
+        &["-q", "--index", &index, "recover", &devpath, "/", &dest],
+        &[],
+    );
+    let mut v = vec![];
+    get_tree(std::path::Path::new(&dest), "", &mut v);
+    assert!(!v.iter().any(|x| x.starts_with("/lost+found/")), "{v:?}");
+    let dest = dir.path("dest4");
+    std::fs::create_dir(&dest).unwrap();
+    run(
+        hammer2,
+        &[
+            "-q",
+            "--save-index",
+            &index,
+            "recover",
+            &devpath,
+            "/",
+            &dest,
+        ],
+        &[],
+    );
+    assert!(std::fs::read_to_string(&index)
+        .unwrap()
+        .contains("\nscan_dirents 0\n"));
+    let out = common::command(
+        hammer2,
+        &[
+            "-q",
+            "--scan-dirents",
+            "--index",
+            &index,
+            "recover",
+            &devpath,
+            "/",
+            &dest,
+        ],
+        &[],
+    );
+    assert!(!out.status.success());
+    std::fs::write(&index, s).unwrap();
+
+    let other = TempDir::new("recover-index-other");
+    let devpath = newfs(&other, 1, None);
+    let out = common::command(
+        hammer2,
+        &["-q", "--index", &index, "recover", &devpath, "/", &dest],
+        &[],
+    );
+    assert!(!out.status.success());
+}
+
+// Nothing is written with --list, and only JSON objects are printed with
+// --list --json.  All versions are listed in the order they would be
+// recovered.
+#[test]
+fn test_recover_list() {
+    let dir = TempDir::new("recover-list");
+    let devpath = newfs(&dir, 1, None);
+    populate(&devpath);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let dest = dir.path("dest");
+    std::fs::create_dir(&dest).unwrap();
+    let s = run(hammer2, &["--list", "recover", &devpath, "/", &dest], &[]);
+    let l: Vec<_> = s.lines().skip_while(|x| *x != "LIST").collect();
+    assert_eq!(l[1], format!("{dest}/dir/file"), "{s}");
+    for (i, x) in l[2..5].iter().enumerate() {
+        let prefix = format!("    .{:05} ok inum=", i + 1);
+        assert!(x.starts_with(&prefix), "{s}");
+        assert!(x.contains(" size=100000 "), "{s}");
+    }
+    assert!(!l[5].starts_with("    ."), "{s}");
+
+    let s = run(
+        hammer2,
+        &["--list", "--json", "recover", &devpath, "/", &dest],
+        &[],
+    );
+    let l: Vec<_> = s.lines().collect();
+    assert_eq!(l.len(), 3, "{s}");
+    for (i, x) in l.iter().enumerate() {
+        assert!(
+            x.starts_with(&format!(
+                "{{\"path\":\"{dest}/dir/file\",\"version\":{},",
+                i + 1
+            )),
+            "{s}"
+        );
+        assert!(x.ends_with(",\"valid\":true}"), "{s}");
+    }
+    assert_eq!(std::fs::read_dir(&dest).unwrap().count(), 0);
+}
+
+#[test]
+fn test_recover_by_inum() {
+    let dir = TempDir::new("recover-by-inum");
+    let devpath = newfs(&dir, 1, None);
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let dest = dir.path("dest");
+    std::fs::create_dir(&dest).unwrap();
+    let s = run(
+        hammer2,
+        &[
+            "-q",
+            "--inum",
+            "99,0x100",
+            "--modify-tid-range",
+            "1-",
+            "--mtime-range",
+            "2000-01-01,",
+            "recover",
+            &devpath,
+            "/",
+            &dest,
+        ],
+        &[],
+    );
+    assert!(s.contains("By-inum=0"), "{s}");
+    assert!(!std::path::Path::new(&dest).join("by-inum").exists());
+
+    // Both PFSes have an unreachable file of each inode number, and files
+    // which aren't selected aren't recovered by path either.
+    let inums = populate(&devpath);
+    let s = run(
+        hammer2,
+        &[
+            "-q",
+            "--inum",
+            &format!("{},{:#x}", inums[1], inums[2]),
+            "recover",
+            &devpath,
+            "/",
+            &dest,
+        ],
+        &[],
+    );
+    assert!(s.contains("By-inum=4"), "{s}");
+    let mut v = vec![];
+    get_tree(std::path::Path::new(&dest), "", &mut v);
+    let mut contents = vec![];
+    for (i, inum) in inums[1..3].iter().enumerate() {
+        for version in 1..=2 {
+            let f = format!("/by-inum/{inum}.{version:05}");
+            assert!(v.contains(&f), "{v:?}");
+            contents.push(std::fs::read_to_string(format!("{dest}{f}")).unwrap());
+        }
+        assert!(contents.contains(&format!("f{}", i + 1)), "{contents:?}");
+        assert!(contents.contains(&format!("l{}", i + 1)), "{contents:?}");
+    }
+    assert_eq!(v.iter().filter(|x| x.starts_with("/by-inum/")).count(), 4);
+    assert!(!v.iter().any(|x| x.starts_with("/dir/file")), "{v:?}");
+    for opts in [
+        &["--modify-tid-range", "2-1"][..],
+        &["--mtime-range", "2000-01-01"],
+        &["--inum", "1,x"],
+    ] {
+        let mut args = opts.to_vec();
+        args.extend(["recover", devpath.as_str(), "/", dest.as_str()]);
+        let out = common::command(hammer2, &args, &[]);
+        assert!(!out.status.success(), "{args:?} succeeded");
+    }
+}
@@ -0,0 +1,102 @@
+// Tests for show path lookups and --json and --dot output against an image
+// with directories, files and a snapshot.
+
+mod common;
+
+use common::fixture::{Image, ROOT_INUM};
+use common::{newfs, run, TempDir};
+
+fn populate(devpath: &str) {
+    let mut image = Image::open(devpath);
+    let mut pfs = image.pfs("DATA");
+    let dir = pfs.mkdir(ROOT_INUM, "dir");
+    pfs.create(dir, "embedded", b"embedded data\n");
+    pfs.create(dir, "large", &vec![0x5a; 100_000]);
+    let sub = pfs.mkdir(dir, "sub");
+    pfs.create(sub, "file", &vec![0xa5; 4096]);
+    pfs.commit(&mut image);
+    image.snapshot(&pfs, "DATA.snap");
+    image.write_freemap();
+    image.finish();
+}
+
+#[test]
+fn test_show_path() {
+    let dir = TempDir::new("show-path");
+    let devpath = newfs(&dir, 1, None);
+    populate(&devpath);
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let root = format!("{devpath}@DATA");
+    let s = run(hammer2, &["show", &root], &[]);
+    assert_eq!(s, run(hammer2, &["show", &format!("{root}:/")], &[]));
+    assert_eq!(s, run(hammer2, &["--inum", "1", "show", &root], &[]));
+    let s = run(hammer2, &["show", &format!("{root}:/dir/sub/file")], &[]);
+    assert!(s.contains("filename \"file\""), "{s}");
+    assert!(!s.contains("filename \"sub\""), "{s}");
+}
+
+#[test]
+fn test_show_json() {
+    let dir = TempDir::new("show-json");
+    let devpath = newfs(&dir, 1, None);
+    populate(&devpath);
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let s = run(hammer2, &["--json", "show", &devpath], &[]);
+    assert!(
+        s.lines().all(|x| x.starts_with('{') && x.ends_with('}')),
+        "{s}"
+    );
+    assert!(s.contains("\"type\":\"inode\""), "{s}");
+    assert!(s.contains("\"filename\":\"large\""), "{s}");
+    assert!(s.contains("\"name\":\"embedded\""), "{s}");
+    assert!(s.contains("\"check\":\"ok\""), "{s}");
+    assert!(!s.contains("\"check\":\"failed\""), "{s}");
+
+    let s = run(
+        hammer2,
+        &["--json", "--type", "dirent", "show", &devpath],
+        &[],
+    );
+    assert!(s.contains("\"name\":\"file\""), "{s}");
+    assert!(s.lines().all(|x| x.contains("\"type\":\"dirent\"")), "{s}");
+}
+
+// Blocks shared by the snapshot are printed once with an edge from each
+// parent, and edges only refer to printed nodes.
+#[test]
+fn test_show_dot() {
+    let dir = TempDir::new("show-dot");
+    let devpath = newfs(&dir, 1, None);
+    populate(&devpath);
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let s = run(hammer2, &["--dot", "show", &devpath], &[]);
+    let lines: Vec<_> = s.lines().collect();
+    assert_eq!(lines.first(), Some(&"digraph hammer2 {"), "{s}");
+    assert_eq!(lines.last(), Some(&"}"), "{s}");
+
+    let mut nodes = std::collections::HashSet::new();
+    let mut edges = vec![];
+    for x in &lines {
+        if let Some((name, _)) = x.trim_start().split_once(" [label=") {
+            assert!(nodes.insert(name), "{name} printed twice\n{s}");
+        } else if let Some((from, to)) = x.trim_start().split_once(" -> ") {
+            edges.push((from, to.trim_end_matches(';')));
+        }
+    }
+    assert!(!edges.is_empty(), "{s}");
+    for (from, to) in &edges {
+        assert!(
+            nodes.contains(from) && nodes.contains(to),
+            "{from} -> {to}\n{s}"
+        );
+    }
+    // the directory inode is shared by DATA and DATA.snap
+    let shared = nodes
+        .iter()
+        .filter(|x| edges.iter().filter(|(_, to)| to == *x).count() > 1)
+        .count();
+    assert!(shared > 0, "{s}");
+}
@@ -0,0 +1,28 @@
+// Tests for volhdr --compare against volumes with several volume headers.
+
+mod common;
+
+use common::{newfs_sizes, run, TempDir, VOLUME_SIZE};
+
+#[test]
+fn test_volhdr_compare() {
+    let dir = TempDir::new("volhdr-compare");
+    let devpath = newfs_sizes(&dir, &[5 * VOLUME_SIZE, VOLUME_SIZE], None);
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let s = run(hammer2, &["--compare", "volhdr", &devpath], &[]);
+    assert!(s.contains("vol0/zone2"), "{s}");
+    assert!(s.contains("vol1/zone0 (best)"), "{s}");
+    assert!(!s.contains("vol1/zone1"), "{s}");
+    assert!(!s.contains("FAILED"), "{s}");
+    assert!(s.contains(" same mirror_tid"), "{s}");
+
+    // Rows differing among headers of the same volume are marked.
+    let offset = libhammer2::volume::get_volume_data_offset(1);
+    let path = devpath.split(':').next().unwrap();
+    let fp = std::fs::File::options().write(true).open(path).unwrap();
+    std::os::unix::fs::FileExt::write_all_at(&fp, &[0; 8], offset).unwrap();
+    let s = run(hammer2, &["--compare", "volhdr", &devpath], &[]);
+    assert!(s.lines().any(|x| x.starts_with("* ")), "{s}");
+    assert!(s.contains("    header 1 invalid: "), "{s}");
+}
@@ -0,0 +1,164 @@
+// Tests for directives which modify volume headers of unmounted
+// filesystems, followed by fsck_hammer2 and read-only directives.
+
+mod common;
+
+use common::{count_map_cells, newfs, newfs_sizes, run, TempDir, VOLUME_SIZE};
+
+// Get values of the field in all volume headers of volhdr output.
+fn get_volhdr_fields(s: &str, name: &str) -> Vec<String> {
+    s.lines()
+        .filter_map(|x| x.trim_start().strip_prefix(name))
+        .filter_map(|x| x.split_whitespace().next())
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn get_volhdr_field(s: &str, name: &str) -> String {
+    get_volhdr_fields(s, name).swap_remove(0)
+}
+
+// Grow the last partial zone and add a new zone.  Blocks of the partial
+// zone's freemap leaf beyond the old size must become free.
+#[test]
+fn test_growfs_offline() {
+    let dir = TempDir::new("growfs-offline");
+    let old_size = 3 << 29; // 1.5GB
+    let new_size = 3 << 30;
+    let devpath = newfs_sizes(&dir, &[old_size], None);
+    let mut image = common::fixture::Image::open(&devpath);
+    image.write_freemap();
+    image.finish();
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let fsck = env!("CARGO_BIN_EXE_fsck_hammer2");
+    let envs = [("COLUMNS", "80")];
+    run(fsck, &[&devpath], &[]);
+    let s = run(hammer2, &["--map", "freemap", &devpath], &envs);
+    let allocated = count_map_cells(&s, '#');
+    let reserved = count_map_cells(&s, 'R');
+    assert!(allocated > 0, "{s}");
+
+    std::fs::File::options()
+        .write(true)
+        .open(&devpath)
+        .unwrap()
+        .set_len(new_size)
+        .unwrap();
+    let s = run(hammer2, &["--offline", "growfs", &devpath], &[]);
+    assert!(s.contains(" grown to "), "{s}");
+    assert!(s.contains("\nfree "), "{s}");
+
+    run(fsck, &[&devpath], &[]);
+    run(hammer2, &["freemap", &devpath], &[]);
+    let s = run(hammer2, &["--map", "freemap", &devpath], &envs);
+    assert_eq!(count_map_cells(&s, '#'), allocated, "{s}");
+    // the reserved segment of the new zone
+    assert_eq!(count_map_cells(&s, 'R'), reserved + 1, "{s}");
+    let s = run(hammer2, &["volhdr", &devpath], &[]);
+    assert_eq!(
+        get_volhdr_field(&s, "volu_size"),
+        format!("{new_size:#018x}"),
+        "{s}"
+    );
+
+    // Nothing to do once grown.
+    let s = run(hammer2, &["--offline", "growfs", &devpath], &[]);
+    assert!(s.contains(" no size change "), "{s}");
+}
+
+// Add a volume to a single volume filesystem.  Both volume headers must
+// describe the new layout, and the new one must be formatted the same
+// way as newfs_hammer2 formats volumes other than the root volume.
+#[test]
+fn test_volume_add() {
+    let dir = TempDir::new("volume-add");
+    let devpath = newfs(&dir, 1, None);
+    let newpath = dir.path("new");
+    std::fs::File::create(&newpath)
+        .unwrap()
+        .set_len(VOLUME_SIZE)
+        .unwrap();
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let fsck = env!("CARGO_BIN_EXE_fsck_hammer2");
+    let s = run(hammer2, &["volume-add", &devpath, &newpath], &[]);
+    assert!(s.contains(" added as volume1"), "{s}");
+
+    let devpath = format!("{devpath}:{newpath}");
+    run(fsck, &[&devpath], &[]);
+    let s = run(hammer2, &["volhdr", &devpath], &[]);
+    assert_eq!(get_volhdr_fields(&s, "volu_id"), ["0", "1"], "{s}");
+    assert_eq!(get_volhdr_fields(&s, "nvolumes"), ["2", "2"], "{s}");
+    assert_eq!(get_volhdr_fields(&s, "peer_type"), ["3", "3"], "{s}");
+    let total_size = format!("{:#018x}", 2 * VOLUME_SIZE);
+    assert_eq!(
+        get_volhdr_fields(&s, "total_size"),
+        [total_size.as_str(); 2],
+        "{s}"
+    );
+    let fsid = get_volhdr_fields(&s, "fsid");
+    assert_eq!(fsid.len(), 2, "{s}");
+    assert_eq!(fsid[0], fsid[1], "{s}");
+    let s = run(hammer2, &["--compare", "volhdr", &devpath], &[]);
+    assert!(!s.contains("FAILED"), "{s}");
+
+    // The new volume can't be added twice.
+    let out = common::command(hammer2, &["volume-add", &devpath, &newpath], &[]);
+    assert!(!out.status.success());
+}
+
+// Add a volume after a last volume which isn't aligned to the level1
+// freemap size.  The re-aligned tail must become free in the freemap
+// leaf of the last partial zone.
+#[test]
+fn test_volume_add_unaligned() {
+    let dir = TempDir::new("volume-add-unaligned");
+    let old_size = 3 << 29; // 1.5GB
+    let devpath = newfs_sizes(&dir, &[old_size], None);
+    let mut image = common::fixture::Image::open(&devpath);
+    image.write_freemap();
+    image.finish();
+
+    let hammer2 = env!("CARGO_BIN_EXE_hammer2");
+    let fsck = env!("CARGO_BIN_EXE_fsck_hammer2");
+    let envs = [("COLUMNS", "80")];
+    let s = run(hammer2, &["--map", "freemap", &devpath], &envs);
+    let allocated = count_map_cells(&s, '#');
+    let reserved = count_map_cells(&s, 'R');
+    assert!(allocated > 0, "{s}");
+
+    std::fs::File::options()
+        .write(true)
+        .open(&devpath)
+        .unwrap()
+        .set_len(2 * VOLUME_SIZE)
+        .unwrap();
+    let newpath = dir.path("new");
+    std::fs::File::create(&newpath)
+        .unwrap()
+        .set_len(VOLUME_SIZE)
+        .unwrap();
+    let s = run(hammer2, &["volume-add", &devpath, &newpath], &[]);
+    assert!(s.contains(" added as volume1"), "{s}");
+    assert!(s.contains("\nfree "), "{s}");
+
+    let devpath = format!("{devpath}:{newpath}");
+    run(fsck, &[&devpath], &[]);
+    run(hammer2, &["freemap", &devpath], &[]);
+    let s = run(hammer2, &["--map", "freemap", &devpath], &envs);
+    assert_eq!(count_map_cells(&s, '#'), allocated, "{s}");
+    // the reserved segment of the new volume's zone
+    assert_eq!(count_map_cells(&s, 'R'), reserved + 1, "{s}");
+    let s = run(hammer2, &["volhdr", &devpath], &[]);
+    assert_eq!(
+        get_volhdr_fields(&s, "volu_size"),
+        [
+            format!("{:#018x}", 2 * VOLUME_SIZE),
+            format!("{VOLUME_SIZE:#018x}")
+        ],
+        "{s}"
+    );
+    let s = run(hammer2, &["--compare", "volhdr", &devpath], &[]);
+    assert!(!s.contains("FAILED"), "{s}");
+}